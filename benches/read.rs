@@ -0,0 +1,45 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::time::Instant;
+
+use csv_io::{read_parallel_from_with, read_with, CsvDialect};
+
+// 逐次読み込みと並列読み込みの処理速度を比較する
+// cargo bench --bench read
+// 行数は環境変数CSV_IO_BENCH_ROWSで指定する(既定は100万行、約70MB)
+fn main() {
+    let rows: usize = std::env::var("CSV_IO_BENCH_ROWS").ok().and_then(|rows| rows.parse().ok()).unwrap_or(1_000_000);
+    let path = std::env::temp_dir().join(format!("csv-io-bench-{}.csv", std::process::id()));
+    let path = path.to_str().unwrap();
+
+    let mut text = String::from("id,名前,金額,備考\r\n");
+    for index in 0..rows {
+        text.push_str(&format!("{},いるかねこ{},{},\"船長うさぎ, \"\"{}\"\"\r\n改行\"\r\n", index, index, index * 10, index));
+    }
+    fs::write(path, &text).unwrap();
+    let megabytes = text.len() as f64 / 1024.0 / 1024.0;
+    let cpus = std::thread::available_parallelism().map(|count| count.get()).unwrap_or(1);
+    println!("{:.1}MB, CPU数={}", megabytes, cpus);
+
+    let dialect = CsvDialect::default();
+    let start = Instant::now();
+    let csv = read_with(path, &dialect).unwrap();
+    report("read_with", csv.get_body().len(), megabytes, start);
+
+    // スレッドごとに複数のチャンクを処理するよう、チャンクのサイズも変えて比較する
+    for chunk_size in [1024 * 1024, 4 * 1024 * 1024] {
+        for threads in [1, 2, 4, 0] {
+            let start = Instant::now();
+            let reader = BufReader::new(File::open(path).unwrap());
+            let csv = read_parallel_from_with(reader, &dialect, threads, chunk_size).unwrap();
+            report(&format!("threads={}, chunk={}MB", threads, chunk_size / 1024 / 1024), csv.get_body().len(), megabytes, start);
+        }
+    }
+
+    fs::remove_file(path).unwrap();
+}
+
+fn report(name: &str, rows: usize, megabytes: f64, start: Instant) {
+    let seconds = start.elapsed().as_secs_f64();
+    println!("{:<26} {:>8}行 {:>8.3}秒 {:>8.1}MB/s", name, rows, seconds, megabytes / seconds);
+}
@@ -0,0 +1,225 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+
+use crate::compression::{self, Compression};
+use crate::dialect::{format_record, split_record, RecordReader};
+use crate::lock::{self, LockOptions};
+use crate::{CsvDialect, CsvFile};
+
+// 追加書き込みの設定
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AppendOptions {
+    pub dialect: CsvDialect,
+    // ファイルのヘッダー
+    // 指定した場合はこの列の順序で書き込み、指定しない場合はCsvFileのヘッダーと比較する
+    pub header: Option<Vec<String>>,
+    // 指定した場合は書き込みが終わるまでファイルを排他ロックする
+    pub lock: Option<LockOptions>,
+}
+
+pub fn append(path: &str, csv_file: &CsvFile) -> Result<(), String> {
+    append_with(path, csv_file, &AppendOptions::default())
+}
+
+// 既存のファイルの末尾に行を追加する
+// ファイルがない場合や空の場合はヘッダーも書き込む
+// ファイルのヘッダーが一致しない場合はエラー
+pub fn append_with(path: &str, csv_file: &CsvFile, options: &AppendOptions) -> Result<(), String> {
+    let dialect = &options.dialect;
+    dialect.validate()?;
+    if Compression::from_path(path) != Compression::None {
+        return Err(String::from("圧縮されたファイルには追加できません。"));
+    }
+    let header = match &options.header {
+        Some(header) => header.clone(),
+        None => csv_file.csv_header.name.clone(),
+    };
+    // 指定したヘッダーの順序で値を取り出す
+    let mut rows = Vec::with_capacity(csv_file.csv_body.rows.len());
+    for row in &csv_file.csv_body.rows {
+        match &options.header {
+            Some(header) => {
+                let mut values = Vec::with_capacity(header.len());
+                for name in header {
+                    values.push(row.get_value(name)?);
+                }
+                rows.push(values);
+            }
+            None => rows.push(row.values()),
+        }
+    }
+
+    let mut file = match OpenOptions::new().read(true).append(true).create(true).open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("openに失敗しました。{}", e)),
+    };
+    if let Some(lock_options) = &options.lock {
+        lock::lock_exclusive(&file, path, lock_options)?;
+    }
+
+    let mut data = String::new();
+    let length = match file.metadata() {
+        Ok(metadata) => metadata.len(),
+        Err(e) => return Err(format!("openに失敗しました。{}", e)),
+    };
+    let existing = if length == 0 { None } else { file_header(&mut file, dialect)? };
+    match existing {
+        Some(file_header) => {
+            if file_header != header {
+                return Err(format!("ヘッダーが一致しません。ファイル=[{}], 追加=[{}]", file_header.join(","), header.join(",")));
+            }
+            // 最後の行に改行がない場合は改行してから追加する
+            if !ends_with_newline(&mut file, length)? {
+                data.push('\n');
+            }
+        }
+        None => {
+            data.push_str(&format_record(header.iter().map(|name| name.as_str()), dialect));
+            data.push('\n');
+        }
+    }
+    for values in &rows {
+        data.push_str(&format_record(values.iter().map(|value| value.as_str()), dialect));
+        data.push('\n');
+    }
+
+    if let Err(e) = file.write_all(data.as_bytes()) {
+        return Err(format!("writeに失敗しました。[{}]", e));
+    }
+    if let Err(e) = file.flush() {
+        return Err(format!("flushに失敗しました。[{}]", e));
+    }
+
+    Ok(())
+}
+
+// ファイルの最初のレコード(空行だけのファイルの場合はNone)
+fn file_header(file: &mut File, dialect: &CsvDialect) -> Result<Option<Vec<String>>, String> {
+    if let Err(e) = file.seek(SeekFrom::Start(0)) {
+        return Err(format!("seekに失敗しました。[{}]", e));
+    }
+    let mut reader = BufReader::new(file);
+    if compression::detect(&mut reader)? != Compression::None {
+        return Err(String::from("圧縮されたファイルには追加できません。"));
+    }
+
+    match RecordReader::new(reader, dialect).next_record() {
+        Some(record) => {
+            let record = record?;
+            let fields = split_record(&record.text, dialect)?;
+            Ok(Some(fields.into_iter().map(|field| field.into_owned()).collect()))
+        }
+        None => Ok(None),
+    }
+}
+
+fn ends_with_newline(file: &mut File, length: u64) -> Result<bool, String> {
+    let mut last = [0; 1];
+    if let Err(e) = file.seek(SeekFrom::Start(length - 1)).and_then(|_| file.read_exact(&mut last)) {
+        return Err(format!("readに失敗しました。[{}]", e));
+    }
+    Ok(last[0] == b'\n')
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::read;
+
+    #[test]
+    fn append_new_file() {
+        let path = temp_path("new");
+
+        append(&path, &make_file(&["いるかねこ", "100"])).unwrap();
+        append(&path, &make_file(&["船長うさぎ", "200"])).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!("名前,金額\nいるかねこ,100\n船長うさぎ,200\n", text);
+    }
+
+    #[test]
+    fn append_without_newline() {
+        let path = temp_path("newline");
+        std::fs::write(&path, "\u{FEFF}名前,金額\r\nいるかねこ,100").unwrap();
+
+        append_with(&path, &make_file(&["船長うさぎ", "200"]), &AppendOptions {lock: Some(LockOptions::new()), ..Default::default()}).unwrap();
+        let csv = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(2, csv.get_body().len());
+        assert_eq!(vec!["船長うさぎ", "200"], csv.get_body().get_row(1).unwrap().values());
+    }
+
+    #[test]
+    fn append_with_header() {
+        let path = temp_path("header");
+        std::fs::write(&path, "金額,名前\n100,いるかねこ\n").unwrap();
+
+        let options = AppendOptions {header: Some(vec![String::from("金額"), String::from("名前")]), ..Default::default()};
+        append_with(&path, &make_file(&["船長うさぎ", "200"]), &options).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!("金額,名前\n100,いるかねこ\n200,船長うさぎ\n", text);
+    }
+
+    #[test]
+    fn append_header_mismatch() {
+        let path = temp_path("mismatch");
+        std::fs::write(&path, "名前,単価\nいるかねこ,100\n").unwrap();
+
+        let result = append(&path, &make_file(&["船長うさぎ", "200"]));
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        match result {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("ヘッダーが一致しません。ファイル=[名前,単価], 追加=[名前,金額]", e),
+        }
+        // ファイルは変更しない
+        assert_eq!("名前,単価\nいるかねこ,100\n", text);
+    }
+
+    #[test]
+    fn append_lock_timeout() {
+        let path = temp_path("lock");
+        std::fs::write(&path, "名前,金額\n").unwrap();
+        let holder = File::open(&path).unwrap();
+        holder.lock_shared().unwrap();
+
+        let options = AppendOptions {lock: Some(LockOptions::with_timeout(Duration::from_millis(20))), ..Default::default()};
+        let result = append_with(&path, &make_file(&["船長うさぎ", "200"]), &options);
+        drop(holder);
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err_and(|e| e.starts_with("ロックを取得できませんでした。")));
+        assert_eq!("名前,金額\n", text);
+    }
+
+    fn make_file(values: &[&str]) -> CsvFile {
+        CsvFile::from_rows(
+            vec![String::from("名前"), String::from("金額")],
+            vec![values.iter().map(|value| value.to_string()).collect()],
+        )
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("csv-io-append-{}-{}.csv", name, std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+}
@@ -0,0 +1,290 @@
+use futures_util::Stream;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
+
+use crate::dialect::{format_record, RawRecord, RecordBuilder};
+use crate::{parse_header, parse_row, CsvDialect, CsvHeader, CsvRow, ParseError};
+
+// AsyncReadから1行ずつ読み込む
+// レコードの組み立てと項目の分割は同期の読み込みと共通
+pub struct AsyncCsvReader<R: AsyncRead + Unpin> {
+    reader: BufReader<R>,
+    dialect: CsvDialect,
+    builder: RecordBuilder,
+    csv_header: CsvHeader,
+    // 改行まで読み込んでいない行のバイト列
+    line: Vec<u8>,
+    // 最後まで読み込んだか、読み込みに失敗した
+    done: bool,
+}
+impl<R: AsyncRead + Unpin> AsyncCsvReader<R> {
+    // 最初のレコードをヘッダーとして読み込む
+    pub async fn new(reader: R, dialect: &CsvDialect) -> Result<Self, String> {
+        dialect.validate()?;
+        let mut csv_reader = Self {
+            reader: BufReader::new(reader),
+            dialect: dialect.clone(),
+            builder: RecordBuilder::new(dialect),
+            csv_header: CsvHeader::new(),
+            line: Vec::new(),
+            done: false,
+        };
+        if let Some(record) = csv_reader.next_record().await? {
+            csv_reader.csv_header = parse_header(&record, dialect)?;
+        }
+        Ok(csv_reader)
+    }
+
+    pub fn get_header(&self) -> &CsvHeader {
+        &self.csv_header
+    }
+
+    // 読み込みに失敗した場合はエラーを1度だけ返し、以降はNoneを返す
+    // キャンセルしても読み込み途中の行は失われないため、select!やtimeoutの中で呼び出せる
+    pub async fn next_row(&mut self) -> Option<Result<CsvRow, String>> {
+        if self.done {
+            return None;
+        }
+        match self.next_record().await {
+            Ok(Some(record)) => Some(parse_row(&self.csv_header, &record, &self.dialect).map_err(String::from)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e.into()))
+            }
+        }
+    }
+
+    // 残りの行を順に返すStream
+    pub fn into_stream(self) -> impl Stream<Item = Result<CsvRow, String>> {
+        futures_util::stream::unfold(self, |mut reader| async move {
+            reader.next_row().await.map(|row| (row, reader))
+        })
+    }
+
+    async fn next_record(&mut self) -> Result<Option<RawRecord>, ParseError> {
+        loop {
+            // read_untilはキャンセルされても読み込んだバイト列をlineに残す
+            // 改行まで読み込んでから文字列にし、組み立て中のレコードに追加する
+            if let Err(e) = self.reader.read_until(b'\n', &mut self.line).await {
+                return Err(self.builder.error(&format!("readに失敗しました。[{}]", e)));
+            }
+            if self.line.is_empty() {
+                if self.builder.text.is_empty() {
+                    return Ok(None);
+                }
+            } else {
+                let start = self.builder.text.len();
+                match std::str::from_utf8(&self.line) {
+                    Ok(line) => self.builder.text.push_str(line),
+                    Err(_) => return Err(self.builder.error("readに失敗しました。[stream did not contain valid UTF-8]")),
+                }
+                self.line.clear();
+                if !self.builder.line_added(start) {
+                    continue;
+                }
+            }
+            if let Some(record) = self.builder.take() {
+                return Ok(Some(record));
+            }
+        }
+    }
+}
+
+// AsyncWriteに1行ずつ書き込む
+// 最後にfinishを呼び出して書き込みを完了する
+pub struct AsyncCsvWriter<W: AsyncWrite + Unpin> {
+    writer: BufWriter<W>,
+    dialect: CsvDialect,
+}
+impl<W: AsyncWrite + Unpin> AsyncCsvWriter<W> {
+    pub fn new(writer: W, dialect: &CsvDialect) -> Result<Self, String> {
+        dialect.validate()?;
+        Ok(Self {writer: BufWriter::new(writer), dialect: dialect.clone()})
+    }
+
+    pub async fn write_header(&mut self, csv_header: &CsvHeader) -> Result<(), String> {
+        self.write_record(csv_header.name.iter().map(|name| name.as_str())).await
+    }
+
+    pub async fn write_row(&mut self, csv_row: &CsvRow) -> Result<(), String> {
+        self.write_record(csv_row.data.iter().map(|csv_data| csv_data.value.as_str())).await
+    }
+
+    // バッファの内容を書き込み、元のWriterを返す
+    pub async fn finish(mut self) -> Result<W, String> {
+        if let Err(e) = self.writer.flush().await {
+            return Err(format!("flushに失敗しました。[{}]", e));
+        }
+        Ok(self.writer.into_inner())
+    }
+
+    async fn write_record<'a, I>(&mut self, values: I) -> Result<(), String>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut data = format_record(values, &self.dialect);
+        data.push('\n');
+        match self.writer.write_all(data.as_bytes()).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("writeに失敗しました。[{}]", e)),
+        }
+    }
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::poll_fn;
+    use std::pin::pin;
+
+    use crate::{read, read_from};
+
+    #[tokio::test]
+    async fn async_read_same_as_sequential() {
+        let text = "\u{FEFF}id,名前,備考\r\n1,いるかねこ,\"改行\r\n\"\"あり\"\"\"\r\n\r\n2,船長うさぎ,\r\n";
+
+        let mut reader = AsyncCsvReader::new(text.as_bytes(), &CsvDialect::default()).await.unwrap();
+        let expected = read_from(text.as_bytes(), &CsvDialect::default()).unwrap();
+
+        assert_eq!(expected.get_header().name, reader.get_header().name);
+        assert_eq!(expected.get_body().get_row(0).unwrap().values(), reader.next_row().await.unwrap().unwrap().values());
+        assert_eq!(expected.get_body().get_row(1).unwrap().values(), reader.next_row().await.unwrap().unwrap().values());
+        assert!(reader.next_row().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn async_stream_to_writer() {
+        let csv = read("test/test.csv").unwrap();
+        let file = tokio::fs::File::open("test/test.csv").await.unwrap();
+        let reader = AsyncCsvReader::new(file, &CsvDialect::default()).await.unwrap();
+        let mut writer = AsyncCsvWriter::new(Vec::new(), &CsvDialect::tsv()).unwrap();
+        writer.write_header(reader.get_header()).await.unwrap();
+
+        let mut stream = pin!(reader.into_stream());
+        while let Some(row) = poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+            writer.write_row(&row.unwrap()).await.unwrap();
+        }
+        let bytes = writer.finish().await.unwrap();
+
+        let written = read_from(bytes.as_slice(), &CsvDialect::tsv()).unwrap();
+        assert_eq!(csv.get_header().name, written.get_header().name);
+        assert_eq!(csv.get_body().len(), written.get_body().len());
+        assert_eq!(csv.get_body().get_row(2).unwrap().values(), written.get_body().get_row(2).unwrap().values());
+    }
+
+    #[tokio::test]
+    async fn async_read_error() {
+        let text = "a,b\n1,2,3\n";
+
+        let mut reader = AsyncCsvReader::new(text.as_bytes(), &CsvDialect::default()).await.unwrap();
+
+        match reader.next_row().await {
+            Some(Err(e)) => assert_eq!("範囲外のインデックスが指定されました。[2] 行=[2], 列=[5], レコード=[1], バイト位置=[8], 項目=[2]\n1,2,3\n    ^", e),
+            _ => panic!("エラーが発生しませんでした。"),
+        }
+    }
+
+    #[tokio::test]
+    async fn async_read_io_error() {
+        use tokio::io::AsyncReadExt;
+
+        let reader = "a,b\n1,2\n".as_bytes().chain(FailingReader);
+        let mut reader = AsyncCsvReader::new(reader, &CsvDialect::default()).await.unwrap();
+
+        assert_eq!(vec!["1", "2"], reader.next_row().await.unwrap().unwrap().values());
+        match reader.next_row().await {
+            Some(Err(e)) => assert!(e.starts_with("readに失敗しました。[ディスクが壊れています。]"), "{}", e),
+            _ => panic!("エラーが発生しませんでした。"),
+        }
+        // 失敗し続けるReaderでもエラーは1度だけ返す
+        assert!(reader.next_row().await.is_none());
+        assert!(reader.next_row().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn async_stream_io_error() {
+        use tokio::io::AsyncReadExt;
+
+        let reader = "a,b\n1,2\n".as_bytes().chain(FailingReader);
+        let reader = AsyncCsvReader::new(reader, &CsvDialect::default()).await.unwrap();
+
+        let mut stream = pin!(reader.into_stream());
+        let mut results = Vec::new();
+        while let Some(row) = poll_fn(|cx| stream.as_mut().poll_next(cx)).await {
+            results.push(row.is_ok());
+        }
+        assert_eq!(vec![true, false], results);
+    }
+
+    // 常に読み込みに失敗する
+    struct FailingReader;
+    impl AsyncRead for FailingReader {
+        fn poll_read(self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>, _: &mut tokio::io::ReadBuf<'_>)
+            -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Err(std::io::Error::other("ディスクが壊れています。")))
+        }
+    }
+
+    #[tokio::test]
+    async fn async_read_cancel() {
+        let expected = vec![vec!["1", "改\n行\nあり"], vec!["3", "4"]];
+        for polls in 1..12 {
+            // 複数バイトの文字の途中でも区切る
+            let text = "a,b\n1,\"改\n行\nあり\"\n3,4\n";
+            let reader = PendingReader {pieces: text.as_bytes().chunks(4).map(|piece| piece.to_vec()).collect(), pending: true};
+            let mut reader = AsyncCsvReader::new(reader, &CsvDialect::default()).await.unwrap();
+
+            // polls回だけ進めてからキャンセルする
+            let mut rows = Vec::new();
+            {
+                let mut future = pin!(reader.next_row());
+                let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+                for _ in 0..polls {
+                    if let std::task::Poll::Ready(row) = future.as_mut().poll(&mut context) {
+                        rows.push(row.unwrap().unwrap().values());
+                        break;
+                    }
+                }
+            }
+            while let Some(row) = reader.next_row().await {
+                rows.push(row.unwrap().values());
+            }
+            assert_eq!(expected, rows, "{}", polls);
+        }
+    }
+
+    // 1つずつバイト列を返し、その前に1度Pendingを返す
+    struct PendingReader {
+        pieces: std::collections::VecDeque<Vec<u8>>,
+        pending: bool,
+    }
+    impl AsyncRead for PendingReader {
+        fn poll_read(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>, buf: &mut tokio::io::ReadBuf<'_>)
+            -> std::task::Poll<std::io::Result<()>> {
+            if self.pending {
+                self.pending = false;
+                cx.waker().wake_by_ref();
+                return std::task::Poll::Pending;
+            }
+            self.pending = true;
+            if let Some(piece) = self.pieces.pop_front() {
+                buf.put_slice(&piece);
+            }
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+}
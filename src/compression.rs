@@ -0,0 +1,249 @@
+use std::io::{BufRead, Read, Write};
+
+// 圧縮形式
+// Gzip、Zstd、Bzip2の読み書きにはそれぞれgzip、zstd、bzip2フィーチャーが必要
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+impl Compression {
+    // 拡張子(.gz, .zst, .bz2)から判定する
+    pub fn from_path(path: &str) -> Compression {
+        let path = path.to_lowercase();
+        if path.ends_with(".gz") || path.ends_with(".gzip") {
+            Compression::Gzip
+        } else if path.ends_with(".zst") || path.ends_with(".zstd") {
+            Compression::Zstd
+        } else if path.ends_with(".bz2") {
+            Compression::Bzip2
+        } else {
+            Compression::None
+        }
+    }
+
+    // 先頭のバイト列(マジックナンバー)から判定する
+    pub fn from_magic(bytes: &[u8]) -> Compression {
+        if bytes.starts_with(&[0x1F, 0x8B]) {
+            Compression::Gzip
+        } else if bytes.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+            Compression::Zstd
+        } else if is_bzip2(bytes) {
+            Compression::Bzip2
+        } else {
+            Compression::None
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Bzip2 => "bzip2",
+        }
+    }
+}
+
+// BZh、ブロックサイズ(1から9)、最初のブロックか空のストリームの終わりを表すマジックナンバー
+// BZhで始まるヘッダー名の無圧縮のファイルを誤って判定しないよう、すべてを比較する
+fn is_bzip2(bytes: &[u8]) -> bool {
+    const BLOCK: &[u8] = b"1AY&SY";
+    const END: &[u8] = &[0x17, 0x72, 0x45, 0x38, 0x50, 0x90];
+    match bytes {
+        [b'B', b'Z', b'h', b'1'..=b'9', rest @ ..] => rest.starts_with(BLOCK) || rest.starts_with(END),
+        _ => false,
+    }
+}
+
+// 必要なフィーチャーが有効か
+pub(crate) fn check(compression: Compression) -> Result<(), String> {
+    let supported = match compression {
+        Compression::None => true,
+        Compression::Gzip => cfg!(feature = "gzip"),
+        Compression::Zstd => cfg!(feature = "zstd"),
+        Compression::Bzip2 => cfg!(feature = "bzip2"),
+    };
+    if supported {
+        Ok(())
+    } else {
+        Err(unsupported(compression))
+    }
+}
+
+// 先頭のバイト列から圧縮形式を判定する(読み込み位置は進めない)
+pub(crate) fn detect<R: BufRead>(reader: &mut R) -> Result<Compression, String> {
+    match reader.fill_buf() {
+        Ok(bytes) => Ok(Compression::from_magic(bytes)),
+        Err(e) => Err(format!("readに失敗しました。[{}]", e)),
+    }
+}
+
+// 展開しながら読み込むReaderを作成する
+pub(crate) fn decoder<'a, R: BufRead + 'a>(reader: R, compression: Compression) -> Result<Box<dyn Read + 'a>, String> {
+    match compression {
+        Compression::None => Ok(Box::new(reader)),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(reader))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => match zstd::stream::read::Decoder::with_buffer(reader) {
+            Ok(decoder) => Ok(Box::new(decoder)),
+            Err(e) => Err(format!("展開に失敗しました。[{}]", e)),
+        },
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Ok(Box::new(bzip2::bufread::MultiBzDecoder::new(reader))),
+        #[allow(unreachable_patterns)]
+        compression => Err(unsupported(compression)),
+    }
+}
+
+// 圧縮しながら書き込み、最後に圧縮を完了する
+pub(crate) fn encode<W, F>(writer: W, compression: Compression, write: F) -> Result<(), String>
+where
+    W: Write,
+    F: FnOnce(&mut dyn Write) -> Result<(), String>,
+{
+    match compression {
+        Compression::None => {
+            let mut writer = writer;
+            write(&mut writer)
+        }
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+            write(&mut encoder)?;
+            finish(encoder.finish().map(|_| ()))
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            let mut encoder = match zstd::stream::write::Encoder::new(writer, 0) {
+                Ok(encoder) => encoder,
+                Err(e) => return Err(format!("圧縮に失敗しました。[{}]", e)),
+            };
+            write(&mut encoder)?;
+            finish(encoder.finish().map(|_| ()))
+        }
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => {
+            let mut encoder = bzip2::write::BzEncoder::new(writer, bzip2::Compression::default());
+            write(&mut encoder)?;
+            finish(encoder.finish().map(|_| ()))
+        }
+        #[allow(unreachable_patterns)]
+        compression => Err(unsupported(compression)),
+    }
+}
+
+#[allow(dead_code)]
+fn finish(result: std::io::Result<()>) -> Result<(), String> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) => Err(format!("圧縮に失敗しました。[{}]", e)),
+    }
+}
+
+fn unsupported(compression: Compression) -> String {
+    format!("圧縮形式に対応していません。{}フィーチャーを有効にしてください。[{}]", compression.name(), compression.name())
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read_with_compression, CsvDialect};
+
+    #[test]
+    fn compression_from_path() {
+        assert_eq!(Compression::Gzip, Compression::from_path("sales.csv.GZ"));
+        assert_eq!(Compression::Zstd, Compression::from_path("sales.csv.zst"));
+        assert_eq!(Compression::Bzip2, Compression::from_path("sales.tsv.bz2"));
+        assert_eq!(Compression::None, Compression::from_path("sales.csv"));
+    }
+
+    #[test]
+    fn compression_from_magic() {
+        assert_eq!(Compression::Gzip, Compression::from_magic(&[0x1F, 0x8B, 0x08]));
+        assert_eq!(Compression::Zstd, Compression::from_magic(&[0x28, 0xB5, 0x2F, 0xFD, 0x00]));
+        assert_eq!(Compression::Bzip2, Compression::from_magic(b"BZh91AY&SY\x00"));
+        assert_eq!(Compression::Bzip2, Compression::from_magic(&[b'B', b'Z', b'h', b'1', 0x17, 0x72, 0x45, 0x38, 0x50, 0x90]));
+        // BZhで始まる無圧縮のファイル
+        assert_eq!(Compression::None, Compression::from_magic(b"BZh,b\n1,2\n"));
+        assert_eq!(Compression::None, Compression::from_magic(b"BZh91AY,b\n"));
+        assert_eq!(Compression::None, Compression::from_magic("ヘッダー1".as_bytes()));
+        assert_eq!(Compression::None, Compression::from_magic(&[]));
+    }
+
+    #[test]
+    fn read_bzh_header() {
+        let path = std::env::temp_dir().join(format!("csv-io-compression-bzh-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "BZh,b\n1,2\n").unwrap();
+
+        let csv = crate::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(vec!["BZh", "b"], csv.get_header().name);
+        assert_eq!(1, csv.get_body().len());
+    }
+
+    #[test]
+    fn read_with_compression_none() {
+        // 無圧縮を指定した場合は判定しない
+        let csv = read_with_compression("test/test.csv", &CsvDialect::default(), Compression::None).unwrap();
+
+        assert_eq!(3, csv.get_body().len());
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn write_unsupported() {
+        use crate::{read, write_with};
+
+        let csv = read("test/test.csv").unwrap();
+        let path = std::env::temp_dir().join(format!("csv-io-unsupported-{}.csv.gz", std::process::id()));
+
+        match write_with(path.to_str().unwrap(), &csv, &CsvDialect::default()) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("圧縮形式に対応していません。gzipフィーチャーを有効にしてください。[gzip]", e),
+        }
+        // ファイルは作成しない
+        assert!(!path.exists());
+    }
+
+    #[cfg(all(feature = "gzip", feature = "zstd", feature = "bzip2"))]
+    #[test]
+    fn compressed_round_trip() {
+        use crate::{read, write_with};
+
+        let csv = read("test/test.csv").unwrap();
+        for (extension, magic) in [("gz", Compression::Gzip), ("zst", Compression::Zstd), ("bz2", Compression::Bzip2)] {
+            let path = std::env::temp_dir().join(format!("csv-io-compressed-{}.csv.{}", std::process::id(), extension));
+            let path = path.to_str().unwrap();
+
+            write_with(path, &csv, &CsvDialect::default()).unwrap();
+            let bytes = std::fs::read(path).unwrap();
+            // 拡張子がなくても先頭のバイト列から判定して読み込む
+            let plain_path = format!("{}.csv", path);
+            std::fs::write(&plain_path, &bytes).unwrap();
+            let read_csv = read(&plain_path).unwrap();
+            std::fs::remove_file(path).unwrap();
+            std::fs::remove_file(&plain_path).unwrap();
+
+            assert_eq!(magic, Compression::from_magic(&bytes));
+            assert_eq!(csv.get_header().name, read_csv.get_header().name);
+            assert_eq!(csv.get_body().get_row(2).unwrap().values(), read_csv.get_body().get_row(2).unwrap().values());
+        }
+    }
+}
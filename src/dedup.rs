@@ -0,0 +1,359 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{CsvFile, CsvRow};
+
+// 重複した行のうち、どの行を残すか
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Keep {
+    First,
+    Last,
+}
+
+// 主キーの列とキー→行番号の索引
+#[derive(Clone)]
+pub(crate) struct PrimaryKey {
+    header_names: Vec<String>,
+    index: HashMap<Vec<String>, usize>,
+}
+impl PrimaryKey {
+    fn make_key(&self, row: &CsvRow) -> Vec<String> {
+        self.header_names.iter()
+            .map(|header_name| row.get_value(header_name).unwrap_or_default())
+            .collect()
+    }
+}
+
+impl CsvFile {
+    // 行全体が同じ行を削除し、最初の行を残す
+    // 削除した行数を返す
+    pub fn dedup(&mut self) -> usize {
+        let mut seen: HashSet<Vec<String>> = HashSet::new();
+        let before = self.csv_body.rows.len();
+        self.csv_body.rows.retain(|row| {
+            seen.insert(row.data.iter().map(|csv_data| csv_data.value.clone()).collect())
+        });
+        self.rebuild_primary_key();
+
+        before - self.csv_body.rows.len()
+    }
+
+    // 指定した列の値が同じ行を削除する
+    // Keep::Lastの場合は最後の行がその位置に残る
+    pub fn dedup_by(&mut self, header_names: &[&str], keep: Keep) -> Result<usize, String> {
+        for header_name in header_names {
+            if !self.csv_header.name.iter().any(|name| name == header_name) {
+                return Err(format!("重複の削除に失敗しました。[存在しないヘッダー名です。[{}]]", header_name));
+            }
+        }
+
+        let mut keys = Vec::with_capacity(self.csv_body.rows.len());
+        for row in &self.csv_body.rows {
+            let mut key = Vec::with_capacity(header_names.len());
+            for header_name in header_names {
+                key.push(row.get_value(header_name)?);
+            }
+            keys.push(key);
+        }
+
+        // 残す行を決める
+        let mut kept = vec![false; keys.len()];
+        let mut seen: HashSet<&Vec<String>> = HashSet::new();
+        match keep {
+            Keep::First => {
+                for (index, key) in keys.iter().enumerate() {
+                    kept[index] = seen.insert(key);
+                }
+            }
+            Keep::Last => {
+                for (index, key) in keys.iter().enumerate().rev() {
+                    kept[index] = seen.insert(key);
+                }
+            }
+        }
+
+        let before = self.csv_body.rows.len();
+        let mut kept = kept.into_iter();
+        self.csv_body.rows.retain(|_| kept.next().unwrap_or(false));
+        self.rebuild_primary_key();
+
+        Ok(before - self.csv_body.rows.len())
+    }
+
+    // 主キーを設定する
+    // 以降、append/insert/updateでキーが重複する行はエラーになる
+    pub fn set_primary_key(&mut self, header_names: &[&str]) -> Result<(), String> {
+        if header_names.is_empty() {
+            return Err(String::from("主キーの設定に失敗しました。[キー列が指定されていません。]"));
+        }
+        for header_name in header_names {
+            if !self.csv_header.name.iter().any(|name| name == header_name) {
+                return Err(format!("主キーの設定に失敗しました。[存在しないヘッダー名です。[{}]]", header_name));
+            }
+        }
+
+        let mut primary_key = PrimaryKey {
+            header_names: header_names.iter().map(|name| name.to_string()).collect(),
+            index: HashMap::with_capacity(self.csv_body.rows.len()),
+        };
+        for (index, row) in self.csv_body.rows.iter().enumerate() {
+            let key = primary_key.make_key(row);
+            if primary_key.index.contains_key(&key) {
+                return Err(format!("主キーの設定に失敗しました。[主キーが重複しています。[{}]]", key.join(", ")));
+            }
+            primary_key.index.insert(key, index);
+        }
+        self.primary_key = Some(primary_key);
+
+        Ok(())
+    }
+
+    pub fn clear_primary_key(&mut self) {
+        self.primary_key = None;
+    }
+
+    // 主キーの値から行を取得する
+    pub fn get_by_key(&self, key: &[&str]) -> Result<CsvRow, String> {
+        let primary_key = match &self.primary_key {
+            Some(primary_key) => primary_key,
+            None => return Err(String::from("主キーが設定されていません。")),
+        };
+        let key: Vec<String> = key.iter().map(|value| value.to_string()).collect();
+        match primary_key.index.get(&key) {
+            Some(index) => self.csv_body.get_row(*index),
+            None => Err(format!("存在しないキーです。[{}]", key.join(", "))),
+        }
+    }
+
+    // ignoreには更新対象の行番号を指定する(自分自身との重複は許可する)
+    pub(crate) fn check_primary_key(&self, row: &CsvRow, ignore: Option<usize>) -> Result<(), String> {
+        if let Some(primary_key) = &self.primary_key {
+            let key = primary_key.make_key(row);
+            if let Some(index) = primary_key.index.get(&key)
+                && Some(*index) != ignore {
+                return Err(format!("主キーが重複しています。[{}]", key.join(", ")));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn index_primary_key(&mut self, index: usize) {
+        if let Some(primary_key) = &mut self.primary_key {
+            let key = primary_key.make_key(&self.csv_body.rows[index]);
+            primary_key.index.insert(key, index);
+        }
+    }
+
+    pub(crate) fn update_primary_key(&mut self, old_row: &CsvRow, index: usize) {
+        if let Some(primary_key) = &mut self.primary_key {
+            let old_key = primary_key.make_key(old_row);
+            primary_key.index.remove(&old_key);
+            let key = primary_key.make_key(&self.csv_body.rows[index]);
+            primary_key.index.insert(key, index);
+        }
+    }
+
+    // index行目に行を挿入した後に呼び出す
+    // 後ろの行の番号をずらして、挿入した行を索引に追加する
+    pub(crate) fn insert_primary_key(&mut self, index: usize) {
+        if let Some(primary_key) = &mut self.primary_key {
+            if index + 1 < self.csv_body.rows.len() {
+                for row_index in primary_key.index.values_mut() {
+                    if *row_index >= index {
+                        *row_index += 1;
+                    }
+                }
+            }
+            let key = primary_key.make_key(&self.csv_body.rows[index]);
+            primary_key.index.insert(key, index);
+        }
+    }
+
+    // index行目の行(row)を削除した後に呼び出す
+    pub(crate) fn remove_primary_key(&mut self, row: &CsvRow, index: usize) {
+        if let Some(primary_key) = &mut self.primary_key {
+            let key = primary_key.make_key(row);
+            primary_key.index.remove(&key);
+            for row_index in primary_key.index.values_mut() {
+                if *row_index > index {
+                    *row_index -= 1;
+                }
+            }
+        }
+    }
+
+    // 行の位置が変わった場合に索引を作り直す
+    pub(crate) fn rebuild_primary_key(&mut self) {
+        if let Some(primary_key) = &mut self.primary_key {
+            primary_key.index.clear();
+            for (index, row) in self.csv_body.rows.iter().enumerate() {
+                let key = primary_key.make_key(row);
+                primary_key.index.insert(key, index);
+            }
+        }
+    }
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedup() {
+        let mut file = make_file();
+
+        assert_eq!(1, file.dedup());
+        assert_eq!(vec!["1", "2", "1", "3"], column(&file, "id"));
+    }
+
+    #[test]
+    fn dedup_by_keep_first() {
+        let mut file = make_file();
+
+        assert_eq!(2, file.dedup_by(&["id"], Keep::First).unwrap());
+        assert_eq!(vec!["いるかねこ", "船長うさぎ", "すもっく"], column(&file, "name"));
+    }
+
+    #[test]
+    fn dedup_by_keep_last() {
+        let mut file = make_file();
+
+        assert_eq!(2, file.dedup_by(&["id"], Keep::Last).unwrap());
+        assert_eq!(vec!["船長うさぎ", "やかまし", "すもっく"], column(&file, "name"));
+    }
+
+    #[test]
+    fn dedup_by_error() {
+        let mut file = make_file();
+
+        match file.dedup_by(&["ヘッダー4"], Keep::First) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("重複の削除に失敗しました。[存在しないヘッダー名です。[ヘッダー4]]", e),
+        }
+    }
+
+    #[test]
+    fn set_primary_key_error() {
+        let mut file = make_file();
+
+        match file.set_primary_key(&["id"]) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("主キーの設定に失敗しました。[主キーが重複しています。[1]]", e),
+        }
+    }
+
+    #[test]
+    fn get_by_key() {
+        let mut file = make_unique_file();
+        file.set_primary_key(&["id"]).unwrap();
+
+        assert_eq!("船長うさぎ", file.get_by_key(&["2"]).unwrap().get_value("name").unwrap());
+    }
+
+    #[test]
+    fn get_by_key_error() {
+        let mut file = make_unique_file();
+
+        match file.get_by_key(&["2"]) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("主キーが設定されていません。", e),
+        }
+
+        file.set_primary_key(&["id"]).unwrap();
+        match file.get_by_key(&["5"]) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("存在しないキーです。[5]", e),
+        }
+    }
+
+    #[test]
+    fn primary_key_append() {
+        let mut file = make_unique_file();
+        file.set_primary_key(&["id"]).unwrap();
+
+        file.append(vec![String::from("4"), String::from("おけぶろ")]).unwrap();
+        assert_eq!("おけぶろ", file.get_by_key(&["4"]).unwrap().get_value("name").unwrap());
+
+        match file.append(vec![String::from("2"), String::from("いぬねこ")]) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("行の追加に失敗しました。[主キーが重複しています。[2]]", e),
+        }
+        assert_eq!(4, file.get_body().len());
+    }
+
+    #[test]
+    fn primary_key_insert_remove() {
+        let mut file = make_unique_file();
+        file.set_primary_key(&["id"]).unwrap();
+
+        file.insert(0, vec![String::from("4"), String::from("おけぶろ")]).unwrap();
+        assert_eq!("すもっく", file.get_by_key(&["3"]).unwrap().get_value("name").unwrap());
+
+        file.remove(0).unwrap();
+        assert_eq!("すもっく", file.get_by_key(&["3"]).unwrap().get_value("name").unwrap());
+        assert!(file.get_by_key(&["4"]).is_err());
+
+        match file.insert(1, vec![String::from("1"), String::from("いぬねこ")]) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("行の追加に失敗しました。[主キーが重複しています。[1]]", e),
+        }
+    }
+
+    #[test]
+    fn primary_key_update() {
+        let mut file = make_unique_file();
+        file.set_primary_key(&["id"]).unwrap();
+
+        // 自分自身のキーのままの更新は許可する
+        file.update(1, vec![String::from("2"), String::from("いぬねこ")]).unwrap();
+        file.update(0, vec![String::from("5"), String::from("おけぶろ")]).unwrap();
+        assert_eq!("おけぶろ", file.get_by_key(&["5"]).unwrap().get_value("name").unwrap());
+        assert!(file.get_by_key(&["1"]).is_err());
+
+        match file.update(0, vec![String::from("3"), String::from("いぬねこ")]) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("行の追加に失敗しました。[主キーが重複しています。[3]]", e),
+        }
+    }
+
+    fn column(file: &CsvFile, header_name: &str) -> Vec<String> {
+        (0..file.get_body().len())
+            .map(|index| file.get_value(header_name, index).unwrap())
+            .collect()
+    }
+
+    fn make_file() -> CsvFile {
+        make(&[
+            ["1", "いるかねこ"],
+            ["2", "船長うさぎ"],
+            ["1", "いるかねこ"],
+            ["1", "やかまし"],
+            ["3", "すもっく"],
+        ])
+    }
+
+    fn make_unique_file() -> CsvFile {
+        make(&[
+            ["1", "いるかねこ"],
+            ["2", "船長うさぎ"],
+            ["3", "すもっく"],
+        ])
+    }
+
+    fn make(rows: &[[&str; 2]]) -> CsvFile {
+        CsvFile::from_rows(
+            vec![String::from("id"), String::from("name")],
+            rows.iter().map(|row| row.iter().map(|value| value.to_string()).collect()).collect(),
+        )
+    }
+}
@@ -0,0 +1,494 @@
+use std::borrow::Cow;
+use std::io::BufRead;
+
+use crate::error::{ParseError, RecordPosition};
+
+// 区切り文字と囲み文字の設定
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvDialect {
+    pub delimiter: char,
+    pub quote: char,
+}
+impl CsvDialect {
+    pub fn new(delimiter: char, quote: char) -> Self {
+        Self {delimiter, quote}
+    }
+
+    // タブ区切り
+    pub fn tsv() -> Self {
+        Self::new('\t', '"')
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.delimiter == self.quote {
+            return Err(format!("区切り文字と囲み文字が同じです。[{}]", self.delimiter));
+        }
+        for c in [self.delimiter, self.quote] {
+            if c == '\r' || c == '\n' {
+                return Err(String::from("区切り文字と囲み文字に改行は使用できません。"));
+            }
+        }
+        Ok(())
+    }
+}
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self::new(',', '"')
+    }
+}
+
+// 1レコード分の文字列
+pub(crate) struct RawRecord {
+    pub(crate) text: String,
+    pub(crate) position: RecordPosition,
+}
+impl RawRecord {
+    // このレコードで発生したエラー
+    pub(crate) fn error(&self, message: &str, field: Option<usize>, dialect: &CsvDialect) -> ParseError {
+        ParseError::new(message, &self.text, self.position, field, dialect)
+    }
+}
+
+// 囲み文字内の改行を考慮して、1レコードずつ読み込む
+pub(crate) struct RecordReader<R: BufRead> {
+    reader: R,
+    builder: RecordBuilder,
+}
+impl<R: BufRead> RecordReader<R> {
+    pub(crate) fn new(reader: R, dialect: &CsvDialect) -> Self {
+        Self {reader, builder: RecordBuilder::new(dialect)}
+    }
+
+    // ファイルの途中から読み込む場合の最初のレコードの位置
+    // レコード番号が0以外の場合はBOMを読み飛ばさない
+    pub(crate) fn starting_at(mut self, position: RecordPosition) -> Self {
+        self.builder.position = position;
+        self
+    }
+
+    // 次に読み込むレコードの位置
+    pub(crate) fn position(&self) -> RecordPosition {
+        self.builder.position
+    }
+
+    pub(crate) fn next_record(&mut self) -> Option<Result<RawRecord, ParseError>> {
+        loop {
+            let start = self.builder.text.len();
+            match self.reader.read_line(&mut self.builder.text) {
+                Ok(0) if self.builder.text.is_empty() => return None,
+                Ok(0) => {}
+                Ok(_) => if !self.builder.line_added(start) {
+                    continue;
+                },
+                Err(e) => return Some(Err(self.builder.error(&format!("readに失敗しました。[{}]", e)))),
+            }
+            if let Some(record) = self.builder.take() {
+                return Some(Ok(record));
+            }
+        }
+    }
+}
+
+// 読み込んだ行からレコードを組み立てる
+// 同期と非同期の読み込みで共通して使用する
+pub(crate) struct RecordBuilder {
+    dialect: CsvDialect,
+    // 次のレコードの開始位置
+    position: RecordPosition,
+    // 読み込み中のレコード
+    pub(crate) text: String,
+    scanner: QuoteScanner,
+}
+impl RecordBuilder {
+    pub(crate) fn new(dialect: &CsvDialect) -> Self {
+        Self {dialect: dialect.clone(), position: RecordPosition::default(), text: String::new(), scanner: QuoteScanner::new(dialect)}
+    }
+
+    // startの位置から1行追加したときに呼び出す
+    // 囲み文字の外で行が終わればレコードの終わり
+    pub(crate) fn line_added(&mut self, start: usize) -> bool {
+        // 先頭のBOMは項目に含めないため、囲み文字の判定からも除く
+        let start = match start == 0 && self.position.record == 0 && self.text.starts_with('\u{FEFF}') {
+            true => '\u{FEFF}'.len_utf8(),
+            false => start,
+        };
+        self.scanner.scan(&self.text.as_bytes()[start..])
+    }
+
+    // 組み立てたレコードを取り出す
+    // 空行の場合はNone
+    pub(crate) fn take(&mut self) -> Option<RawRecord> {
+        let mut text = std::mem::take(&mut self.text);
+        self.scanner.reset();
+        let mut position = self.position;
+        self.position.line += text.matches('\n').count();
+        self.position.offset += text.len();
+
+        trim_line_end(&mut text);
+        // 先頭のBOMは読み飛ばす
+        if position.record == 0 && text.starts_with('\u{FEFF}') {
+            text.remove(0);
+            position.offset += '\u{FEFF}'.len_utf8();
+        }
+        // 空行は読み飛ばす
+        if text.is_empty() {
+            return None;
+        }
+
+        self.position.record += 1;
+        Some(RawRecord {text, position})
+    }
+
+    // 読み込み中のレコードで発生したエラー
+    pub(crate) fn error(&self, message: &str) -> ParseError {
+        ParseError::new(message, &self.text, self.position, None, &self.dialect)
+    }
+}
+
+// 項目の状態
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FieldState {
+    // 項目の先頭
+    Start,
+    // 囲み文字で始まらない項目
+    Unquoted,
+    // 囲み文字の中
+    Quoted,
+    // 囲み文字の中で囲み文字が現れた直後(閉じた囲み文字かエスケープ)
+    QuoteInQuoted,
+}
+
+// レコードの終わりを判定するため、バイト列を読み進めて囲み文字の状態を追跡する
+// split_recordと同じく、項目の先頭の囲み文字だけを囲みの開始とする
+#[derive(Clone, Debug)]
+pub(crate) struct QuoteScanner {
+    quote: Vec<u8>,
+    delimiter: Vec<u8>,
+    state: FieldState,
+}
+impl QuoteScanner {
+    pub(crate) fn new(dialect: &CsvDialect) -> Self {
+        Self {
+            quote: dialect.quote.to_string().into_bytes(),
+            delimiter: dialect.delimiter.to_string().into_bytes(),
+            state: FieldState::Start,
+        }
+    }
+
+    // bytes[index..]の先頭の1文字を読み進め、次の位置を返す
+    pub(crate) fn advance(&mut self, bytes: &[u8], index: usize) -> usize {
+        let rest = &bytes[index..];
+        if rest.starts_with(&self.quote) {
+            self.state = match self.state {
+                FieldState::Start => FieldState::Quoted,
+                FieldState::Unquoted => FieldState::Unquoted,
+                FieldState::Quoted => FieldState::QuoteInQuoted,
+                FieldState::QuoteInQuoted => FieldState::Quoted,
+            };
+            return index + self.quote.len();
+        }
+        if rest.starts_with(&self.delimiter) {
+            if self.state != FieldState::Quoted {
+                self.state = FieldState::Start;
+            }
+            return index + self.delimiter.len();
+        }
+        self.state = match (self.state, rest[0]) {
+            (FieldState::Quoted, _) => FieldState::Quoted,
+            (_, b'\n') => FieldState::Start,
+            // 閉じた囲み文字の後の不正な文字は、改行までを1つの項目とする
+            _ => FieldState::Unquoted,
+        };
+        index + 1
+    }
+
+    // 囲み文字の中の改行はレコードの終わりではない
+    pub(crate) fn in_quote(&self) -> bool {
+        self.state == FieldState::Quoted
+    }
+
+    // 1行分のバイト列を読み進め、囲み文字の外で終わればtrue
+    pub(crate) fn scan(&mut self, bytes: &[u8]) -> bool {
+        let mut index = 0;
+        while index < bytes.len() {
+            // 囲み文字の中では囲み文字、囲まれていない項目では区切り文字と改行まで状態が変わらないため読み飛ばす
+            let skip = match self.state {
+                FieldState::Quoted => bytes[index..].iter().position(|byte| *byte == self.quote[0]),
+                FieldState::Unquoted => bytes[index..].iter().position(|byte| *byte == self.delimiter[0] || *byte == b'\n'),
+                _ => Some(0),
+            };
+            match skip {
+                Some(skip) => index = self.advance(bytes, index + skip),
+                None => break,
+            }
+        }
+        !self.in_quote()
+    }
+
+    // 次のレコードの先頭に戻す
+    pub(crate) fn reset(&mut self) {
+        self.state = FieldState::Start;
+    }
+}
+
+pub(crate) fn trim_line_end(text: &mut String) {
+    if text.ends_with('\n') {
+        text.pop();
+        if text.ends_with('\r') {
+            text.pop();
+        }
+    }
+}
+
+pub(crate) const UNCLOSED_QUOTE: &str = "囲み文字が閉じられていません。";
+
+// 1レコードを項目に分割する
+// 囲み文字のエスケープ("")がない項目は元の文字列を参照する
+pub(crate) fn split_record<'a>(text: &'a str, dialect: &CsvDialect) -> Result<Vec<Cow<'a, str>>, FieldError> {
+    let mut fields = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    loop {
+        match chars.peek() {
+            Some((start, c)) if *c == dialect.quote => {
+                let content_start = start + c.len_utf8();
+                chars.next();
+                let mut owned: Option<String> = None;
+                let mut content_end = None;
+                while let Some((index, c)) = chars.next() {
+                    if c != dialect.quote {
+                        if let Some(value) = &mut owned {
+                            value.push(c);
+                        }
+                        continue;
+                    }
+                    match chars.peek() {
+                        Some((_, next)) if *next == dialect.quote => {
+                            // エスケープされた囲み文字
+                            let value = owned.get_or_insert_with(|| text[content_start..index].to_string());
+                            value.push(c);
+                            chars.next();
+                        }
+                        _ => {
+                            content_end = Some(index);
+                            break;
+                        }
+                    }
+                }
+                let content_end = match content_end {
+                    Some(index) => index,
+                    None => return Err(FieldError::new(UNCLOSED_QUOTE, fields.len())),
+                };
+                match owned {
+                    Some(value) => fields.push(Cow::Owned(value)),
+                    None => fields.push(Cow::Borrowed(&text[content_start..content_end])),
+                }
+
+                // 閉じた囲み文字の後は区切り文字か行末
+                match chars.next() {
+                    Some((_, c)) if c == dialect.delimiter => {
+                        if chars.peek().is_none() {
+                            fields.push(Cow::Borrowed(""));
+                            break;
+                        }
+                    }
+                    Some(_) => return Err(FieldError::new("囲み文字の後に不正な文字があります。", fields.len() - 1)),
+                    None => break,
+                }
+            }
+            Some((start, _)) => {
+                let start = *start;
+                let mut end = text.len();
+                let mut has_delimiter = false;
+                for (index, c) in chars.by_ref() {
+                    if c == dialect.delimiter {
+                        end = index;
+                        has_delimiter = true;
+                        break;
+                    }
+                }
+                fields.push(Cow::Borrowed(&text[start..end]));
+                if has_delimiter && chars.peek().is_none() {
+                    fields.push(Cow::Borrowed(""));
+                }
+                if !has_delimiter {
+                    break;
+                }
+            }
+            None => {
+                // 空のレコードは空の項目が1つとする
+                if fields.is_empty() {
+                    fields.push(Cow::Borrowed(""));
+                }
+                break;
+            }
+        }
+    }
+
+    Ok(fields)
+}
+
+// 項目の分割で発生したエラー
+#[derive(Debug)]
+pub(crate) struct FieldError {
+    pub(crate) message: &'static str,
+    pub(crate) field: usize,
+}
+impl FieldError {
+    fn new(message: &'static str, field: usize) -> Self {
+        Self {message, field}
+    }
+
+    // 発生したレコードの位置を付ける
+    pub(crate) fn at(self, record: &RawRecord, dialect: &CsvDialect) -> ParseError {
+        record.error(self.message, Some(self.field), dialect)
+    }
+}
+impl From<FieldError> for String {
+    fn from(e: FieldError) -> Self {
+        format!("{}項目=[{}]", e.message, e.field)
+    }
+}
+
+// 項目を区切り文字でつなぎ、必要な項目は囲み文字で囲む
+pub(crate) fn format_record<'a, I>(values: I, dialect: &CsvDialect) -> String
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut text = String::new();
+    for (index, value) in values.into_iter().enumerate() {
+        if index > 0 {
+            text.push(dialect.delimiter);
+        }
+        push_field(&mut text, value, dialect);
+    }
+    text
+}
+
+fn push_field(text: &mut String, value: &str, dialect: &CsvDialect) {
+    let needs_quote = value.chars()
+        .any(|c| c == dialect.delimiter || c == dialect.quote || c == '\r' || c == '\n');
+    if !needs_quote {
+        text.push_str(value);
+        return;
+    }
+    text.push(dialect.quote);
+    for c in value.chars() {
+        if c == dialect.quote {
+            text.push(c);
+        }
+        text.push(c);
+    }
+    text.push(dialect.quote);
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn split_record_plain() {
+        let fields = split_record("いるかねこ,,やかまし,", &CsvDialect::default()).unwrap();
+
+        assert_eq!(vec!["いるかねこ", "", "やかまし", ""], fields);
+    }
+
+    #[test]
+    fn split_record_quoted() {
+        let fields = split_record(r#""a,b","say ""hi""","改
+行""#, &CsvDialect::default()).unwrap();
+
+        assert_eq!(vec!["a,b", r#"say "hi""#, "改\n行"], fields);
+        assert!(matches!(fields[0], Cow::Borrowed(_)));
+        assert!(matches!(fields[1], Cow::Owned(_)));
+    }
+
+    #[test]
+    fn split_record_tsv() {
+        let fields = split_record("a,b\t'c\td'", &CsvDialect::new('\t', '\'')).unwrap();
+
+        assert_eq!(vec!["a,b", "c\td"], fields);
+    }
+
+    #[test]
+    fn split_record_error() {
+        match split_record(r#"a,"b"c"#, &CsvDialect::default()) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("囲み文字の後に不正な文字があります。項目=[1]", String::from(e)),
+        }
+        match split_record(r#"a,"b"#, &CsvDialect::default()) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("囲み文字が閉じられていません。項目=[1]", String::from(e)),
+        }
+    }
+
+    #[test]
+    fn format_record_quote() {
+        let text = format_record(["a,b", r#"say "hi""#, "改\n行", "いるかねこ"], &CsvDialect::default());
+
+        assert_eq!("\"a,b\",\"say \"\"hi\"\"\",\"改\n行\",いるかねこ", text);
+        assert_eq!(vec!["a,b", r#"say "hi""#, "改\n行", "いるかねこ"],
+                   split_record(&text, &CsvDialect::default()).unwrap());
+    }
+
+    #[test]
+    fn record_reader() {
+        let text = "\u{FEFF}a,b\r\n\r\n\"1\r\n2\",3\n4,5";
+        let mut reader = RecordReader::new(Cursor::new(text), &CsvDialect::default());
+
+        // 空行とBOMも行番号とバイト位置に含める
+        let expect = [("a,b", 0, 1, 3), ("\"1\r\n2\",3", 1, 3, 10), ("4,5", 2, 5, 19)];
+        for (text, record, line, offset) in expect {
+            let raw = reader.next_record().unwrap().unwrap();
+            assert_eq!(text, raw.text);
+            assert_eq!(RecordPosition {record, line, offset}, raw.position);
+        }
+        assert!(reader.next_record().is_none());
+    }
+
+    #[test]
+    fn record_reader_stray_quote() {
+        // 項目の途中の囲み文字は囲みの開始にしない
+        let text = "名前,金額\n船長\"うさぎ,200\nやかまし,300\n\"閉じて\"いない,400\nおけぶろ,500\n";
+        let mut reader = RecordReader::new(Cursor::new(text), &CsvDialect::default());
+
+        let mut records = Vec::new();
+        while let Some(record) = reader.next_record() {
+            records.push(record.unwrap().text);
+        }
+        assert_eq!(vec!["名前,金額", "船長\"うさぎ,200", "やかまし,300", "\"閉じて\"いない,400", "おけぶろ,500"], records);
+        assert_eq!(vec!["船長\"うさぎ", "200"], split_record(&records[1], &CsvDialect::default()).unwrap());
+    }
+
+    #[test]
+    fn record_reader_bom_quoted() {
+        // BOMの直後の囲み文字は囲みの開始になる
+        let text = "\u{FEFF}\"名\n前\",金額\n船長うさぎ,200\n";
+        let mut reader = RecordReader::new(Cursor::new(text), &CsvDialect::default());
+
+        assert_eq!("\"名\n前\",金額", reader.next_record().unwrap().unwrap().text);
+        assert_eq!("船長うさぎ,200", reader.next_record().unwrap().unwrap().text);
+    }
+
+    #[test]
+    fn quote_scanner_multibyte() {
+        let mut scanner = QuoteScanner::new(&CsvDialect::new('、', '「'));
+
+        assert!(!scanner.scan("a、「改".as_bytes()));
+        assert!(!scanner.scan("\n行「「、".as_bytes()));
+        assert!(scanner.scan("」「\n".as_bytes()));
+        scanner.reset();
+        assert!(scanner.scan("い「る、か\n".as_bytes()));
+    }
+}
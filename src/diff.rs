@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::{CsvFile, CsvRow};
+
+// 2つのCsvFileの差分
+pub struct CsvDiff {
+    pub(crate) key_columns: Vec<String>,
+    pub(crate) added_columns: Vec<String>,
+    pub(crate) removed_columns: Vec<String>,
+    pub(crate) added: Vec<CsvRow>,
+    // 追加された行の新しいCsvFileでの行番号
+    pub(crate) added_indexes: Vec<usize>,
+    pub(crate) removed: Vec<CsvRow>,
+    // 削除された行の古いCsvFileでの行番号
+    pub(crate) removed_indexes: Vec<usize>,
+    pub(crate) changed: Vec<RowChange>,
+}
+
+// キーが同じで値が変わった行
+pub struct RowChange {
+    pub(crate) key: Vec<String>,
+    pub(crate) cells: Vec<CellChange>,
+}
+
+// 値が変わったセル
+#[derive(Clone, Debug, PartialEq)]
+pub struct CellChange {
+    pub(crate) header_name: String,
+    pub(crate) old_value: String,
+    pub(crate) new_value: String,
+}
+
+// キー列で行を対応付けて差分を求める
+// 値の比較は両方に存在する列のみを対象にする
+pub fn diff(old: &CsvFile, new: &CsvFile, key_columns: &[&str]) -> Result<CsvDiff, String> {
+    if key_columns.is_empty() {
+        return Err(String::from("差分の取得に失敗しました。[キー列が指定されていません。]"));
+    }
+    let old_names = &old.csv_header.name;
+    let new_names = &new.csv_header.name;
+    for key_column in key_columns {
+        if !old_names.iter().any(|name| name == key_column)
+            || !new_names.iter().any(|name| name == key_column) {
+            return Err(format!("差分の取得に失敗しました。[存在しないヘッダー名です。[{}]]", key_column));
+        }
+    }
+
+    let added_columns: Vec<String> = new_names.iter()
+        .filter(|name| !old_names.contains(name))
+        .cloned()
+        .collect();
+    let removed_columns: Vec<String> = old_names.iter()
+        .filter(|name| !new_names.contains(name))
+        .cloned()
+        .collect();
+    let common_columns: Vec<&String> = new_names.iter()
+        .filter(|name| old_names.contains(name))
+        .collect();
+
+    let old_index = make_index(old, key_columns)?;
+    let new_index = make_index(new, key_columns)?;
+
+    let mut removed = Vec::new();
+    let mut removed_indexes = Vec::new();
+    for (row_index, row) in old.csv_body.rows.iter().enumerate() {
+        if !new_index.contains_key(&make_key(row, key_columns)?) {
+            removed.push(row.clone());
+            removed_indexes.push(row_index);
+        }
+    }
+
+    let mut added = Vec::new();
+    let mut added_indexes = Vec::new();
+    let mut changed = Vec::new();
+    for (row_index, row) in new.csv_body.rows.iter().enumerate() {
+        let key = make_key(row, key_columns)?;
+        match old_index.get(&key) {
+            Some(index) => {
+                let old_row = &old.csv_body.rows[*index];
+                let mut cells = Vec::new();
+                for name in &common_columns {
+                    let old_value = old_row.get_value(name)?;
+                    let new_value = row.get_value(name)?;
+                    if old_value != new_value {
+                        cells.push(CellChange {header_name: name.to_string(), old_value, new_value});
+                    }
+                }
+                if !cells.is_empty() {
+                    changed.push(RowChange {key, cells});
+                }
+            }
+            None => {
+                added.push(row.clone());
+                added_indexes.push(row_index);
+            }
+        }
+    }
+
+    Ok(CsvDiff {
+        key_columns: key_columns.iter().map(|name| name.to_string()).collect(),
+        added_columns,
+        removed_columns,
+        added,
+        added_indexes,
+        removed,
+        removed_indexes,
+        changed,
+    })
+}
+
+fn make_key(row: &CsvRow, key_columns: &[&str]) -> Result<Vec<String>, String> {
+    let mut key = Vec::with_capacity(key_columns.len());
+    for key_column in key_columns {
+        key.push(row.get_value(key_column)?);
+    }
+    Ok(key)
+}
+
+fn make_index(csv_file: &CsvFile, key_columns: &[&str]) -> Result<HashMap<Vec<String>, usize>, String> {
+    let mut index = HashMap::with_capacity(csv_file.csv_body.rows.len());
+    for (row_index, row) in csv_file.csv_body.rows.iter().enumerate() {
+        let key = make_key(row, key_columns)?;
+        if index.contains_key(&key) {
+            return Err(format!("差分の取得に失敗しました。[キーが重複しています。[{}]]", key.join(", ")));
+        }
+        index.insert(key, row_index);
+    }
+    Ok(index)
+}
+
+impl CsvDiff {
+    pub fn key_columns(&self) -> &[String] {
+        &self.key_columns
+    }
+
+    pub fn added_columns(&self) -> &[String] {
+        &self.added_columns
+    }
+
+    pub fn removed_columns(&self) -> &[String] {
+        &self.removed_columns
+    }
+
+    pub fn added(&self) -> &[CsvRow] {
+        &self.added
+    }
+
+    pub fn removed(&self) -> &[CsvRow] {
+        &self.removed
+    }
+
+    pub fn changed(&self) -> &[RowChange] {
+        &self.changed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_columns.is_empty() && self.removed_columns.is_empty()
+            && self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    // 人が読むためのレポートを作成する
+    pub fn to_report(&self) -> String {
+        let mut report = String::new();
+        for name in &self.added_columns {
+            report.push_str(&format!("列の追加: [{}]\n", name));
+        }
+        for name in &self.removed_columns {
+            report.push_str(&format!("列の削除: [{}]\n", name));
+        }
+        for row in &self.removed {
+            report.push_str(&format!("行の削除: {}\n", self.format_row_key(row)));
+        }
+        for row in &self.added {
+            report.push_str(&format!("行の追加: {}\n", self.format_row_key(row)));
+        }
+        for row_change in &self.changed {
+            report.push_str(&format!("行の変更: {}\n", self.format_key(&row_change.key)));
+            for cell in &row_change.cells {
+                report.push_str(&format!("    {}: [{}] -> [{}]\n"
+                                         , cell.header_name, cell.old_value, cell.new_value));
+            }
+        }
+        report
+    }
+
+    // 変更内容を1セル1行のCsvFileにする
+    // ヘッダーはchange, キー列, column, old_value, new_value
+    pub fn to_csv(&self) -> CsvFile {
+        let mut header_names = vec![String::from("change")];
+        header_names.extend(self.key_columns.iter().cloned());
+        header_names.extend([
+            String::from("column"),
+            String::from("old_value"),
+            String::from("new_value"),
+        ]);
+
+        let mut rows = Vec::new();
+        for row in &self.removed {
+            for csv_data in &row.data {
+                let mut values = vec![String::from("removed")];
+                values.extend(self.row_key(row));
+                values.extend([csv_data.header_name.clone(), csv_data.value.clone(), String::new()]);
+                rows.push(values);
+            }
+        }
+        for row in &self.added {
+            for csv_data in &row.data {
+                let mut values = vec![String::from("added")];
+                values.extend(self.row_key(row));
+                values.extend([csv_data.header_name.clone(), String::new(), csv_data.value.clone()]);
+                rows.push(values);
+            }
+        }
+        for row_change in &self.changed {
+            for cell in &row_change.cells {
+                let mut values = vec![String::from("changed")];
+                values.extend(row_change.key.iter().cloned());
+                values.extend([cell.header_name.clone(), cell.old_value.clone(), cell.new_value.clone()]);
+                rows.push(values);
+            }
+        }
+
+        CsvFile::from_rows(header_names, rows)
+    }
+
+    fn row_key(&self, row: &CsvRow) -> Vec<String> {
+        self.key_columns.iter()
+            .map(|name| row.get_value(name).unwrap_or_default())
+            .collect()
+    }
+
+    fn format_row_key(&self, row: &CsvRow) -> String {
+        self.format_key(&self.row_key(row))
+    }
+
+    fn format_key(&self, key: &[String]) -> String {
+        self.key_columns.iter().zip(key)
+            .map(|(name, value)| format!("{}=[{}]", name, value))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+impl fmt::Display for CsvDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_report())
+    }
+}
+
+impl RowChange {
+    pub fn key(&self) -> &[String] {
+        &self.key
+    }
+
+    pub fn cells(&self) -> &[CellChange] {
+        &self.cells
+    }
+}
+
+impl CellChange {
+    pub fn new(header_name: &str, old_value: &str, new_value: &str) -> Self {
+        Self {
+            header_name: header_name.to_string(),
+            old_value: old_value.to_string(),
+            new_value: new_value.to_string(),
+        }
+    }
+
+    pub fn header_name(&self) -> &str {
+        &self.header_name
+    }
+
+    pub fn old_value(&self) -> &str {
+        &self.old_value
+    }
+
+    pub fn new_value(&self) -> &str {
+        &self.new_value
+    }
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_columns() {
+        let result = diff(&make_old(), &make_new(), &["id"]).unwrap();
+
+        assert_eq!(vec![String::from("price")], result.added_columns());
+        assert_eq!(vec![String::from("note")], result.removed_columns());
+    }
+
+    #[test]
+    fn diff_rows() {
+        let result = diff(&make_old(), &make_new(), &["id"]).unwrap();
+
+        assert_eq!(1, result.removed().len());
+        assert_eq!("2", result.removed()[0].get_value("id").unwrap());
+        assert_eq!(1, result.added().len());
+        assert_eq!("4", result.added()[0].get_value("id").unwrap());
+
+        assert_eq!(1, result.changed().len());
+        let row_change = &result.changed()[0];
+        assert_eq!(vec![String::from("3")], row_change.key());
+        assert_eq!(1, row_change.cells().len());
+        assert_eq!("name", row_change.cells()[0].header_name());
+        assert_eq!("やかまし", row_change.cells()[0].old_value());
+        assert_eq!("すもっく", row_change.cells()[0].new_value());
+    }
+
+    #[test]
+    fn diff_same() {
+        let result = diff(&make_old(), &make_old(), &["id"]).unwrap();
+
+        assert!(result.is_empty());
+        assert_eq!("", result.to_report());
+    }
+
+    #[test]
+    fn diff_report() {
+        let result = diff(&make_old(), &make_new(), &["id"]).unwrap();
+
+        assert_eq!("列の追加: [price]\n\
+                    列の削除: [note]\n\
+                    行の削除: id=[2]\n\
+                    行の追加: id=[4]\n\
+                    行の変更: id=[3]\n    name: [やかまし] -> [すもっく]\n", result.to_string());
+    }
+
+    #[test]
+    fn diff_to_csv() {
+        let result = diff(&make_old(), &make_new(), &["id"]).unwrap().to_csv();
+
+        let expect = [
+            ["change", "id", "column", "old_value", "new_value"],
+            ["removed", "2", "id", "2", ""],
+            ["removed", "2", "name", "船長うさぎ", ""],
+            ["removed", "2", "note", "B", ""],
+            ["added", "4", "id", "", "4"],
+            ["added", "4", "name", "", "おけぶろ"],
+            ["added", "4", "price", "", "400"],
+            ["changed", "3", "name", "やかまし", "すもっく"],
+        ];
+        let header = result.get_header();
+        for (index, name) in expect[0].iter().enumerate() {
+            assert_eq!(*name, header.get_name(index).unwrap());
+        }
+        assert_eq!(expect.len() - 1, result.get_body().len());
+        for (row_index, values) in expect[1..].iter().enumerate() {
+            for (index, value) in values.iter().enumerate() {
+                assert_eq!(*value, result.get_value(header.get_name(index).unwrap(), row_index).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn diff_error_duplicate_key() {
+        let old = make(&["id", "name"], &[["1", "いるかねこ"], ["1", "やかまし"]]);
+
+        match diff(&old, &make_new(), &["id"]) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("差分の取得に失敗しました。[キーが重複しています。[1]]", e),
+        }
+    }
+
+    #[test]
+    fn diff_error_header_name() {
+        match diff(&make_old(), &make_new(), &["note"]) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("差分の取得に失敗しました。[存在しないヘッダー名です。[note]]", e),
+        }
+    }
+
+    fn make_old() -> CsvFile {
+        make(&["id", "name", "note"], &[
+            ["1", "いるかねこ", "A"],
+            ["2", "船長うさぎ", "B"],
+            ["3", "やかまし", "C"],
+        ])
+    }
+
+    fn make_new() -> CsvFile {
+        make(&["id", "name", "price"], &[
+            ["1", "いるかねこ", "100"],
+            ["3", "すもっく", "300"],
+            ["4", "おけぶろ", "400"],
+        ])
+    }
+
+    fn make<const N: usize>(header_names: &[&str], rows: &[[&str; N]]) -> CsvFile {
+        CsvFile::from_rows(
+            header_names.iter().map(|name| name.to_string()).collect(),
+            rows.iter().map(|row| row.iter().map(|value| value.to_string()).collect()).collect(),
+        )
+    }
+}
@@ -0,0 +1,187 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::dialect::{CsvDialect, QuoteScanner};
+use crate::width::str_width;
+
+// 表示する行の最大の表示幅
+const SNIPPET_WIDTH: usize = 80;
+
+// 読み込み時のエラーと発生位置
+// 行と列はエラーが発生した項目の位置(項目が特定できない場合はレコードの先頭)
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub message: String,
+    // 1から始まる物理的な行番号
+    pub line: usize,
+    // 1から始まる文字単位の列番号
+    pub column: usize,
+    // ヘッダーを0とするレコード番号
+    pub record: usize,
+    // ファイルの先頭からのバイト位置
+    pub offset: usize,
+    // 0から始まる項目の番号
+    pub field: Option<usize>,
+    // エラーが発生した行の内容
+    pub snippet: String,
+}
+impl ParseError {
+    // レコードの開始位置とレコードの文字列から、項目の位置を求める
+    pub(crate) fn new(message: &str, text: &str, position: RecordPosition, field: Option<usize>, dialect: &CsvDialect)
+        -> Self {
+        let index = match field {
+            Some(field) => field_start(text, field, dialect),
+            None => 0,
+        };
+        let line_start = text[..index].rfind('\n').map(|found| found + 1).unwrap_or(0);
+        let line_end = text[index..].find('\n').map(|found| index + found).unwrap_or(text.len());
+        Self {
+            message: message.to_string(),
+            line: position.line + text[..index].matches('\n').count(),
+            column: text[line_start..index].chars().count() + 1,
+            record: position.record,
+            offset: position.offset + index,
+            field,
+            snippet: text[line_start..line_end].trim_end_matches('\r').to_string(),
+        }
+    }
+}
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} 行=[{}], 列=[{}], レコード=[{}], バイト位置=[{}]", self.message, self.line, self.column, self.record, self.offset)?;
+        if let Some(field) = self.field {
+            write!(f, ", 項目=[{}]", field)?;
+        }
+
+        // 長い行は列の位置が含まれるように切り詰める
+        let chars: Vec<char> = self.snippet.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+        let mut start = self.column.saturating_sub(1).min(chars.len());
+        let mut width = 0;
+        while start > 0 && width + str_width(&chars[start - 1].to_string()) <= SNIPPET_WIDTH / 2 {
+            start -= 1;
+            width += str_width(&chars[start].to_string());
+        }
+        let mut snippet = String::new();
+        if start > 0 {
+            snippet.push('…');
+        }
+        let mut caret = str_width(&snippet) + width;
+        let mut end = start;
+        while end < chars.len() && str_width(&snippet) + str_width(&chars[end].to_string()) <= SNIPPET_WIDTH {
+            snippet.push(chars[end]);
+            end += 1;
+        }
+        if end < chars.len() {
+            snippet.push('…');
+        }
+        if chars.is_empty() {
+            caret = 0;
+        }
+        write!(f, "\n{}\n{}^", snippet, " ".repeat(caret))
+    }
+}
+impl Error for ParseError {}
+impl From<ParseError> for String {
+    fn from(e: ParseError) -> Self {
+        e.to_string()
+    }
+}
+
+// レコードの開始位置
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct RecordPosition {
+    pub(crate) record: usize,
+    pub(crate) line: usize,
+    pub(crate) offset: usize,
+}
+impl Default for RecordPosition {
+    // ファイルの先頭
+    fn default() -> Self {
+        Self {record: 0, line: 1, offset: 0}
+    }
+}
+
+// 項目の開始位置(バイト単位)
+// 項目数が足りない場合はレコードの終わり
+// レコードの読み込みと同じく、項目の先頭の囲み文字だけを囲みの開始とする
+fn field_start(text: &str, field: usize, dialect: &CsvDialect) -> usize {
+    if field == 0 {
+        return 0;
+    }
+    let bytes = text.as_bytes();
+    let delimiter = dialect.delimiter.to_string();
+    let mut scanner = QuoteScanner::new(dialect);
+    let mut count = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+        let in_quote = scanner.in_quote();
+        let next = scanner.advance(bytes, index);
+        if !in_quote && bytes[index..].starts_with(delimiter.as_bytes()) {
+            count += 1;
+            if count == field {
+                return next;
+            }
+        }
+        index = next;
+    }
+    text.len()
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_error_position() {
+        let position = RecordPosition {record: 3, line: 5, offset: 100};
+        let e = ParseError::new("範囲外のインデックスが指定されました。[2]", "いるかねこ,\"改\r\n行\",船長うさぎ", position, Some(2), &CsvDialect::default());
+
+        assert_eq!(6, e.line);
+        assert_eq!(4, e.column);
+        assert_eq!(3, e.record);
+        assert_eq!(100 + "いるかねこ,\"改\r\n行\",".len(), e.offset);
+        assert_eq!("行\",船長うさぎ", e.snippet);
+        assert_eq!(format!("範囲外のインデックスが指定されました。[2] 行=[6], 列=[4], レコード=[3], バイト位置=[{}], 項目=[2]\n行\",船長うさぎ\n    ^", e.offset),
+                   e.to_string());
+    }
+
+    #[test]
+    fn parse_error_stray_quote() {
+        // 項目の途中の囲み文字では囲みを開始しない
+        let e = ParseError::new("エラー", "船長\"うさぎ,200,x", RecordPosition::default(), Some(2), &CsvDialect::default());
+
+        assert_eq!(12, e.column);
+        assert_eq!("船長\"うさぎ,200,".len(), e.offset);
+        assert_eq!(format!("エラー 行=[1], 列=[12], レコード=[0], バイト位置=[{}], 項目=[2]\n船長\"うさぎ,200,x\n{}^", e.offset, " ".repeat(16)),
+                   e.to_string());
+
+        match crate::read_from("名前,金額\n船長\"うさぎ,200,x\n".as_bytes(), &CsvDialect::default()) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert!(e.contains("行=[2], 列=[12], レコード=[1], バイト位置=[35], 項目=[2]"), "{}", e),
+        }
+    }
+
+    #[test]
+    fn parse_error_long_line() {
+        let text = format!("{},{}", "あ".repeat(60), "い".repeat(60));
+        let e = ParseError::new("エラー", &text, RecordPosition::default(), Some(1), &CsvDialect::default());
+
+        let display = e.to_string();
+        let lines: Vec<&str> = display.lines().collect();
+        assert_eq!("エラー 行=[1], 列=[62], レコード=[0], バイト位置=[181], 項目=[1]", lines[0]);
+        // 列の位置の前後を表示する
+        assert_eq!(format!("…{},{}…", "あ".repeat(19), "い".repeat(20)), lines[1]);
+        assert_eq!(format!("{}^", " ".repeat(40)), lines[2]);
+    }
+}
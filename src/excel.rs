@@ -0,0 +1,219 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::dialect::format_record;
+use crate::sanitize::is_formula;
+use crate::{CsvDialect, CsvFile};
+
+// Excelで開くファイルの文字コード
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExcelEncoding {
+    // BOM付きUTF-8
+    Utf8Bom,
+    // Shift_JIS(Windows-31J)
+    #[cfg(feature = "cp932")]
+    Cp932,
+}
+
+pub struct ExcelOptions {
+    pub encoding: ExcelEncoding,
+    // 数値として解釈される値を="00123"の形式で書き込む列
+    pub leading_zero_columns: Vec<String>,
+    // =,+,-,@で始まる値の先頭に'を付けて数式として解釈されないようにする
+    pub escape_formulas: bool,
+}
+impl Default for ExcelOptions {
+    fn default() -> Self {
+        Self {
+            encoding: ExcelEncoding::Utf8Bom,
+            leading_zero_columns: Vec::new(),
+            escape_formulas: true,
+        }
+    }
+}
+
+// Excelで開くためのCSVを書き込む
+// 既存のファイルは上書きする
+pub fn write_excel(path: &str, csv_file: &CsvFile, options: &ExcelOptions) -> Result<(), String> {
+    let file = match File::create(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("createに失敗しました。[{}]", e)),
+    };
+    write_excel_to(file, csv_file, options)
+}
+
+pub fn write_excel_to<W: Write>(writer: W, csv_file: &CsvFile, options: &ExcelOptions) -> Result<(), String> {
+    let header_names = &csv_file.csv_header.name;
+    for column in &options.leading_zero_columns {
+        if !header_names.contains(column) {
+            return Err(format!("Excel形式の書き込みに失敗しました。[存在しないヘッダー名です。[{}]]", column));
+        }
+    }
+    let protected: Vec<bool> = header_names.iter()
+        .map(|name| options.leading_zero_columns.contains(name))
+        .collect();
+
+    let dialect = CsvDialect::default();
+    let mut data = String::new();
+    if options.encoding == ExcelEncoding::Utf8Bom {
+        data.push('\u{FEFF}');
+    }
+    push_line(&mut data, header_names.iter().map(|name| (name.as_str(), false)), options, &dialect);
+    for row in &csv_file.csv_body.rows {
+        let values = row.data.iter().enumerate()
+            .map(|(index, csv_data)| (csv_data.value.as_str(), protected.get(index).copied().unwrap_or(false)));
+        push_line(&mut data, values, options, &dialect);
+    }
+
+    let bytes = encode(data, csv_file, options.encoding)?;
+    let mut writer = BufWriter::new(writer);
+    if let Err(e) = writer.write_all(&bytes) {
+        return Err(format!("writeに失敗しました。[{}]", e));
+    }
+    if let Err(e) = writer.flush() {
+        return Err(format!("flushに失敗しました。[{}]", e));
+    }
+    Ok(())
+}
+
+// 1行分を改行コード(CRLF)付きで追加する
+fn push_line<'a, I>(data: &mut String, values: I, options: &ExcelOptions, dialect: &CsvDialect)
+where
+    I: Iterator<Item = (&'a str, bool)>,
+{
+    for (index, (value, protected)) in values.enumerate() {
+        if index > 0 {
+            data.push(dialect.delimiter);
+        }
+        if protected && !value.is_empty() && value.parse::<f64>().is_ok() {
+            data.push_str(&format!("=\"{}\"", value));
+        } else if options.escape_formulas && is_formula(value) {
+            data.push_str(&format_record([format!("'{}", value).as_str()], dialect));
+        } else {
+            data.push_str(&format_record([value], dialect));
+        }
+    }
+    data.push_str("\r\n");
+}
+
+#[cfg(not(feature = "cp932"))]
+fn encode(data: String, _csv_file: &CsvFile, _encoding: ExcelEncoding) -> Result<Vec<u8>, String> {
+    Ok(data.into_bytes())
+}
+
+#[cfg(feature = "cp932")]
+fn encode(data: String, csv_file: &CsvFile, encoding: ExcelEncoding) -> Result<Vec<u8>, String> {
+    if encoding == ExcelEncoding::Utf8Bom {
+        return Ok(data.into_bytes());
+    }
+    let (bytes, _, had_errors) = encoding_rs::SHIFT_JIS.encode(&data);
+    if !had_errors {
+        return Ok(bytes.into_owned());
+    }
+
+    // 変換できない値を探す
+    let encodable = |value: &str| !encoding_rs::SHIFT_JIS.encode(value).2;
+    for name in &csv_file.csv_header.name {
+        if !encodable(name) {
+            return Err(format!("Excel形式の書き込みに失敗しました。[CP932に変換できない文字があります。ヘッダー名=[{}]]", name));
+        }
+    }
+    for (index, row) in csv_file.csv_body.rows.iter().enumerate() {
+        for csv_data in &row.data {
+            if !encodable(&csv_data.value) {
+                return Err(format!("Excel形式の書き込みに失敗しました。[CP932に変換できない文字があります。行=[{}], 列=[{}], 値=[{}]]",
+                                   index, csv_data.header_name, csv_data.value));
+            }
+        }
+    }
+    Err(String::from("Excel形式の書き込みに失敗しました。[CP932に変換できない文字があります。]"))
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_excel_utf8_bom() {
+        let csv = make_file();
+        let options = ExcelOptions {leading_zero_columns: vec![String::from("コード")], ..ExcelOptions::default()};
+
+        let mut output = Vec::new();
+        write_excel_to(&mut output, &csv, &options).unwrap();
+
+        assert_eq!("\u{FEFF}コード,名前,金額\r\n\
+                    =\"00123\",いるかねこ,-2.5\r\n\
+                    A-1,'=SUM(A1:A2),'-1+2\r\n\
+                    ,\"船長,うさぎ\",\"改\n行\"\r\n",
+                   String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn write_excel_no_escape() {
+        let csv = make_file();
+        let options = ExcelOptions {escape_formulas: false, ..ExcelOptions::default()};
+
+        let mut output = Vec::new();
+        write_excel_to(&mut output, &csv, &options).unwrap();
+
+        let text = String::from_utf8(output).unwrap();
+        assert!(text.contains("\r\n00123,いるかねこ,-2.5\r\n"));
+        assert!(text.contains("\r\nA-1,=SUM(A1:A2),-1+2\r\n"));
+    }
+
+    #[test]
+    fn write_excel_error() {
+        let csv = make_file();
+        let options = ExcelOptions {leading_zero_columns: vec![String::from("電話")], ..ExcelOptions::default()};
+
+        match write_excel_to(Vec::new(), &csv, &options) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("Excel形式の書き込みに失敗しました。[存在しないヘッダー名です。[電話]]", e),
+        }
+    }
+
+    #[cfg(feature = "cp932")]
+    #[test]
+    fn write_excel_cp932() {
+        let csv = CsvFile::from_rows(
+            vec![String::from("名前")],
+            vec![vec![String::from("いるかねこ")], vec![String::from("😀")]],
+        );
+        let options = ExcelOptions {encoding: ExcelEncoding::Cp932, ..ExcelOptions::default()};
+
+        match write_excel_to(Vec::new(), &csv, &options) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("Excel形式の書き込みに失敗しました。[CP932に変換できない文字があります。行=[1], 列=[名前], 値=[😀]]", e),
+        }
+
+        let csv = csv.head(1);
+        let mut output = Vec::new();
+        write_excel_to(&mut output, &csv, &options).unwrap();
+        let (text, _, had_errors) = encoding_rs::SHIFT_JIS.decode(&output);
+        assert!(!had_errors);
+        assert_eq!("名前\r\nいるかねこ\r\n", text);
+        assert_eq!(&[0x96, 0xBC, 0x91, 0x4F], &output[..4]);
+    }
+
+    fn make_file() -> CsvFile {
+        CsvFile::from_rows(
+            vec![String::from("コード"), String::from("名前"), String::from("金額")],
+            vec![
+                vec![String::from("00123"), String::from("いるかねこ"), String::from("-2.5")],
+                vec![String::from("A-1"), String::from("=SUM(A1:A2)"), String::from("-1+2")],
+                vec![String::new(), String::from("船長,うさぎ"), String::from("改\n行")],
+            ],
+        )
+    }
+}
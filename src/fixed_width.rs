@@ -0,0 +1,311 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+
+use crate::dialect::trim_line_end;
+use crate::width::{char_width, str_width};
+use crate::CsvFile;
+
+// 値の寄せ方
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+// 固定長の1列
+// startとwidthは表示幅(全角文字は2)で指定する
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixedWidthColumn {
+    pub name: String,
+    // 行頭を0とする開始位置
+    pub start: usize,
+    pub width: usize,
+    pub align: Align,
+    // 埋め文字(半角文字)
+    pub pad: char,
+}
+impl FixedWidthColumn {
+    // 左寄せ、空白埋めの列
+    pub fn new(name: &str, start: usize, width: usize) -> Self {
+        Self {name: name.to_string(), start, width, align: Align::Left, pad: ' '}
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FixedWidthLayout {
+    columns: Vec<FixedWidthColumn>,
+}
+impl FixedWidthLayout {
+    pub fn new() -> Self {
+        Self {columns: Vec::new()}
+    }
+
+    // 他の列と重なる列は追加できない
+    pub fn push(&mut self, column: FixedWidthColumn) -> Result<(), String> {
+        if column.width == 0 {
+            return Err(format!("列の幅が0です。[{}]", column.name));
+        }
+        if char_width(column.pad) != 1 {
+            return Err(format!("埋め文字は半角文字を指定してください。[{}]", column.name));
+        }
+        for other in &self.columns {
+            if other.name == column.name {
+                return Err(format!("ヘッダー名が重複しています。[{}]", column.name));
+            }
+            if column.start < other.start + other.width && other.start < column.start + column.width {
+                return Err(format!("列の範囲が重なっています。[{}], [{}]", other.name, column.name));
+            }
+        }
+        self.columns.push(column);
+        Ok(())
+    }
+
+    pub fn columns(&self) -> &[FixedWidthColumn] {
+        &self.columns
+    }
+}
+
+pub fn read_fixed_width(path: &str, layout: &FixedWidthLayout) -> Result<CsvFile, String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("openに失敗しました。{}", e)),
+    };
+    read_fixed_width_from(file, layout)
+}
+
+// 1行を1レコードとして読み込む
+// 空行は読み飛ばし、行が短い場合は足りない列を空とする
+pub fn read_fixed_width_from<R: Read>(reader: R, layout: &FixedWidthLayout) -> Result<CsvFile, String> {
+    let header_names = layout.columns.iter().map(|column| column.name.clone()).collect();
+    let mut rows = Vec::new();
+    let mut reader = BufReader::new(reader);
+    let mut line_number = 0;
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => return Err(format!("readに失敗しました。[{}]", e)),
+        }
+        line_number += 1;
+        trim_line_end(&mut line);
+        if line_number == 1 && line.starts_with('\u{FEFF}') {
+            line.remove(0);
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut values = Vec::with_capacity(layout.columns.len());
+        for column in &layout.columns {
+            match slice(&line, column) {
+                Ok(value) => values.push(value),
+                Err(e) => return Err(format!("固定長の読み込みに失敗しました。行=[{}], 列=[{}], [{}]", line_number, column.name, e)),
+            }
+        }
+        rows.push(values);
+    }
+
+    Ok(CsvFile::from_rows(header_names, rows))
+}
+
+// 列の範囲の文字列を取り出し、埋め文字を取り除く
+fn slice(line: &str, column: &FixedWidthColumn) -> Result<String, String> {
+    let end = column.start + column.width;
+    let mut value = String::new();
+    let mut position = 0;
+    for c in line.chars() {
+        let next = position + char_width(c);
+        if position >= end {
+            break;
+        }
+        if next > column.start {
+            if position < column.start || next > end {
+                return Err(String::from("全角文字が列の境界をまたいでいます。"));
+            }
+            value.push(c);
+        }
+        position = next;
+    }
+
+    let trimmed = match column.align {
+        Align::Left => value.trim_end_matches(column.pad),
+        Align::Right => value.trim_start_matches(column.pad),
+    };
+    // 空白以外の埋め文字だけの場合は1文字を残す(0埋めの000は0)
+    if trimmed.is_empty() && !value.is_empty() && column.pad != ' ' {
+        return Ok(column.pad.to_string());
+    }
+    Ok(trimmed.to_string())
+}
+
+// 既存のファイルは上書きする
+pub fn write_fixed_width(path: &str, csv_file: &CsvFile, layout: &FixedWidthLayout) -> Result<(), String> {
+    let file = match File::create(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("createに失敗しました。[{}]", e)),
+    };
+    write_fixed_width_to(file, csv_file, layout)
+}
+
+// ヘッダー行は出力しない
+// 列の間の隙間は空白で埋める
+pub fn write_fixed_width_to<W: Write>(writer: W, csv_file: &CsvFile, layout: &FixedWidthLayout) -> Result<(), String> {
+    let mut columns: Vec<(&FixedWidthColumn, usize)> = Vec::with_capacity(layout.columns.len());
+    for column in &layout.columns {
+        match csv_file.csv_header.name.iter().position(|name| *name == column.name) {
+            Some(index) => columns.push((column, index)),
+            None => return Err(format!("固定長の書き込みに失敗しました。[存在しないヘッダー名です。[{}]]", column.name)),
+        }
+    }
+    columns.sort_by_key(|(column, _)| column.start);
+
+    let mut data = String::new();
+    for (row_index, row) in csv_file.csv_body.rows.iter().enumerate() {
+        let mut position = 0;
+        for (column, index) in &columns {
+            let value = row.data.get(*index).map(|csv_data| csv_data.value.as_str()).unwrap_or("");
+            let width = str_width(value);
+            if width > column.width {
+                return Err(format!("固定長の書き込みに失敗しました。[値が列の幅を超えています。行=[{}], 列=[{}], 値=[{}]]",
+                                   row_index, column.name, value));
+            }
+            if value.contains(['\r', '\n']) {
+                return Err(format!("固定長の書き込みに失敗しました。[値に改行が含まれています。行=[{}], 列=[{}]]",
+                                   row_index, column.name));
+            }
+
+            data.push_str(&" ".repeat(column.start - position));
+            let padding: String = std::iter::repeat_n(column.pad, column.width - width).collect();
+            match column.align {
+                Align::Left => {
+                    data.push_str(value);
+                    data.push_str(&padding);
+                }
+                Align::Right => {
+                    data.push_str(&padding);
+                    data.push_str(value);
+                }
+            }
+            position = column.start + column.width;
+        }
+        data.push('\n');
+    }
+
+    let mut writer = BufWriter::new(writer);
+    if let Err(e) = writer.write_all(data.as_bytes()) {
+        return Err(format!("writeに失敗しました。[{}]", e));
+    }
+    if let Err(e) = writer.flush() {
+        return Err(format!("flushに失敗しました。[{}]", e));
+    }
+    Ok(())
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_fixed_width_full_width() {
+        let csv = CsvFile::from_rows(
+            vec![String::from("コード"), String::from("名前"), String::from("金額")],
+            vec![
+                vec![String::from("1"), String::from("いるかねこ"), String::from("100")],
+                vec![String::from("22"), String::from("船長"), String::from("-5")],
+            ],
+        );
+
+        let mut output = Vec::new();
+        write_fixed_width_to(&mut output, &csv, &make_layout()).unwrap();
+
+        assert_eq!("\
+001 いるかねこ   100
+022 船長          -5
+", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn read_fixed_width_full_width() {
+        let text = "001 いるかねこ   100\r\n\r\n022 船長          -5\n3";
+
+        let csv = read_fixed_width_from(text.as_bytes(), &make_layout()).unwrap();
+
+        assert_eq!(vec!["コード", "名前", "金額"], csv.get_header().name);
+        assert_eq!(vec!["1", "いるかねこ", "100"], csv.get_body().get_row(0).unwrap().values());
+        assert_eq!(vec!["22", "船長", "-5"], csv.get_body().get_row(1).unwrap().values());
+        assert_eq!(vec!["3", "", ""], csv.get_body().get_row(2).unwrap().values());
+    }
+
+    #[test]
+    fn fixed_width_round_trip_zero() {
+        let csv = CsvFile::from_rows(
+            vec![String::from("コード"), String::from("名前"), String::from("金額")],
+            vec![vec![String::from("0"), String::from("いるかねこ"), String::from("0")]],
+        );
+
+        let mut output = Vec::new();
+        write_fixed_width_to(&mut output, &csv, &make_layout()).unwrap();
+        let read_csv = read_fixed_width_from(output.as_slice(), &make_layout()).unwrap();
+
+        assert_eq!("000 いるかねこ     0\n", String::from_utf8(output).unwrap());
+        assert_eq!(vec!["0", "いるかねこ", "0"], read_csv.get_body().get_row(0).unwrap().values());
+    }
+
+    #[test]
+    fn read_fixed_width_error() {
+        // 全角文字が名前の終わり(14桁目)をまたぐ
+        let text = "001 いるかね こ";
+
+        match read_fixed_width_from(text.as_bytes(), &make_layout()) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("固定長の読み込みに失敗しました。行=[1], 列=[名前], [全角文字が列の境界をまたいでいます。]", e),
+        }
+    }
+
+    #[test]
+    fn write_fixed_width_error() {
+        let csv = CsvFile::from_rows(
+            vec![String::from("コード"), String::from("名前"), String::from("金額")],
+            vec![vec![String::from("1"), String::from("いるかねこいるか"), String::from("0")]],
+        );
+
+        match write_fixed_width_to(Vec::new(), &csv, &make_layout()) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("固定長の書き込みに失敗しました。[値が列の幅を超えています。行=[0], 列=[名前], 値=[いるかねこいるか]]", e),
+        }
+    }
+
+    #[test]
+    fn layout_push_error() {
+        let mut layout = make_layout();
+
+        match layout.push(FixedWidthColumn::new("備考", 10, 5)) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("列の範囲が重なっています。[名前], [備考]", e),
+        }
+        match layout.push(FixedWidthColumn {pad: '０', ..FixedWidthColumn::new("備考", 20, 5)}) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("埋め文字は半角文字を指定してください。[備考]", e),
+        }
+    }
+
+    fn make_layout() -> FixedWidthLayout {
+        let mut layout = FixedWidthLayout::new();
+        layout.push(FixedWidthColumn {align: Align::Right, pad: '0', ..FixedWidthColumn::new("コード", 0, 3)}).unwrap();
+        layout.push(FixedWidthColumn::new("名前", 4, 10)).unwrap();
+        layout.push(FixedWidthColumn {align: Align::Right, ..FixedWidthColumn::new("金額", 15, 5)}).unwrap();
+        layout
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use crate::CsvFile;
+
+// 集計方法
+#[derive(Clone, Debug, PartialEq)]
+enum AggregationKind {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+}
+
+// 集計列の定義
+// count(), sum("amount")などの関数で作成する
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aggregation {
+    kind: AggregationKind,
+    header_name: String,
+}
+impl Aggregation {
+    // 集計結果のヘッダー名(count, sum(amount)など)
+    pub fn name(&self) -> String {
+        match self.kind {
+            AggregationKind::Count => String::from("count"),
+            AggregationKind::Sum => format!("sum({})", self.header_name),
+            AggregationKind::Min => format!("min({})", self.header_name),
+            AggregationKind::Max => format!("max({})", self.header_name),
+            AggregationKind::Avg => format!("avg({})", self.header_name),
+        }
+    }
+}
+
+pub fn count() -> Aggregation {
+    Aggregation {kind: AggregationKind::Count, header_name: String::new()}
+}
+
+pub fn sum(header_name: &str) -> Aggregation {
+    Aggregation {kind: AggregationKind::Sum, header_name: header_name.to_string()}
+}
+
+pub fn min(header_name: &str) -> Aggregation {
+    Aggregation {kind: AggregationKind::Min, header_name: header_name.to_string()}
+}
+
+pub fn max(header_name: &str) -> Aggregation {
+    Aggregation {kind: AggregationKind::Max, header_name: header_name.to_string()}
+}
+
+pub fn avg(header_name: &str) -> Aggregation {
+    Aggregation {kind: AggregationKind::Avg, header_name: header_name.to_string()}
+}
+
+pub struct GroupBy<'a> {
+    csv_file: &'a CsvFile,
+    header_names: Vec<String>,
+}
+impl GroupBy<'_> {
+    // グループごとに集計し、グループ列+集計列のCsvFileを作成する
+    // グループは最初に出現した順に並ぶ
+    pub fn agg<I>(&self, aggregations: I) -> Result<CsvFile, String>
+    where
+        I: IntoIterator<Item = Aggregation>,
+    {
+        let aggregations: Vec<Aggregation> = aggregations.into_iter().collect();
+
+        // 存在しないヘッダー名はここでエラーにする
+        let names = &self.csv_file.csv_header.name;
+        for header_name in &self.header_names {
+            if !names.contains(header_name) {
+                return Err(format!("集計に失敗しました。[存在しないヘッダー名です。[{}]]", header_name));
+            }
+        }
+        for aggregation in &aggregations {
+            if aggregation.kind != AggregationKind::Count && !names.contains(&aggregation.header_name) {
+                return Err(format!("集計に失敗しました。[存在しないヘッダー名です。[{}]]"
+                                   , aggregation.header_name));
+            }
+        }
+
+        let mut keys: Vec<Vec<String>> = Vec::new();
+        let mut accumulators: Vec<Vec<Accumulator>> = Vec::new();
+        let mut positions: HashMap<Vec<String>, usize> = HashMap::new();
+        let mut errors: Vec<String> = Vec::new();
+        for (row_index, row) in self.csv_file.csv_body.rows.iter().enumerate() {
+            let mut key = Vec::with_capacity(self.header_names.len());
+            for header_name in &self.header_names {
+                key.push(row.get_value(header_name)?);
+            }
+
+            let position = match positions.get(&key) {
+                Some(position) => *position,
+                None => {
+                    positions.insert(key.clone(), keys.len());
+                    keys.push(key);
+                    accumulators.push(vec![Accumulator::new(); aggregations.len()]);
+                    keys.len() - 1
+                }
+            };
+
+            for (aggregation, accumulator) in aggregations.iter()
+                .zip(accumulators[position].iter_mut()) {
+                accumulator.rows += 1;
+                if aggregation.kind == AggregationKind::Count {
+                    continue;
+                }
+
+                // 空の値は集計対象外にする
+                let value = row.get_value(&aggregation.header_name)?;
+                if value.trim().is_empty() {
+                    continue;
+                }
+                match value.trim().parse::<f64>() {
+                    Ok(number) => accumulator.add(number),
+                    Err(_) => errors.push(format!("数値に変換できません。行=[{}], 列=[{}], 値=[{}]"
+                                                  , row_index, aggregation.header_name, value)),
+                }
+            }
+        }
+
+        // 変換エラーはすべてのセル分をまとめて返す
+        if !errors.is_empty() {
+            return Err(format!("集計に失敗しました。[{}]", errors.join(", ")));
+        }
+
+        let mut header_names = self.header_names.clone();
+        for aggregation in &aggregations {
+            header_names.push(aggregation.name());
+        }
+
+        let mut rows = Vec::with_capacity(keys.len());
+        for (key, accumulators) in keys.into_iter().zip(accumulators) {
+            let mut values = key;
+            for (aggregation, accumulator) in aggregations.iter().zip(accumulators) {
+                values.push(accumulator.result(&aggregation.kind));
+            }
+            rows.push(values);
+        }
+
+        Ok(CsvFile::from_rows(header_names, rows))
+    }
+}
+
+#[derive(Clone)]
+struct Accumulator {
+    rows: usize,
+    count: usize,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+impl Accumulator {
+    fn new() -> Self {
+        Self {rows: 0, count: 0, sum: 0.0, min: f64::INFINITY, max: f64::NEG_INFINITY}
+    }
+
+    fn add(&mut self, number: f64) {
+        self.count += 1;
+        self.sum += number;
+        self.min = self.min.min(number);
+        self.max = self.max.max(number);
+    }
+
+    fn result(&self, kind: &AggregationKind) -> String {
+        if *kind == AggregationKind::Count {
+            return self.rows.to_string();
+        }
+        // 集計対象の値がなかった場合は空にする
+        if self.count == 0 {
+            return String::new();
+        }
+        match kind {
+            AggregationKind::Sum => self.sum.to_string(),
+            AggregationKind::Min => self.min.to_string(),
+            AggregationKind::Max => self.max.to_string(),
+            AggregationKind::Avg => (self.sum / self.count as f64).to_string(),
+            AggregationKind::Count => self.rows.to_string(),
+        }
+    }
+}
+
+impl CsvFile {
+    pub fn group_by(&self, header_names: &[&str]) -> GroupBy<'_> {
+        GroupBy {
+            csv_file: self,
+            header_names: header_names.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_by_agg_header() {
+        let file = make_file();
+
+        let result = file.group_by(&["region"])
+            .agg([count(), sum("amount"), avg("price"), min("price"), max("price")])
+            .unwrap();
+
+        let header = result.get_header();
+        let expect = ["region", "count", "sum(amount)", "avg(price)", "min(price)", "max(price)"];
+        assert_eq!(expect.len(), header.len());
+        for (index, name) in expect.iter().enumerate() {
+            assert_eq!(*name, header.get_name(index).unwrap());
+        }
+    }
+
+    #[test]
+    fn group_by_agg() {
+        let file = make_file();
+
+        let result = file.group_by(&["region"])
+            .agg([count(), sum("amount"), avg("price"), min("price"), max("price")])
+            .unwrap();
+
+        let expect = [
+            ["東京", "3", "60", "200", "100", "300"],
+            ["大阪", "2", "5", "15", "10", "20"],
+        ];
+        assert_eq!(2, result.get_body().len());
+        for (row_index, values) in expect.iter().enumerate() {
+            for (index, value) in values.iter().enumerate() {
+                let name = result.get_header().get_name(index).unwrap().to_string();
+                assert_eq!(*value, result.get_value(&name, row_index).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn group_by_agg_multi_column() {
+        let file = make_file();
+
+        let result = file.group_by(&["region", "shop"]).agg([count()]).unwrap();
+
+        assert_eq!(4, result.get_body().len());
+        assert_eq!("梅田", result.get_value("shop", 2).unwrap());
+        assert_eq!("2", result.get_value("count", 0).unwrap());
+    }
+
+    #[test]
+    fn group_by_agg_skip_empty() {
+        let file = CsvFile::from_rows(
+            vec![String::from("region"), String::from("amount")],
+            vec![
+                vec![String::from("東京"), String::from("")],
+                vec![String::from("東京"), String::from("4")],
+                vec![String::from("大阪"), String::from("")],
+            ],
+        );
+
+        let result = file.group_by(&["region"]).agg([count(), avg("amount")]).unwrap();
+
+        assert_eq!("2", result.get_value("count", 0).unwrap());
+        assert_eq!("4", result.get_value("avg(amount)", 0).unwrap());
+        assert_eq!("", result.get_value("avg(amount)", 1).unwrap());
+    }
+
+    #[test]
+    fn group_by_agg_error_number() {
+        let file = CsvFile::from_rows(
+            vec![String::from("region"), String::from("amount")],
+            vec![
+                vec![String::from("東京"), String::from("abc")],
+                vec![String::from("東京"), String::from("4")],
+                vec![String::from("大阪"), String::from("1,0")],
+            ],
+        );
+
+        match file.group_by(&["region"]).agg([sum("amount")]) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("集計に失敗しました。[\
+                                  数値に変換できません。行=[0], 列=[amount], 値=[abc], \
+                                  数値に変換できません。行=[2], 列=[amount], 値=[1,0]]", e),
+        }
+    }
+
+    #[test]
+    fn group_by_agg_error_header_name() {
+        let file = make_file();
+
+        match file.group_by(&["region"]).agg([sum("ヘッダー4")]) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("集計に失敗しました。[存在しないヘッダー名です。[ヘッダー4]]", e),
+        }
+    }
+
+    fn make_file() -> CsvFile {
+        let rows = [
+            ["東京", "新宿", "10", "100"],
+            ["大阪", "難波", "2", "10"],
+            ["東京", "新宿", "20", "200"],
+            ["大阪", "梅田", "3", "20"],
+            ["東京", "渋谷", "30", "300"],
+        ];
+
+        CsvFile::from_rows(
+            ["region", "shop", "amount", "price"].iter().map(|name| name.to_string()).collect(),
+            rows.iter().map(|row| row.iter().map(|value| value.to_string()).collect()).collect(),
+        )
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+
+use crate::{CsvFile, CsvRow};
+
+// 結合の種類
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Right,
+    Full,
+}
+
+pub struct JoinOptions {
+    pub kind: JoinKind,
+    // キー以外で名前が重複した列に付ける接尾辞
+    pub left_suffix: String,
+    pub right_suffix: String,
+}
+impl JoinOptions {
+    pub fn new(kind: JoinKind) -> Self {
+        Self {
+            kind,
+            left_suffix: String::from("_left"),
+            right_suffix: String::from("_right"),
+        }
+    }
+}
+
+impl CsvFile {
+    pub fn join(&self, right: &CsvFile, header_names: &[&str], kind: JoinKind)
+        -> Result<CsvFile, String> {
+        self.join_with(right, header_names, &JoinOptions::new(kind))
+    }
+
+    // キー列でハッシュ結合する
+    // 結果の列は左側の列(キー列を含む)、右側のキー以外の列の順になる
+    pub fn join_with(&self, right: &CsvFile, header_names: &[&str], options: &JoinOptions)
+        -> Result<CsvFile, String> {
+        if header_names.is_empty() {
+            return Err(String::from("結合に失敗しました。[キー列が指定されていません。]"));
+        }
+        let left_names = &self.csv_header.name;
+        let right_names = &right.csv_header.name;
+        for header_name in header_names {
+            if !left_names.iter().any(|name| name == header_name)
+                || !right_names.iter().any(|name| name == header_name) {
+                return Err(format!("結合に失敗しました。[存在しないヘッダー名です。[{}]]", header_name));
+            }
+        }
+
+        // 結果のヘッダー名を作成する
+        let is_key = |name: &String| header_names.iter().any(|key| key == name);
+        let right_indexes: Vec<usize> = (0..right_names.len()).filter(|index| !is_key(&right_names[*index])).collect();
+        let right_columns: Vec<&String> = right_indexes.iter().map(|index| &right_names[*index]).collect();
+        let mut result_names = Vec::with_capacity(left_names.len() + right_columns.len());
+        for name in left_names {
+            if !is_key(name) && right_columns.contains(&name) {
+                result_names.push(format!("{}{}", name, options.left_suffix));
+            } else {
+                result_names.push(name.clone());
+            }
+        }
+        for name in &right_columns {
+            if left_names.contains(name) {
+                result_names.push(format!("{}{}", name, options.right_suffix));
+            } else {
+                result_names.push(name.to_string());
+            }
+        }
+        for (index, name) in result_names.iter().enumerate() {
+            if result_names[..index].contains(name) {
+                return Err(format!("結合に失敗しました。[結合後のヘッダー名が重複しています。[{}]]", name));
+            }
+        }
+
+        // 右側のキーからハッシュ表を作成する
+        let mut table: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+        for (index, row) in right.csv_body.rows.iter().enumerate() {
+            table.entry(make_key(row, header_names)?).or_default().push(index);
+        }
+
+        let mut matched = vec![false; right.csv_body.rows.len()];
+        let mut rows = Vec::new();
+        for left_row in &self.csv_body.rows {
+            let key = make_key(left_row, header_names)?;
+            match table.get(&key) {
+                Some(indexes) => {
+                    for index in indexes {
+                        matched[*index] = true;
+                        let right_values = padded_values(&right.csv_body.rows[*index], right_names.len());
+                        let mut values = padded_values(left_row, left_names.len());
+                        values.extend(right_indexes.iter().map(|index| right_values[*index].clone()));
+                        rows.push(values);
+                    }
+                }
+                None => {
+                    if options.kind == JoinKind::Left || options.kind == JoinKind::Full {
+                        let mut values = padded_values(left_row, left_names.len());
+                        values.resize(result_names.len(), String::new());
+                        rows.push(values);
+                    }
+                }
+            }
+        }
+
+        // 右側で結合されなかった行を追加する
+        if options.kind == JoinKind::Right || options.kind == JoinKind::Full {
+            for (index, right_row) in right.csv_body.rows.iter().enumerate() {
+                if matched[index] {
+                    continue;
+                }
+                let right_values = padded_values(right_row, right_names.len());
+                let mut values = Vec::with_capacity(result_names.len());
+                for name in left_names {
+                    if is_key(name) {
+                        values.push(right_row.get_value(name)?);
+                    } else {
+                        values.push(String::new());
+                    }
+                }
+                values.extend(right_indexes.iter().map(|index| right_values[*index].clone()));
+                rows.push(values);
+            }
+        }
+
+        Ok(CsvFile::from_rows(result_names, rows))
+    }
+}
+
+fn make_key(row: &CsvRow, header_names: &[&str]) -> Result<Vec<String>, String> {
+    let mut key = Vec::with_capacity(header_names.len());
+    for header_name in header_names {
+        key.push(row.get_value(header_name)?);
+    }
+    Ok(key)
+}
+
+// 項目が足りない行は空文字で補い、ヘッダーの列数に揃える
+fn padded_values(row: &CsvRow, width: usize) -> Vec<String> {
+    let mut values = row.values();
+    values.resize(width, String::new());
+    values
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read_from, CsvDialect};
+
+    #[test]
+    fn join_header() {
+        let result = make_master().join(&make_transaction(), &["id"], JoinKind::Inner).unwrap();
+
+        let expect = ["id", "name", "note_left", "amount", "note_right"];
+        let header = result.get_header();
+        assert_eq!(expect.len(), header.len());
+        for (index, name) in expect.iter().enumerate() {
+            assert_eq!(*name, header.get_name(index).unwrap());
+        }
+    }
+
+    #[test]
+    fn join_header_suffix() {
+        let mut options = JoinOptions::new(JoinKind::Inner);
+        options.left_suffix = String::from("_m");
+        options.right_suffix = String::from("_t");
+
+        let result = make_master().join_with(&make_transaction(), &["id"], &options).unwrap();
+
+        assert_eq!("note_m", result.get_header().get_name(2).unwrap());
+        assert_eq!("note_t", result.get_header().get_name(4).unwrap());
+    }
+
+    #[test]
+    fn join_inner() {
+        let result = make_master().join(&make_transaction(), &["id"], JoinKind::Inner).unwrap();
+
+        let expect = [
+            ["1", "いるかねこ", "A", "100", "x"],
+            ["1", "いるかねこ", "A", "200", "y"],
+            ["2", "船長うさぎ", "B", "300", "z"],
+        ];
+        assert_rows(&expect, &result);
+    }
+
+    #[test]
+    fn join_left() {
+        let result = make_master().join(&make_transaction(), &["id"], JoinKind::Left).unwrap();
+
+        let expect = [
+            ["1", "いるかねこ", "A", "100", "x"],
+            ["1", "いるかねこ", "A", "200", "y"],
+            ["2", "船長うさぎ", "B", "300", "z"],
+            ["3", "やかまし", "C", "", ""],
+        ];
+        assert_rows(&expect, &result);
+    }
+
+    #[test]
+    fn join_right() {
+        let result = make_master().join(&make_transaction(), &["id"], JoinKind::Right).unwrap();
+
+        let expect = [
+            ["1", "いるかねこ", "A", "100", "x"],
+            ["1", "いるかねこ", "A", "200", "y"],
+            ["2", "船長うさぎ", "B", "300", "z"],
+            ["4", "", "", "400", "w"],
+        ];
+        assert_rows(&expect, &result);
+    }
+
+    #[test]
+    fn join_full() {
+        let result = make_master().join(&make_transaction(), &["id"], JoinKind::Full).unwrap();
+
+        let expect = [
+            ["1", "いるかねこ", "A", "100", "x"],
+            ["1", "いるかねこ", "A", "200", "y"],
+            ["2", "船長うさぎ", "B", "300", "z"],
+            ["3", "やかまし", "C", "", ""],
+            ["4", "", "", "400", "w"],
+        ];
+        assert_rows(&expect, &result);
+    }
+
+    #[test]
+    fn join_multi_key() {
+        let left = make_file(&["id", "date", "name"], &[
+            ["1", "0101", "いるかねこ"],
+            ["1", "0102", "船長うさぎ"],
+        ]);
+        let right = make_file(&["id", "date", "amount"], &[
+            ["1", "0102", "300"],
+        ]);
+
+        let result = left.join(&right, &["id", "date"], JoinKind::Inner).unwrap();
+
+        assert_rows(&[["1", "0102", "船長うさぎ", "300"]], &result);
+    }
+
+    #[test]
+    fn join_error_header_name() {
+        match make_master().join(&make_transaction(), &["name"], JoinKind::Inner) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("結合に失敗しました。[存在しないヘッダー名です。[name]]", e),
+        }
+    }
+
+    #[test]
+    fn join_error_duplicate_header_name() {
+        let left = make_file(&["id", "note", "note_left"], &[["1", "A", "B"]]);
+        let right = make_file(&["id", "note"], &[["1", "C"]]);
+
+        match left.join(&right, &["id"], JoinKind::Inner) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("結合に失敗しました。[結合後のヘッダー名が重複しています。[note_left]]", e),
+        }
+    }
+
+    #[test]
+    fn join_short_row() {
+        // 項目が足りない行は空文字で補い、列がずれない
+        let left = read_from("name,id,note\nいるかねこ,1\n船長うさぎ,2,B\nやかまし,3\n".as_bytes(), &CsvDialect::default()).unwrap();
+        let right = read_from("id,amount,note\n1\n2,300,z\n4,400\n".as_bytes(), &CsvDialect::default()).unwrap();
+
+        let result = left.join(&right, &["id"], JoinKind::Full).unwrap();
+
+        let expect = [
+            ["いるかねこ", "1", "", "", ""],
+            ["船長うさぎ", "2", "B", "300", "z"],
+            ["やかまし", "3", "", "", ""],
+            ["", "4", "", "400", ""],
+        ];
+        assert_rows(&expect, &result);
+    }
+
+    fn assert_rows<const N: usize>(expect: &[[&str; N]], file: &CsvFile) {
+        let header = file.get_header();
+        assert_eq!(expect.len(), file.get_body().len());
+        for (row_index, values) in expect.iter().enumerate() {
+            for (index, value) in values.iter().enumerate() {
+                assert_eq!(*value, file.get_value(header.get_name(index).unwrap(), row_index).unwrap());
+            }
+        }
+    }
+
+    fn make_master() -> CsvFile {
+        make_file(&["id", "name", "note"], &[
+            ["1", "いるかねこ", "A"],
+            ["2", "船長うさぎ", "B"],
+            ["3", "やかまし", "C"],
+        ])
+    }
+
+    fn make_transaction() -> CsvFile {
+        make_file(&["id", "amount", "note"], &[
+            ["1", "100", "x"],
+            ["2", "300", "z"],
+            ["1", "200", "y"],
+            ["4", "400", "w"],
+        ])
+    }
+
+    fn make_file<const N: usize>(header_names: &[&str], rows: &[[&str; N]]) -> CsvFile {
+        CsvFile::from_rows(
+            header_names.iter().map(|name| name.to_string()).collect(),
+            rows.iter().map(|row| row.iter().map(|value| value.to_string()).collect()).collect(),
+        )
+    }
+}
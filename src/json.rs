@@ -0,0 +1,654 @@
+// パッチやJSON変換で使用するJSONの読み書き
+// オブジェクトはキーの順序を保持する
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufWriter, Write};
+
+use crate::schema::{parse_bool, ColumnType, CsvSchema};
+use crate::{CsvFile, CsvRow};
+
+// 配列とオブジェクトの入れ子の最大の深さ(スタックの溢れを防ぐ)
+const MAX_DEPTH: usize = 128;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum JsonValue {
+    Null,
+    Bool(bool),
+    // 数値は元の表記のまま保持する
+    Number(String),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+impl JsonValue {
+    pub(crate) fn parse(text: &str) -> Result<JsonValue, String> {
+        let mut parser = Parser {chars: text.chars().collect(), position: 0, depth: 0};
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.position < parser.chars.len() {
+            return Err(format!("JSONの解析に失敗しました。[不正な文字があります。位置=[{}]]", parser.position));
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members.iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_usize(&self) -> Option<usize> {
+        match self {
+            JsonValue::Number(value) => value.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        let mut text = String::new();
+        self.write_json(&mut text);
+        text
+    }
+
+    fn write_json(&self, text: &mut String) {
+        match self {
+            JsonValue::Null => text.push_str("null"),
+            JsonValue::Bool(value) => text.push_str(if *value { "true" } else { "false" }),
+            JsonValue::Number(value) => text.push_str(value),
+            JsonValue::String(value) => write_string(value, text),
+            JsonValue::Array(values) => {
+                text.push('[');
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        text.push(',');
+                    }
+                    value.write_json(text);
+                }
+                text.push(']');
+            }
+            JsonValue::Object(members) => {
+                text.push('{');
+                for (index, (name, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        text.push(',');
+                    }
+                    write_string(name, text);
+                    text.push(':');
+                    value.write_json(text);
+                }
+                text.push('}');
+            }
+        }
+    }
+}
+
+fn write_string(value: &str, text: &mut String) {
+    text.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            c if (c as u32) < 0x20 => text.push_str(&format!("\\u{:04x}", c as u32)),
+            c => text.push(c),
+        }
+    }
+    text.push('"');
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    // 解析中の配列とオブジェクトの深さ
+    depth: usize,
+}
+impl Parser {
+    fn error(&self, message: &str) -> String {
+        format!("JSONの解析に失敗しました。[{}。位置=[{}]]", message, self.position)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.chars.get(self.position) {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.chars.get(self.position) == Some(&expected) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("{}がありません", expected)))
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, String> {
+        self.skip_whitespace();
+        match self.chars.get(self.position) {
+            Some('{') => self.nested(Self::parse_object),
+            Some('[') => self.nested(Self::parse_array),
+            Some('"') => Ok(JsonValue::String(self.parse_string()?)),
+            Some('t') => self.parse_literal("true", JsonValue::Bool(true)),
+            Some('f') => self.parse_literal("false", JsonValue::Bool(false)),
+            Some('n') => self.parse_literal("null", JsonValue::Null),
+            Some(c) if *c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(_) => Err(self.error("不正な文字があります")),
+            None => Err(self.error("値がありません")),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<JsonValue, String>) -> Result<JsonValue, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(self.error(&format!("入れ子が深すぎます。最大=[{}]", MAX_DEPTH)));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: JsonValue) -> Result<JsonValue, String> {
+        for expected in literal.chars() {
+            if self.chars.get(self.position) != Some(&expected) {
+                return Err(self.error("不正な文字があります"));
+            }
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, String> {
+        let start = self.position;
+        while let Some(c) = self.chars.get(self.position) {
+            if !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                break;
+            }
+            self.position += 1;
+        }
+        let number: String = self.chars[start..self.position].iter().collect();
+        if number.parse::<f64>().is_err() {
+            return Err(self.error("不正な数値です"));
+        }
+        Ok(JsonValue::Number(number))
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            let c = match self.chars.get(self.position) {
+                Some(c) => *c,
+                None => return Err(self.error("文字列が閉じられていません")),
+            };
+            self.position += 1;
+            match c {
+                '"' => return Ok(value),
+                '\\' => {
+                    let escaped = match self.chars.get(self.position) {
+                        Some(c) => *c,
+                        None => return Err(self.error("文字列が閉じられていません")),
+                    };
+                    self.position += 1;
+                    match escaped {
+                        '"' => value.push('"'),
+                        '\\' => value.push('\\'),
+                        '/' => value.push('/'),
+                        'b' => value.push('\u{8}'),
+                        'f' => value.push('\u{c}'),
+                        'n' => value.push('\n'),
+                        'r' => value.push('\r'),
+                        't' => value.push('\t'),
+                        'u' => value.push(self.parse_unicode_escape()?),
+                        _ => return Err(self.error("不正なエスケープです")),
+                    }
+                }
+                c => value.push(c),
+            }
+        }
+    }
+
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.parse_hex4()?;
+        // サロゲートペアの場合は続く\uXXXXと組み合わせる
+        if (0xD800..0xDC00).contains(&high) {
+            if self.chars.get(self.position) == Some(&'\\') && self.chars.get(self.position + 1) == Some(&'u') {
+                self.position += 2;
+                let low = self.parse_hex4()?;
+                if !(0xDC00..0xE000).contains(&low) {
+                    return Err(self.error("不正なエスケープです"));
+                }
+                let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                return char::from_u32(code).ok_or_else(|| self.error("不正なエスケープです"));
+            }
+            return Err(self.error("不正なエスケープです"));
+        }
+        char::from_u32(high).ok_or_else(|| self.error("不正なエスケープです"))
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        if self.position + 4 > self.chars.len() {
+            return Err(self.error("不正なエスケープです"));
+        }
+        let hex: String = self.chars[self.position..self.position + 4].iter().collect();
+        self.position += 4;
+        u32::from_str_radix(&hex, 16).map_err(|_| self.error("不正なエスケープです"))
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.chars.get(self.position) == Some(&']') {
+            self.position += 1;
+            return Ok(JsonValue::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.chars.get(self.position) {
+                Some(',') => self.position += 1,
+                Some(']') => {
+                    self.position += 1;
+                    return Ok(JsonValue::Array(values));
+                }
+                _ => return Err(self.error("]がありません")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.chars.get(self.position) == Some(&'}') {
+            self.position += 1;
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let name = self.parse_string()?;
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            members.push((name, value));
+            self.skip_whitespace();
+            match self.chars.get(self.position) {
+                Some(',') => self.position += 1,
+                Some('}') => {
+                    self.position += 1;
+                    return Ok(JsonValue::Object(members));
+                }
+                _ => return Err(self.error("}がありません")),
+            }
+        }
+    }
+}
+
+
+impl CsvFile {
+    // ヘッダー名をキーとするオブジェクトの配列に変換する
+    // 値はすべて文字列として出力する
+    pub fn to_json(&self) -> String {
+        let rows = self.csv_body.rows.iter()
+            .map(|row| row_to_object(row, None, 0).unwrap_or(JsonValue::Null))
+            .collect();
+        JsonValue::Array(rows).to_json()
+    }
+
+    // スキーマの型に従って値を出力する
+    // 文字列以外の列の空の値はnullとする
+    pub fn to_json_with(&self, schema: &CsvSchema) -> Result<String, String> {
+        let mut rows = Vec::with_capacity(self.csv_body.rows.len());
+        for (index, row) in self.csv_body.rows.iter().enumerate() {
+            rows.push(row_to_object(row, Some(schema), index)?);
+        }
+        Ok(JsonValue::Array(rows).to_json())
+    }
+
+    // 1行を1つのオブジェクトとして、行ごとに書き込む
+    pub fn to_ndjson<W: Write>(&self, writer: W) -> Result<(), String> {
+        self.write_ndjson(writer, None)
+    }
+
+    pub fn to_ndjson_with<W: Write>(&self, writer: W, schema: &CsvSchema) -> Result<(), String> {
+        self.write_ndjson(writer, Some(schema))
+    }
+
+    fn write_ndjson<W: Write>(&self, writer: W, schema: Option<&CsvSchema>) -> Result<(), String> {
+        let mut writer = BufWriter::new(writer);
+        for (index, row) in self.csv_body.rows.iter().enumerate() {
+            let mut line = row_to_object(row, schema, index)?.to_json();
+            line.push('\n');
+            if let Err(e) = writer.write_all(line.as_bytes()) {
+                return Err(format!("writeに失敗しました。[{}]", e));
+            }
+        }
+        if let Err(e) = writer.flush() {
+            return Err(format!("flushに失敗しました。[{}]", e));
+        }
+        Ok(())
+    }
+
+    // オブジェクトの配列から作成する
+    // 入れ子のオブジェクトと配列は"."でつないだキーの列に展開する
+    pub fn from_json(text: &str) -> Result<CsvFile, String> {
+        let values = match JsonValue::parse(text) {
+            Ok(JsonValue::Array(values)) => values,
+            Ok(_) => return Err(String::from("JSONからの変換に失敗しました。[配列ではありません。]")),
+            Err(e) => return Err(format!("JSONからの変換に失敗しました。[{}]", e)),
+        };
+        let mut columns = Columns::new();
+        for (index, value) in values.iter().enumerate() {
+            columns.push(value, index)?;
+        }
+        Ok(columns.into_file())
+    }
+
+    // 1行に1つのオブジェクトが書かれたデータから作成する
+    // 空行は読み飛ばす
+    pub fn from_ndjson<R: BufRead>(reader: R) -> Result<CsvFile, String> {
+        let mut columns = Columns::new();
+        let mut index = 0;
+        for line in reader.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => return Err(format!("readに失敗しました。[{}]", e)),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let value = match JsonValue::parse(&line) {
+                Ok(value) => value,
+                Err(e) => return Err(format!("JSONからの変換に失敗しました。行=[{}], [{}]", index, e)),
+            };
+            columns.push(&value, index)?;
+            index += 1;
+        }
+        Ok(columns.into_file())
+    }
+}
+
+fn row_to_object(row: &CsvRow, schema: Option<&CsvSchema>, index: usize) -> Result<JsonValue, String> {
+    let mut members = Vec::with_capacity(row.data.len());
+    for csv_data in &row.data {
+        let column_type = match schema {
+            Some(schema) => schema.get(&csv_data.header_name),
+            None => ColumnType::String,
+        };
+        let value = match typed_value(&csv_data.value, column_type) {
+            Some(value) => value,
+            None => return Err(format!("JSONへの変換に失敗しました。[{}に変換できません。行=[{}], 列=[{}], 値=[{}]]",
+                                       column_type.name(), index, csv_data.header_name, csv_data.value)),
+        };
+        members.push((csv_data.header_name.clone(), value));
+    }
+    Ok(JsonValue::Object(members))
+}
+
+fn typed_value(value: &str, column_type: ColumnType) -> Option<JsonValue> {
+    if value.is_empty() && column_type != ColumnType::String {
+        return Some(JsonValue::Null);
+    }
+    match column_type {
+        ColumnType::String => Some(JsonValue::String(value.to_string())),
+        ColumnType::Integer => value.parse::<i64>().ok()
+            .map(|number| JsonValue::Number(number.to_string())),
+        ColumnType::Float => value.parse::<f64>().ok()
+            .filter(|number| number.is_finite())
+            .map(|number| JsonValue::Number(number.to_string())),
+        ColumnType::Boolean => parse_bool(value).map(JsonValue::Bool),
+    }
+}
+
+// 展開した列を出現順に保持する
+struct Columns {
+    names: Vec<String>,
+    indexes: HashMap<String, usize>,
+    rows: Vec<Vec<String>>,
+}
+impl Columns {
+    fn new() -> Self {
+        Self {names: Vec::new(), indexes: HashMap::new(), rows: Vec::new()}
+    }
+
+    fn push(&mut self, value: &JsonValue, index: usize) -> Result<(), String> {
+        if !matches!(value, JsonValue::Object(_)) {
+            return Err(format!("JSONからの変換に失敗しました。行=[{}], [オブジェクトではありません。]", index));
+        }
+        let mut cells = Vec::new();
+        flatten("", value, &mut cells);
+
+        let mut row = vec![String::new(); self.names.len()];
+        let mut filled = vec![false; self.names.len()];
+        for (name, cell) in cells {
+            let column = match self.indexes.get(&name) {
+                Some(column) => *column,
+                None => {
+                    self.indexes.insert(name.clone(), self.names.len());
+                    self.names.push(name.clone());
+                    row.push(String::new());
+                    filled.push(false);
+                    self.names.len() - 1
+                }
+            };
+            if filled[column] {
+                return Err(format!("JSONからの変換に失敗しました。行=[{}], [列名が重複しています。[{}]]", index, name));
+            }
+            row[column] = cell;
+            filled[column] = true;
+        }
+        self.rows.push(row);
+        Ok(())
+    }
+
+    fn into_file(self) -> CsvFile {
+        // 後から追加された列は前の行では空とする
+        let width = self.names.len();
+        let rows = self.rows.into_iter()
+            .map(|mut row| {
+                row.resize(width, String::new());
+                row
+            })
+            .collect();
+        CsvFile::from_rows(self.names, rows)
+    }
+}
+
+fn flatten(prefix: &str, value: &JsonValue, cells: &mut Vec<(String, String)>) {
+    let join = |key: &str| if prefix.is_empty() { key.to_string() } else { format!("{}.{}", prefix, key) };
+    match value {
+        JsonValue::Object(members) => {
+            for (key, value) in members {
+                flatten(&join(key), value, cells);
+            }
+        }
+        JsonValue::Array(values) => {
+            for (index, value) in values.iter().enumerate() {
+                flatten(&join(&index.to_string()), value, cells);
+            }
+        }
+        JsonValue::Null => cells.push((prefix.to_string(), String::new())),
+        JsonValue::Bool(value) => cells.push((prefix.to_string(), value.to_string())),
+        JsonValue::Number(value) => cells.push((prefix.to_string(), value.clone())),
+        JsonValue::String(value) => cells.push((prefix.to_string(), value.clone())),
+    }
+}
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_parse() {
+        let value = JsonValue::parse(r#" {"a": [1, -2.5e3, true, null], "b": "い\"う😀"} "#).unwrap();
+
+        assert_eq!(JsonValue::Object(vec![
+            (String::from("a"), JsonValue::Array(vec![
+                JsonValue::Number(String::from("1")),
+                JsonValue::Number(String::from("-2.5e3")),
+                JsonValue::Bool(true),
+                JsonValue::Null,
+            ])),
+            (String::from("b"), JsonValue::String(String::from("い\"う😀"))),
+        ]), value);
+    }
+
+    #[test]
+    fn json_to_json() {
+        let value = JsonValue::Object(vec![
+            (String::from("b"), JsonValue::String(String::from("改行\n\"引用\""))),
+            (String::from("a"), JsonValue::Array(vec![JsonValue::Number(String::from("10")), JsonValue::Null])),
+        ]);
+
+        assert_eq!(r#"{"b":"改行\n\"引用\"","a":[10,null]}"#, value.to_json());
+        assert_eq!(value, JsonValue::parse(&value.to_json()).unwrap());
+    }
+
+    #[test]
+    fn json_parse_error() {
+        match JsonValue::parse(r#"{"a": [1, 2}"#) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("JSONの解析に失敗しました。[]がありません。位置=[11]]", e),
+        }
+        // 下位サロゲートでない値は組み合わせない
+        match JsonValue::parse(r#""\ud83d\u0041""#) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("JSONの解析に失敗しました。[不正なエスケープです。位置=[13]]", e),
+        }
+        assert_eq!(JsonValue::String(String::from("🐱")), JsonValue::parse(r#""\ud83d\udc31""#).unwrap());
+    }
+
+    #[test]
+    fn json_parse_depth() {
+        let nested = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(JsonValue::parse(&nested).is_ok());
+
+        let text = "[".repeat(100_000);
+        match JsonValue::parse(&text) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("JSONの解析に失敗しました。[入れ子が深すぎます。最大=[128]。位置=[128]]", e),
+        }
+    }
+
+    #[test]
+    fn csv_file_to_json() {
+        let csv = make_file();
+
+        assert_eq!(r#"[{"id":"1","名前":"いるかねこ","金額":"100","有効":"true"},{"id":"2","名前":"船長\"うさぎ\"","金額":"","有効":"0"}]"#,
+                   csv.to_json());
+    }
+
+    #[test]
+    fn csv_file_to_json_with_schema() {
+        let csv = make_file();
+        let mut schema = CsvSchema::new();
+        schema.push("id", ColumnType::Integer);
+        schema.push("金額", ColumnType::Float);
+        schema.push("有効", ColumnType::Boolean);
+
+        assert_eq!(r#"[{"id":1,"名前":"いるかねこ","金額":100,"有効":true},{"id":2,"名前":"船長\"うさぎ\"","金額":null,"有効":false}]"#,
+                   csv.to_json_with(&schema).unwrap());
+    }
+
+    #[test]
+    fn csv_file_to_json_with_schema_error() {
+        let csv = make_file();
+        let mut schema = CsvSchema::new();
+        schema.push("名前", ColumnType::Integer);
+
+        match csv.to_json_with(&schema) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("JSONへの変換に失敗しました。[整数に変換できません。行=[0], 列=[名前], 値=[いるかねこ]]", e),
+        }
+    }
+
+    #[test]
+    fn csv_file_to_ndjson() {
+        let csv = make_file();
+        let mut schema = CsvSchema::new();
+        schema.push("id", ColumnType::Integer);
+
+        let mut output = Vec::new();
+        csv.to_ndjson_with(&mut output, &schema).unwrap();
+
+        assert_eq!(concat!(
+            r#"{"id":1,"名前":"いるかねこ","金額":"100","有効":"true"}"#, "\n",
+            r#"{"id":2,"名前":"船長\"うさぎ\"","金額":"","有効":"0"}"#, "\n",
+        ), String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn csv_file_from_json() {
+        let csv = CsvFile::from_json(r#"[
+            {"id": 1, "名前": "いるかねこ", "住所": {"県": "東京", "市": null}, "タグ": ["a", "b"]},
+            {"id": 2, "名前": "船長うさぎ", "有効": false}
+        ]"#).unwrap();
+
+        assert_eq!(vec!["id", "名前", "住所.県", "住所.市", "タグ.0", "タグ.1", "有効"], csv.get_header().name);
+        assert_eq!(vec!["1", "いるかねこ", "東京", "", "a", "b", ""], csv.get_body().get_row(0).unwrap().values());
+        assert_eq!(vec!["2", "船長うさぎ", "", "", "", "", "false"], csv.get_body().get_row(1).unwrap().values());
+    }
+
+    #[test]
+    fn csv_file_from_ndjson_round_trip() {
+        let csv = make_file();
+        let mut output = Vec::new();
+        csv.to_ndjson(&mut output).unwrap();
+        output.extend_from_slice(b"\n");
+
+        let read = CsvFile::from_ndjson(output.as_slice()).unwrap();
+
+        assert_eq!(csv.to_json(), read.to_json());
+    }
+
+    #[test]
+    fn csv_file_from_json_error() {
+        match CsvFile::from_json(r#"{"id": 1}"#) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("JSONからの変換に失敗しました。[配列ではありません。]", e),
+        }
+        match CsvFile::from_json(r#"[{"a": {"b": 1}, "a.b": 2}]"#) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("JSONからの変換に失敗しました。行=[0], [列名が重複しています。[a.b]]", e),
+        }
+        match CsvFile::from_ndjson("{\"id\": 1}\n[1]\n".as_bytes()) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("JSONからの変換に失敗しました。行=[1], [オブジェクトではありません。]", e),
+        }
+    }
+
+    fn make_file() -> CsvFile {
+        CsvFile::from_rows(
+            vec![String::from("id"), String::from("名前"), String::from("金額"), String::from("有効")],
+            vec![
+                vec![String::from("1"), String::from("いるかねこ"), String::from("100"), String::from("true")],
+                vec![String::from("2"), String::from("船長\"うさぎ\""), String::new(), String::from("0")],
+            ],
+        )
+    }
+}
@@ -1,66 +1,171 @@
+use std::borrow::Cow;
 use std::fs;
 use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::io::{BufReader, BufWriter, Read, Write};
+
+mod append;
+#[cfg(feature = "tokio")]
+mod async_io;
+mod compression;
+mod dedup;
+mod dialect;
+mod diff;
+mod error;
+mod excel;
+mod fixed_width;
+mod group;
+mod join;
+mod json;
+mod lock;
+#[cfg(feature = "mmap")]
+mod mapped;
+mod parallel;
+mod patch;
+#[cfg(feature = "parquet")]
+mod parquet_file;
+#[cfg(feature = "sqlite")]
+mod query;
+mod read_options;
+#[cfg(feature = "arrow")]
+mod record_batch;
+mod row_index;
+mod sanitize;
+mod schema;
+mod sort;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod table;
+mod width;
+
+use dedup::PrimaryKey;
+use dialect::{format_record, split_record, RawRecord, RecordReader};
+use error::RecordPosition;
+
+pub use append::{append, append_with, AppendOptions};
+#[cfg(feature = "tokio")]
+pub use async_io::{AsyncCsvReader, AsyncCsvWriter};
+pub use compression::Compression;
+pub use dedup::Keep;
+pub use dialect::CsvDialect;
+pub use diff::{diff, CellChange, CsvDiff, RowChange};
+pub use error::ParseError;
+pub use excel::{write_excel, write_excel_to, ExcelEncoding, ExcelOptions};
+pub use fixed_width::{read_fixed_width, read_fixed_width_from, write_fixed_width, write_fixed_width_to, Align, FixedWidthColumn, FixedWidthLayout};
+pub use group::{avg, count, max, min, sum, Aggregation, GroupBy};
+pub use join::{JoinKind, JoinOptions};
+pub use lock::{read_locked, write_locked, LockOptions};
+#[cfg(feature = "mmap")]
+pub use mapped::MappedCsvFile;
+pub use parallel::{read_parallel, read_parallel_from, read_parallel_from_with};
+pub use patch::{CsvPatch, PatchOperation, RowTarget};
+#[cfg(feature = "parquet")]
+pub use parquet_file::{read_parquet, write_parquet, write_parquet_with};
+#[cfg(feature = "sqlite")]
+pub use query::{query, CsvQuery};
+pub use read_options::{read_from_with_options, read_with_options, ErrorPolicy, RaggedPolicy, ReadOptions, ReadReport, RejectedRecord, Repair, RowRepair};
+pub use row_index::{IndexedReader, RowIndex};
+pub use sanitize::{write_sanitized, write_sanitized_to, FormulaPolicy, SanitizeOptions, SanitizeReport};
+pub use schema::{ColumnType, CsvSchema};
+pub use sort::{sort_file, sort_file_with, SortCompare, SortOrder};
+pub use table::{TableOptions, TableStyle};
 
 pub fn read(path: &str) -> Result<CsvFile, String> {
+    read_with(path, &CsvDialect::default())
+}
+
+// 圧縮されたファイルは先頭のバイト列から形式を判定して展開する
+pub fn read_with(path: &str, dialect: &CsvDialect) -> Result<CsvFile, String> {
     // ファイルを比較
     let file = match File::open(path) {
         Ok(file) => file,
         Err(e) => return Err(format!("openに失敗しました。{}", e)),
     };
 
-    // 1行ごとにファイルを読み込み、CsvFileを作成する
-    let buffer = BufReader::new(file);
+    let mut reader = BufReader::new(file);
+    let compression = compression::detect(&mut reader)?;
+    read_from(compression::decoder(reader, compression)?, dialect)
+}
+
+pub fn read_with_compression(path: &str, dialect: &CsvDialect, compression: Compression) -> Result<CsvFile, String> {
+    compression::check(compression)?;
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("openに失敗しました。{}", e)),
+    };
+
+    read_from(compression::decoder(BufReader::new(file), compression)?, dialect)
+}
+
+pub fn read_from<R: Read>(reader: R, dialect: &CsvDialect) -> Result<CsvFile, String> {
+    dialect.validate()?;
+    Ok(try_read_from(reader, dialect)?)
+}
+
+// エラーの発生位置(行番号、列番号、レコード番号、バイト位置、項目の番号)が必要な場合に使用する
+pub fn try_read_from<R: Read>(reader: R, dialect: &CsvDialect) -> Result<CsvFile, ParseError> {
+    if let Err(e) = dialect.validate() {
+        return Err(ParseError::new(&e, "", RecordPosition::default(), None, dialect));
+    }
+
+    // 1レコードごとに読み込み、CsvFileを作成する
+    let mut records = RecordReader::new(BufReader::new(reader), dialect);
     let mut csv_header = CsvHeader::new();
     let mut csv_body = CsvBody::new();
-    for (index, line) in buffer.lines().enumerate() {
-        // 最初の一行目はヘッダにする
-        if index == 0 {
-            for data in line.unwrap().split(",") {
-                csv_header.append(data);
-            }
+    while let Some(record) = records.next_record() {
+        let record = record?;
+
+        // 最初のレコードはヘッダにする
+        if record.position.record == 0 {
+            csv_header = parse_header(&record, dialect)?;
             continue;
         }
 
-        // 2行目以降はデータにする
-        let mut csv_row = CsvRow::new();
-        for (index, data) in line.unwrap().split(",").enumerate() {
-            let csv_data = CsvData::new(
-                csv_header.get_name(index)?,
-                data
-            );
-            csv_row.append(csv_data);
-        }
-        csv_body.append(csv_row);
+        // 2レコード目以降はデータにする
+        csv_body.append(parse_row(&csv_header, &record, dialect)?);
     }
 
     Ok(CsvFile::new(csv_header, csv_body))
 }
 
-pub fn write(path: &str, csv_file: &CsvFile) -> Result<(), String> {
-    // 書き込みデータを生成する
-    let mut data: Vec<u8> = Vec::new();
-    // ヘッダーの処理
-    for name in &csv_file.csv_header.name {
-        data.extend(name.as_bytes());
-        data.push(',' as u8);
+fn parse_header(record: &RawRecord, dialect: &CsvDialect) -> Result<CsvHeader, ParseError> {
+    let mut csv_header = CsvHeader::new();
+    for name in split_record(&record.text, dialect).map_err(|e| e.at(record, dialect))? {
+        csv_header.append(&name);
     }
-    // 末尾の,は削除する
-    data.pop();
-    // 改行コードを挿入する
-    data.push('\n' as u8);
+    Ok(csv_header)
+}
 
-    // データの処理
-    for row in &csv_file.csv_body.rows {
-        for csv_data in &row.data {
-            data.extend(csv_data.value.as_bytes());
-            data.push(',' as u8);
-        }
-        // 末尾の,は削除する
-        data.pop();
-        // 改行コードを挿入する
-        data.push('\n' as u8);
-    }
+// 項目数がヘッダーより多い場合は、ヘッダーにない最初の項目の位置をエラーとする
+fn parse_row(csv_header: &CsvHeader, record: &RawRecord, dialect: &CsvDialect) -> Result<CsvRow, ParseError> {
+    let fields = split_record(&record.text, dialect).map_err(|e| e.at(record, dialect))?;
+    build_csv_row(csv_header, &fields).map_err(|e| record.error(&e, Some(csv_header.len()), dialect))
+}
+
+// 分割した項目からヘッダー名付きの行を作成する
+fn build_csv_row(csv_header: &CsvHeader, fields: &[Cow<'_, str>]) -> Result<CsvRow, String> {
+    let mut csv_row = CsvRow::new();
+    for (index, data) in fields.iter().enumerate() {
+        let csv_data = CsvData::new(
+            csv_header.get_name(index)?,
+            data
+        );
+        csv_row.append(csv_data);
+    }
+    Ok(csv_row)
+}
+
+pub fn write(path: &str, csv_file: &CsvFile) -> Result<(), String> {
+    write_with(path, csv_file, &CsvDialect::default())
+}
+
+// 拡張子が.gz, .zst, .bz2の場合は圧縮して書き込む
+pub fn write_with(path: &str, csv_file: &CsvFile, dialect: &CsvDialect) -> Result<(), String> {
+    write_with_compression(path, csv_file, dialect, Compression::from_path(path))
+}
+
+pub fn write_with_compression(path: &str, csv_file: &CsvFile, dialect: &CsvDialect, compression: Compression)
+    -> Result<(), String> {
+    compression::check(compression)?;
 
     // 書き込み処理
     // 対象のファイルがあるかを検証する
@@ -75,16 +180,36 @@ pub fn write(path: &str, csv_file: &CsvFile) -> Result<(), String> {
             Err(e) => return Err(format!("createに失敗しました。[{}]", e)),
         }
     } else {
-        // ファイルが存在した場合、そのファイルを開く
-        match OpenOptions::new().write(true).open(path) {
+        // ファイルが存在した場合、そのファイルを開いて内容を消去する
+        match OpenOptions::new().write(true).truncate(true).open(path) {
             Ok(value) => value,
             Err(e) => return Err(format!("openに失敗しました。[{}]", e)),
         }
     };
 
-    let mut writer = BufWriter::new(file);
+    compression::encode(file, compression, |writer| write_to(writer, csv_file, dialect))
+}
+
+pub fn write_to<W: Write>(writer: W, csv_file: &CsvFile, dialect: &CsvDialect) -> Result<(), String> {
+    dialect.validate()?;
+
+    // 書き込みデータを生成する
+    let mut data = String::new();
+    // ヘッダーの処理
+    data.push_str(&format_record(csv_file.csv_header.name.iter().map(|name| name.as_str()), dialect));
+    // 改行コードを挿入する
+    data.push('\n');
+
+    // データの処理
+    for row in &csv_file.csv_body.rows {
+        data.push_str(&format_record(row.data.iter().map(|csv_data| csv_data.value.as_str()), dialect));
+        // 改行コードを挿入する
+        data.push('\n');
+    }
 
-    if let Err(e) = writer.write(&data) {
+    let mut writer = BufWriter::new(writer);
+
+    if let Err(e) = writer.write_all(data.as_bytes()) {
         return Err(format!("writeに失敗しました。[{}]", e));
     }
     if let Err(e) = writer.flush() {
@@ -94,18 +219,40 @@ pub fn write(path: &str, csv_file: &CsvFile) -> Result<(), String> {
     Ok(())
 }
 
+#[derive(Clone)]
 pub struct CsvFile {
     csv_header: CsvHeader,
     csv_body: CsvBody,
+    primary_key: Option<PrimaryKey>,
 }
 impl CsvFile {
     fn new(csv_header: CsvHeader, csv_body: CsvBody) -> Self {
         Self {
             csv_header,
             csv_body,
+            primary_key: None,
         }
     }
 
+    // ヘッダー名と各行の値からCsvFileを作成する
+    pub fn from_rows(header_names: Vec<String>, rows: Vec<Vec<String>>) -> Self {
+        let mut csv_header = CsvHeader::new();
+        for name in &header_names {
+            csv_header.append(name);
+        }
+
+        let mut csv_body = CsvBody::new();
+        for values in rows {
+            let mut csv_row = CsvRow::new();
+            for (name, value) in header_names.iter().zip(values.iter()) {
+                csv_row.append(CsvData::new(name, value));
+            }
+            csv_body.append(csv_row);
+        }
+
+        Self::new(csv_header, csv_body)
+    }
+
     pub fn get_value(&self, header_name: &str, row_index: usize) -> Result<String, String> {
         match self.csv_body.get_row(row_index) {
             Ok(value) => {
@@ -126,6 +273,11 @@ impl CsvFile {
         self.csv_body.clone()
     }
 
+    // 行を参照する(get_bodyと異なりコピーしない)
+    pub fn rows(&self) -> &[CsvRow] {
+        &self.csv_body.rows
+    }
+
     fn build_row(&self, data: Vec<String>) -> Result<CsvRow, String> {
         let mut row = CsvRow::new();
         for (index, header_name) in self.csv_header.name.iter().enumerate() {
@@ -147,13 +299,33 @@ impl CsvFile {
 
         match self.build_row(data) {
             Ok(row) => {
+                if let Err(e) = self.check_primary_key(&row, None) {
+                    return Err(format!("行の追加に失敗しました。[{}]", e));
+                }
                 self.csv_body.rows.push(row);
+                self.index_primary_key(self.csv_body.rows.len() - 1);
                 Ok(())
             }
             Err(e) => Err(format!("行の追加に失敗しました。[{}]", e))
         }
     }
 
+    // ヘッダーが同じ別のCsvFileの行を末尾に追加する
+    // appendと異なり、項目が足りない行もそのまま追加する
+    pub fn extend(&mut self, other: CsvFile) -> Result<(), String> {
+        if self.csv_header.name != other.csv_header.name {
+            return Err(String::from("行の追加に失敗しました。[ヘッダーが一致しません。]"));
+        }
+        for row in other.csv_body.rows {
+            if let Err(e) = self.check_primary_key(&row, None) {
+                return Err(format!("行の追加に失敗しました。[{}]", e));
+            }
+            self.csv_body.rows.push(row);
+            self.index_primary_key(self.csv_body.rows.len() - 1);
+        }
+        Ok(())
+    }
+
     pub fn insert(&mut self, index: usize, data: Vec<String>) -> Result<(), String> {
         if data.len() != self.csv_header.len() {
             return Err(format!("行のサイズが不正です。必要数=[{}], 渡した数=[{}]"
@@ -166,7 +338,11 @@ impl CsvFile {
 
         match self.build_row(data) {
             Ok(row) => {
+                if let Err(e) = self.check_primary_key(&row, None) {
+                    return Err(format!("行の追加に失敗しました。[{}]", e));
+                }
                 self.csv_body.rows.insert(index, row);
+                self.rebuild_primary_key();
                 Ok(())
             }
             Err(e) => Err(format!("行の追加に失敗しました。[{}]", e))
@@ -185,14 +361,64 @@ impl CsvFile {
 
         match self.build_row(data) {
             Ok(row) => {
-                self.csv_body.rows.remove(index);
+                if let Err(e) = self.check_primary_key(&row, Some(index)) {
+                    return Err(format!("行の追加に失敗しました。[{}]", e));
+                }
+                let old_row = self.csv_body.rows.remove(index);
                 self.csv_body.rows.insert(index, row);
+                self.update_primary_key(&old_row, index);
                 Ok(())
             }
             Err(e) => Err(format!("行の追加に失敗しました。[{}]", e))
         }
     }
 
+    // 指定した列だけのCsvFileを作成する
+    pub fn select(&self, header_names: &[&str]) -> Result<CsvFile, String> {
+        for header_name in header_names {
+            if !self.csv_header.name.iter().any(|name| name == header_name) {
+                return Err(format!("列の選択に失敗しました。[存在しないヘッダー名です。[{}]]", header_name));
+            }
+        }
+
+        let mut rows = Vec::with_capacity(self.csv_body.len());
+        for row in &self.csv_body.rows {
+            let mut values = Vec::with_capacity(header_names.len());
+            for header_name in header_names {
+                values.push(row.get_value(header_name)?);
+            }
+            rows.push(values);
+        }
+
+        Ok(CsvFile::from_rows(header_names.iter().map(|name| name.to_string()).collect(), rows))
+    }
+
+    // 条件に一致する行だけのCsvFileを作成する
+    pub fn filter<F>(&self, predicate: F) -> CsvFile
+    where
+        F: Fn(&CsvRow) -> bool,
+    {
+        let mut csv_body = CsvBody::new();
+        for row in &self.csv_body.rows {
+            if predicate(row) {
+                csv_body.append(row.clone());
+            }
+        }
+        CsvFile::new(self.csv_header.clone(), csv_body)
+    }
+
+    // 先頭からcount行のCsvFileを作成する
+    pub fn head(&self, count: usize) -> CsvFile {
+        let rows = &self.csv_body.rows;
+        CsvFile::new(self.csv_header.clone(), CsvBody {rows: rows[..count.min(rows.len())].to_vec()})
+    }
+
+    // 末尾からcount行のCsvFileを作成する
+    pub fn tail(&self, count: usize) -> CsvFile {
+        let rows = &self.csv_body.rows;
+        CsvFile::new(self.csv_header.clone(), CsvBody {rows: rows[rows.len().saturating_sub(count)..].to_vec()})
+    }
+
     pub fn remove(&mut self, index: usize) -> Result<(), String> {
         if index >= self.csv_header.len() {
             return Err(format!("不正なインデックスです。指定したインデックス=[{}], 許容範囲=[{}]"
@@ -200,6 +426,7 @@ impl CsvFile {
         }
 
         self.csv_body.rows.remove(index);
+        self.rebuild_primary_key();
 
         Ok(())
     }
@@ -229,6 +456,10 @@ impl CsvHeader {
     pub fn len(&self) -> usize {
         self.name.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.name.is_empty()
+    }
 }
 
 #[derive(Clone)]
@@ -258,6 +489,10 @@ impl CsvBody {
     pub fn len(&self) -> usize {
         self.rows.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
 }
 
 #[derive(Clone)]
@@ -282,9 +517,23 @@ impl CsvRow {
         Err(format!("存在しないヘッダー名です。[{}]", header_name))
     }
 
+    // 列番号で値を参照する(項目が足りない場合はNone)
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.data.get(index).map(|csv_data| csv_data.value.as_str())
+    }
+
+    // 列の順に値を取得する
+    pub fn values(&self) -> Vec<String> {
+        self.data.iter().map(|csv_data| csv_data.value.clone()).collect()
+    }
+
     pub fn len(&self) -> usize {
         self.data.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
 }
 
 #[derive(Clone)]
@@ -328,6 +577,23 @@ mod tests {
         assert_eq!("海賊うさぎ", row.get_value("ヘッダー2").unwrap());
     }
 
+    #[test]
+    fn csv_file_extend() {
+        let mut csv = read_from("名前,金額\nいるかねこ,100\n".as_bytes(), &CsvDialect::default()).unwrap();
+        let other = read_from("名前,金額\n船長うさぎ\n".as_bytes(), &CsvDialect::default()).unwrap();
+
+        csv.extend(other).unwrap();
+
+        assert_eq!(2, csv.rows().len());
+        assert_eq!(Some("船長うさぎ"), csv.rows()[1].get(0));
+        assert_eq!(None, csv.rows()[1].get(1));
+
+        match csv.extend(read_from("名前\nやかまし\n".as_bytes(), &CsvDialect::default()).unwrap()) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("行の追加に失敗しました。[ヘッダーが一致しません。]", e),
+        }
+    }
+
     #[test]
     fn csv_row_get_value_error() {
         let row = make_csv_row();
@@ -632,6 +898,82 @@ mod tests {
         }
     }
 
+    #[test]
+    fn csv_row_values() {
+        assert_eq!(vec!["いるかねこ", "海賊うさぎ", "やかまし"], make_csv_row().values());
+    }
+
+    #[test]
+    fn csv_file_select() {
+        let file = make_file().select(&["ヘッダー3", "ヘッダー1"]).unwrap();
+
+        assert_eq!("ヘッダー3", file.get_header().get_name(0).unwrap());
+        assert_eq!(vec!["船長メイド", "塩鯱"], file.get_body().get_row(2).unwrap().values());
+    }
+
+    #[test]
+    fn csv_file_select_error() {
+        match make_file().select(&["ヘッダー4"]) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("列の選択に失敗しました。[存在しないヘッダー名です。[ヘッダー4]]", e),
+        }
+    }
+
+    #[test]
+    fn csv_file_filter() {
+        let file = make_file().filter(|row| row.get_value("ヘッダー1").unwrap().starts_with("い"));
+
+        assert_eq!(2, file.get_body().len());
+        assert_eq!("いぬねこ", file.get_value("ヘッダー1", 1).unwrap());
+    }
+
+    #[test]
+    fn csv_file_head_tail() {
+        let file = make_file();
+
+        assert_eq!("いぬねこ", file.head(2).get_value("ヘッダー1", 1).unwrap());
+        assert_eq!(3, file.head(5).get_body().len());
+        assert_eq!("いぬねこ", file.tail(2).get_value("ヘッダー1", 0).unwrap());
+        assert_eq!(0, file.tail(0).get_body().len());
+    }
+
+    #[test]
+    fn read_from_quoted() {
+        let text = "名前,備考\n\"いるか,ねこ\",\"改\n行\"\n";
+        let csv = read_from(text.as_bytes(), &CsvDialect::default()).unwrap();
+
+        assert_eq!("いるか,ねこ", csv.get_value("名前", 0).unwrap());
+        assert_eq!("改\n行", csv.get_value("備考", 0).unwrap());
+    }
+
+    #[test]
+    fn try_read_from_error_position() {
+        let text = "名前,備考\r\n\r\nいるかねこ,\"改\r\n行\"\r\n船長うさぎ,\"閉じられて\"いない\r\n";
+
+        let e = match try_read_from(text.as_bytes(), &CsvDialect::default()) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => e,
+        };
+
+        assert_eq!("囲み文字の後に不正な文字があります。", e.message);
+        assert_eq!((5, 7, 2, 61, Some(1)), (e.line, e.column, e.record, e.offset, e.field));
+        assert_eq!("船長うさぎ,\"閉じられて\"いない", e.snippet);
+        // readのエラーメッセージにも位置を含める
+        match read_from(text.as_bytes(), &CsvDialect::default()) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(message) => assert_eq!(e.to_string(), message),
+        }
+    }
+
+    #[test]
+    fn write_to_tsv() {
+        let mut data: Vec<u8> = Vec::new();
+        write_to(&mut data, &make_file().head(1), &CsvDialect::tsv()).unwrap();
+
+        assert_eq!("ヘッダー1\tヘッダー2\tヘッダー3\nいるかねこ\t海賊うさぎ\tやかまし\n",
+                   String::from_utf8(data).unwrap());
+    }
+
     #[test]
     fn read_csv_header() {
         let csv = read("test/test.csv").unwrap();
@@ -0,0 +1,175 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::BufReader;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::compression::{self, Compression};
+use crate::{read_from, write_to, CsvDialect, CsvFile};
+
+// ファイルロック(アドバイザリロック)の設定
+// ロックしないプロセスからの読み書きは防げない
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LockOptions {
+    // ロックを取得できるまで待つ時間(Noneの場合は取得できるまで待つ)
+    pub timeout: Option<Duration>,
+    // 取得できなかった場合に再試行する間隔
+    pub retry_interval: Duration,
+}
+impl LockOptions {
+    pub fn new() -> Self {
+        Self {timeout: None, retry_interval: Duration::from_millis(10)}
+    }
+
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {timeout: Some(timeout), ..Self::new()}
+    }
+}
+impl Default for LockOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 共有ロックを取得して読み込む
+pub fn read_locked(path: &str, dialect: &CsvDialect, options: &LockOptions) -> Result<CsvFile, String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("openに失敗しました。{}", e)),
+    };
+    lock_shared(&file, path, options)?;
+
+    let mut reader = BufReader::new(&file);
+    let compression = compression::detect(&mut reader)?;
+    read_from(compression::decoder(reader, compression)?, dialect)
+}
+
+// 排他ロックを取得してから内容を消去して書き込む
+// 拡張子が.gz, .zst, .bz2の場合は圧縮して書き込む
+pub fn write_locked(path: &str, csv_file: &CsvFile, dialect: &CsvDialect, options: &LockOptions) -> Result<(), String> {
+    let compression = Compression::from_path(path);
+    compression::check(compression)?;
+
+    // ロックを取得するまでは内容を消去しない
+    let file = match OpenOptions::new().write(true).create(true).truncate(false).open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("openに失敗しました。{}", e)),
+    };
+    lock_exclusive(&file, path, options)?;
+    if let Err(e) = file.set_len(0) {
+        return Err(format!("truncateに失敗しました。[{}]", e));
+    }
+
+    compression::encode(&file, compression, |writer| write_to(writer, csv_file, dialect))
+}
+
+pub(crate) fn lock_shared(file: &File, path: &str, options: &LockOptions) -> Result<(), String> {
+    lock(path, options, || file.lock_shared(), || file.try_lock_shared())
+}
+
+pub(crate) fn lock_exclusive(file: &File, path: &str, options: &LockOptions) -> Result<(), String> {
+    lock(path, options, || file.lock(), || file.try_lock())
+}
+
+fn lock<L, T>(path: &str, options: &LockOptions, lock: L, try_lock: T) -> Result<(), String>
+where
+    L: Fn() -> std::io::Result<()>,
+    T: Fn() -> Result<(), TryLockError>,
+{
+    let timeout = match options.timeout {
+        Some(timeout) => timeout,
+        None => return lock().map_err(|e| format!("lockに失敗しました。[{}]", e)),
+    };
+    let start = Instant::now();
+    loop {
+        match try_lock() {
+            Ok(()) => return Ok(()),
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(e)) => return Err(format!("lockに失敗しました。[{}]", e)),
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            return Err(format!("ロックを取得できませんでした。ほかのプロセスが使用中です。ファイル=[{}], タイムアウト=[{}ms]",
+                               path, timeout.as_millis()));
+        }
+        thread::sleep(options.retry_interval.min(timeout - elapsed));
+    }
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read;
+
+    #[test]
+    fn write_locked_read_locked() {
+        let path = temp_path("round_trip");
+        let csv = read("test/test.csv").unwrap();
+        std::fs::write(&path, "古い内容が長い場合も残らないこと\n".repeat(10)).unwrap();
+
+        write_locked(&path, &csv, &CsvDialect::default(), &LockOptions::new()).unwrap();
+        // 共有ロック同士は同時に取得できる
+        let holder = File::open(&path).unwrap();
+        holder.lock_shared().unwrap();
+        let read_csv = read_locked(&path, &CsvDialect::default(), &LockOptions::with_timeout(Duration::ZERO)).unwrap();
+        drop(holder);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(csv.get_header().name, read_csv.get_header().name);
+        assert_eq!(csv.get_body().len(), read_csv.get_body().len());
+        assert_eq!(csv.get_body().get_row(2).unwrap().values(), read_csv.get_body().get_row(2).unwrap().values());
+    }
+
+    #[test]
+    fn write_locked_timeout() {
+        let path = temp_path("timeout");
+        std::fs::write(&path, "名前\nいるかねこ\n").unwrap();
+        let holder = File::open(&path).unwrap();
+        holder.lock_shared().unwrap();
+
+        let start = Instant::now();
+        let result = write_locked(&path, &read("test/test.csv").unwrap(), &CsvDialect::default(),
+                                  &LockOptions::with_timeout(Duration::from_millis(50)));
+        let elapsed = start.elapsed();
+        let text = std::fs::read_to_string(&path).unwrap();
+        drop(holder);
+        std::fs::remove_file(&path).unwrap();
+
+        match result {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!(format!("ロックを取得できませんでした。ほかのプロセスが使用中です。ファイル=[{}], タイムアウト=[50ms]", path), e),
+        }
+        assert!(elapsed >= Duration::from_millis(50));
+        // ロックを取得できない場合は内容を消去しない
+        assert_eq!("名前\nいるかねこ\n", text);
+    }
+
+    #[test]
+    fn read_locked_timeout() {
+        let path = temp_path("read_timeout");
+        std::fs::write(&path, "名前\nいるかねこ\n").unwrap();
+        let holder = File::open(&path).unwrap();
+        holder.lock().unwrap();
+
+        let result = read_locked(&path, &CsvDialect::default(), &LockOptions::with_timeout(Duration::ZERO));
+        drop(holder);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err_and(|e| e.starts_with("ロックを取得できませんでした。")));
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("csv-io-lock-{}-{}.csv", name, std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+}
@@ -0,0 +1,574 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::env;
+use std::io::{self, Write};
+use std::process;
+
+use csv_io::{read_from, read_from_with_options, read_with, read_with_options, write_to, CsvDialect, CsvFile, CsvRow,
+             ErrorPolicy, Keep, RaggedPolicy, ReadOptions, SortCompare, SortOrder};
+#[cfg(feature = "sqlite")]
+use csv_io::CsvQuery;
+
+const USAGE: &str = "\
+使い方: csv-io <コマンド> [オプション] [ファイル...]
+
+ファイルを省略した場合、または - を指定した場合は標準入力から読み込みます。
+複数のファイルを指定した場合は、ヘッダーが同じファイルを連結して処理します。
+
+コマンド:
+    cat                     そのまま出力する
+    head [-n 行数]          先頭の行を出力する(既定は10行)
+    tail [-n 行数]          末尾の行を出力する(既定は10行)
+    count                   データの行数を出力する
+    headers                 ヘッダー名を1行ずつ出力する
+    select <列,列...>       指定した列だけを出力する(-cで列を追加する)
+    filter <条件>           条件に一致する行を出力する(-wで条件を追加するとAND)
+                            条件: 列=値, 列!=値, 列<値, 列<=値, 列>値, 列>=値, 列~部分文字列
+    sort -k <列[:desc]>     指定した列で並び替える(-kは複数指定可)
+                            --numeric, --natural, --ignore-case で比較方法を指定する
+    dedup [-k <列,列...>]   重複した行を削除する(--keep-lastで最後の行を残す)
+    convert                 区切り文字を変換する(--out-delimiter, --out-tab)
+    validate                各行の項目数と囲み文字を検証する
+    stats                   列ごとの統計を出力する
+    query <SQL>             SQLを実行する(sqliteフィーチャーが必要)
+                            FROM 'sales.csv' のようにファイルをテーブルとして指定する
+                            指定したファイルはファイルパス、標準入力はstdinという名前のテーブルになる
+
+オプション:
+    -d, --delimiter <文字>  区切り文字(既定は,)
+    -q, --quote <文字>      囲み文字(既定は\")
+    --tab                   タブ区切りとして読み込む
+    -h, --help              この説明を表示する
+";
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    command: String,
+    dialect: CsvDialect,
+    out_delimiter: Option<char>,
+    count: usize,
+    keys: Vec<(String, SortOrder)>,
+    compare: SortCompare,
+    columns: Vec<String>,
+    conditions: Vec<String>,
+    keep: Keep,
+    sql: String,
+    files: Vec<String>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print!("{}", USAGE);
+        return;
+    }
+
+    if let Err(e) = parse_args(&args).and_then(|options| run(&options)) {
+        eprintln!("csv-io: {}", e);
+        process::exit(1);
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        command: args[0].clone(),
+        dialect: CsvDialect::default(),
+        out_delimiter: None,
+        count: 10,
+        keys: Vec::new(),
+        compare: SortCompare::Lexicographic,
+        columns: Vec::new(),
+        conditions: Vec::new(),
+        keep: Keep::First,
+        sql: String::new(),
+        files: Vec::new(),
+    };
+
+    let mut positionals = Vec::new();
+    let mut args = args[1..].iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| match args.next() {
+            Some(value) => Ok(value.clone()),
+            None => Err(format!("{}の値がありません。", name)),
+        };
+        match arg.as_str() {
+            "-d" | "--delimiter" => options.dialect.delimiter = parse_char(&value(arg)?)?,
+            "-q" | "--quote" => options.dialect.quote = parse_char(&value(arg)?)?,
+            "--tab" => options.dialect.delimiter = '\t',
+            "--out-delimiter" => options.out_delimiter = Some(parse_char(&value(arg)?)?),
+            "--out-tab" => options.out_delimiter = Some('\t'),
+            "-n" => {
+                let count = value(arg)?;
+                options.count = match count.parse() {
+                    Ok(count) => count,
+                    Err(_) => return Err(format!("行数が不正です。[{}]", count)),
+                };
+            }
+            "-k" | "--key" => {
+                for key in value(arg)?.split(',') {
+                    options.keys.push(parse_key(key)?);
+                }
+            }
+            "-c" | "--columns" => options.columns.extend(value(arg)?.split(',').map(|name| name.to_string())),
+            "-w" | "--where" => options.conditions.push(value(arg)?),
+            "--numeric" => options.compare = SortCompare::Numeric,
+            "--natural" => options.compare = SortCompare::Natural,
+            "--ignore-case" => options.compare = SortCompare::CaseInsensitive,
+            "--keep-last" => options.keep = Keep::Last,
+            "-" => positionals.push(arg.clone()),
+            _ if arg.starts_with('-') => return Err(format!("不明なオプションです。[{}]", arg)),
+            _ => positionals.push(arg.clone()),
+        }
+    }
+
+    // selectとfilterは最初の引数を列・条件として扱い、-c, -wの指定はその後に追加する
+    let mut positionals = positionals.into_iter();
+    if options.command == "select" {
+        match positionals.next() {
+            Some(columns) => {
+                let mut names: Vec<String> = columns.split(',').map(|name| name.to_string()).collect();
+                names.append(&mut options.columns);
+                options.columns = names;
+            }
+            None => return Err(String::from("列が指定されていません。")),
+        }
+    }
+    if options.command == "filter" {
+        match positionals.next() {
+            Some(condition) => options.conditions.insert(0, condition),
+            None => return Err(String::from("条件が指定されていません。")),
+        }
+    }
+    if options.command == "query" {
+        match positionals.next() {
+            Some(sql) => options.sql = sql,
+            None => return Err(String::from("SQLが指定されていません。")),
+        }
+    }
+    if options.command == "dedup" {
+        options.columns.extend(options.keys.drain(..).map(|(name, _)| name));
+    }
+    options.files = positionals.collect();
+
+    Ok(options)
+}
+
+// 1文字の指定を解釈する(\tとtabはタブ)
+fn parse_char(value: &str) -> Result<char, String> {
+    if value == "\\t" || value == "tab" {
+        return Ok('\t');
+    }
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(format!("1文字で指定してください。[{}]", value)),
+    }
+}
+
+fn parse_key(key: &str) -> Result<(String, SortOrder), String> {
+    match key.rsplit_once(':') {
+        Some((name, "desc")) => Ok((name.to_string(), SortOrder::Desc)),
+        Some((name, "asc")) => Ok((name.to_string(), SortOrder::Asc)),
+        Some((_, order)) => Err(format!("不正な並び順です。[{}]", order)),
+        None => Ok((key.to_string(), SortOrder::Asc)),
+    }
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let stdout = io::stdout();
+    let mut out_dialect = options.dialect.clone();
+    if let Some(delimiter) = options.out_delimiter {
+        out_dialect.delimiter = delimiter;
+    }
+    let out_dialect = &out_dialect;
+    if options.command == "query" {
+        return write_to(stdout.lock(), &query(options)?, out_dialect);
+    }
+    // 項目数が多い行も報告するため、行を除外しながら読み込む
+    if options.command == "validate" {
+        return validate(options);
+    }
+
+    let mut csv_file = load(options)?;
+
+    match options.command.as_str() {
+        "cat" | "convert" => write_to(stdout.lock(), &csv_file, out_dialect),
+        "head" => write_to(stdout.lock(), &csv_file.head(options.count), out_dialect),
+        "tail" => write_to(stdout.lock(), &csv_file.tail(options.count), out_dialect),
+        "count" => print_lines(&[csv_file.get_body().len().to_string()]),
+        "headers" => print_lines(&header_names(&csv_file)?),
+        "select" => {
+            let columns: Vec<&str> = options.columns.iter().map(|name| name.as_str()).collect();
+            write_to(stdout.lock(), &csv_file.select(&columns)?, out_dialect)
+        }
+        "filter" => {
+            let mut conditions = Vec::with_capacity(options.conditions.len());
+            for condition in &options.conditions {
+                conditions.push(Condition::parse(condition)?);
+            }
+            let names = header_names(&csv_file)?;
+            for condition in &conditions {
+                if !names.contains(&condition.column) {
+                    return Err(format!("存在しないヘッダー名です。[{}]", condition.column));
+                }
+            }
+            let filtered = csv_file.filter(|row| conditions.iter().all(|condition| condition.matches(row)));
+            write_to(stdout.lock(), &filtered, out_dialect)
+        }
+        "sort" => {
+            if options.keys.is_empty() {
+                return Err(String::from("-kで並び替える列を指定してください。"));
+            }
+            let keys: Vec<(&str, SortOrder)> = options.keys.iter()
+                .map(|(name, order)| (name.as_str(), *order))
+                .collect();
+            csv_file.sort_by_columns_with(&keys, options.compare)?;
+            write_to(stdout.lock(), &csv_file, out_dialect)
+        }
+        "dedup" => {
+            if options.columns.is_empty() {
+                csv_file.dedup();
+            } else {
+                let columns: Vec<&str> = options.columns.iter().map(|name| name.as_str()).collect();
+                csv_file.dedup_by(&columns, options.keep)?;
+            }
+            write_to(stdout.lock(), &csv_file, out_dialect)
+        }
+        "stats" => write_to(stdout.lock(), &stats(&csv_file)?, out_dialect),
+        _ => Err(format!("不明なコマンドです。[{}]", options.command)),
+    }
+}
+
+// 入力ファイル(なければ標準入力)を読み込み、連結する
+fn load(options: &Options) -> Result<CsvFile, String> {
+    let files = if options.files.is_empty() {
+        vec![String::from("-")]
+    } else {
+        options.files.clone()
+    };
+
+    let mut result: Option<CsvFile> = None;
+    for path in &files {
+        let csv_file = read_input(path, &options.dialect)?;
+        match &mut result {
+            None => result = Some(csv_file),
+            Some(first) => {
+                if header_names(first)? != header_names(&csv_file)? {
+                    return Err(format!("ヘッダーが一致しません。[{}]", path));
+                }
+                // 項目が足りない行もそのまま連結する
+                first.extend(csv_file)?;
+            }
+        }
+    }
+
+    match result {
+        Some(csv_file) => Ok(csv_file),
+        None => Err(String::from("入力がありません。")),
+    }
+}
+
+// -は標準入力
+fn read_input(path: &str, dialect: &CsvDialect) -> Result<CsvFile, String> {
+    if path == "-" {
+        return read_from(io::stdin().lock(), dialect);
+    }
+    // 圧縮されたファイルはread_withで展開する
+    match read_with(path, dialect) {
+        Ok(csv_file) => Ok(csv_file),
+        Err(e) => Err(format!("読み込みに失敗しました。[{}] [{}]", path, e)),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn query(options: &Options) -> Result<CsvFile, String> {
+    let mut query = CsvQuery::new();
+    for path in &options.files {
+        let name = if path == "-" { "stdin" } else { path.as_str() };
+        query.register(name, read_input(path, &options.dialect)?);
+    }
+    query.execute(&options.sql)
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn query(_options: &Options) -> Result<CsvFile, String> {
+    Err(String::from("queryコマンドにはsqliteフィーチャーが必要です。"))
+}
+
+fn header_names(csv_file: &CsvFile) -> Result<Vec<String>, String> {
+    let header = csv_file.get_header();
+    let mut names = Vec::with_capacity(header.len());
+    for index in 0..header.len() {
+        names.push(header.get_name(index)?.to_string());
+    }
+    Ok(names)
+}
+
+fn print_lines(lines: &[String]) -> Result<(), String> {
+    let mut stdout = io::stdout().lock();
+    for line in lines {
+        if let Err(e) = writeln!(stdout, "{}", line) {
+            return Err(format!("writeに失敗しました。[{}]", e));
+        }
+    }
+    Ok(())
+}
+
+// 項目数がヘッダーと一致しない行と解析できない行を報告する
+fn validate(options: &Options) -> Result<(), String> {
+    let files = if options.files.is_empty() {
+        vec![String::from("-")]
+    } else {
+        options.files.clone()
+    };
+    let read_options = ReadOptions {
+        dialect: options.dialect.clone(),
+        ragged: RaggedPolicy::Strict,
+        on_error: ErrorPolicy::Reject,
+        ..Default::default()
+    };
+
+    let mut rows = 0;
+    let mut errors = Vec::new();
+    for path in &files {
+        let (csv_file, report) = if path == "-" {
+            read_from_with_options(io::stdin().lock(), &read_options)?
+        } else {
+            match read_with_options(path, &read_options) {
+                Ok(result) => result,
+                Err(e) => return Err(format!("読み込みに失敗しました。[{}] [{}]", path, e)),
+            }
+        };
+        rows += csv_file.get_body().len();
+        // 複数のファイルの場合はファイル名を付ける
+        let prefix = if files.len() > 1 { format!("{}: ", path) } else { String::new() };
+        for reject in report.rejects() {
+            errors.push(format!("{}行=[{}], レコード=[{}] {}", prefix, reject.error.line, reject.error.record, reject.error.message));
+        }
+    }
+
+    if errors.is_empty() {
+        print_lines(&[format!("OK 行数=[{}]", rows)])
+    } else {
+        print_lines(&errors)?;
+        Err(format!("{}行が不正です。", errors.len()))
+    }
+}
+
+// 列ごとの件数・空の件数・種類数・最小・最大・平均
+fn stats(csv_file: &CsvFile) -> Result<CsvFile, String> {
+    let names = header_names(csv_file)?;
+
+    // 行を1度だけ走査して列ごとに値を集める(項目が足りない行は空とする)
+    let mut columns: Vec<Vec<&str>> = names.iter().map(|_| Vec::with_capacity(csv_file.rows().len())).collect();
+    for row in csv_file.rows() {
+        for (index, values) in columns.iter_mut().enumerate() {
+            values.push(row.get(index).unwrap_or_default());
+        }
+    }
+
+    let header = ["column", "count", "empty", "distinct", "min", "max", "mean"];
+    let mut stats_file = CsvFile::from_rows(header.iter().map(|name| name.to_string()).collect(), Vec::new());
+    for (name, values) in names.iter().zip(&columns) {
+        let filled: Vec<&str> = values.iter().copied().filter(|value| !value.is_empty()).collect();
+        let distinct: HashSet<&str> = filled.iter().copied().collect();
+        let numbers: Vec<f64> = filled.iter().filter_map(|value| value.trim().parse::<f64>().ok()).collect();
+
+        // すべての値が数値の場合は数値として集計する
+        let (min, max, mean) = if !filled.is_empty() && numbers.len() == filled.len() {
+            let min = numbers.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = numbers.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let mean = numbers.iter().sum::<f64>() / numbers.len() as f64;
+            (min.to_string(), max.to_string(), mean.to_string())
+        } else {
+            let min = filled.iter().min().map(|value| value.to_string()).unwrap_or_default();
+            let max = filled.iter().max().map(|value| value.to_string()).unwrap_or_default();
+            (min, max, String::new())
+        };
+
+        stats_file.append(vec![
+            name.clone(),
+            values.len().to_string(),
+            (values.len() - filled.len()).to_string(),
+            distinct.len().to_string(),
+            min,
+            max,
+            mean,
+        ])?;
+    }
+
+    Ok(stats_file)
+}
+
+#[derive(Debug, PartialEq)]
+struct Condition {
+    column: String,
+    operator: String,
+    value: String,
+}
+impl Condition {
+    fn parse(text: &str) -> Result<Condition, String> {
+        let position = match text.find(['!', '=', '<', '>', '~']) {
+            Some(position) => position,
+            None => return Err(format!("条件が不正です。[{}]", text)),
+        };
+        let rest = &text[position..];
+        let operator = ["!=", "<=", ">=", "=", "<", ">", "~"].iter()
+            .find(|operator| rest.starts_with(*operator));
+        match operator {
+            Some(operator) if position > 0 => Ok(Condition {
+                column: text[..position].to_string(),
+                operator: operator.to_string(),
+                value: rest[operator.len()..].to_string(),
+            }),
+            _ => Err(format!("条件が不正です。[{}]", text)),
+        }
+    }
+
+    fn matches(&self, row: &CsvRow) -> bool {
+        let value = match row.get_value(&self.column) {
+            Ok(value) => value,
+            Err(_) => return false,
+        };
+        // 両方が数値の場合は数値として比較する
+        let ordering = match (value.trim().parse::<f64>(), self.value.trim().parse::<f64>()) {
+            (Ok(a), Ok(b)) => a.partial_cmp(&b),
+            _ => Some(value.as_str().cmp(self.value.as_str())),
+        };
+        match self.operator.as_str() {
+            "=" => ordering == Some(Ordering::Equal),
+            "!=" => ordering != Some(Ordering::Equal),
+            "<" => ordering == Some(Ordering::Less),
+            "<=" => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            ">" => ordering == Some(Ordering::Greater),
+            ">=" => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+            "~" => value.contains(&self.value),
+            _ => false,
+        }
+    }
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_args_sort() {
+        let args: Vec<String> = ["sort", "--tab", "-k", "date:desc,id", "--numeric", "a.tsv", "-"]
+            .iter().map(|arg| arg.to_string()).collect();
+
+        let options = parse_args(&args).unwrap();
+
+        assert_eq!('\t', options.dialect.delimiter);
+        assert_eq!(vec![(String::from("date"), SortOrder::Desc), (String::from("id"), SortOrder::Asc)],
+                   options.keys);
+        assert_eq!(SortCompare::Numeric, options.compare);
+        assert_eq!(vec![String::from("a.tsv"), String::from("-")], options.files);
+    }
+
+    #[test]
+    fn parse_args_select() {
+        let args: Vec<String> = ["select", "id,name", "a.csv", "--out-delimiter", ";"]
+            .iter().map(|arg| arg.to_string()).collect();
+
+        let options = parse_args(&args).unwrap();
+
+        assert_eq!(vec![String::from("id"), String::from("name")], options.columns);
+        assert_eq!(Some(';'), options.out_delimiter);
+        assert_eq!(vec![String::from("a.csv")], options.files);
+    }
+
+    #[test]
+    fn parse_args_filter() {
+        let args: Vec<String> = ["filter", "amount>=1000", "-w", "region=東京", "sales.csv"]
+            .iter().map(|arg| arg.to_string()).collect();
+
+        let options = parse_args(&args).unwrap();
+
+        assert_eq!(vec![String::from("amount>=1000"), String::from("region=東京")], options.conditions);
+        assert_eq!(vec![String::from("sales.csv")], options.files);
+    }
+
+    #[test]
+    fn parse_args_query() {
+        let args: Vec<String> = ["query", "SELECT * FROM 'a.csv'", "-"]
+            .iter().map(|arg| arg.to_string()).collect();
+
+        let options = parse_args(&args).unwrap();
+
+        assert_eq!("SELECT * FROM 'a.csv'", options.sql);
+        assert_eq!(vec![String::from("-")], options.files);
+    }
+
+    #[test]
+    fn parse_args_error() {
+        let args: Vec<String> = ["head", "-x"].iter().map(|arg| arg.to_string()).collect();
+
+        match parse_args(&args) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("不明なオプションです。[-x]", e),
+        }
+    }
+
+    #[test]
+    fn condition_parse() {
+        assert_eq!(Condition {column: String::from("金額"), operator: String::from(">="), value: String::from("100")},
+                   Condition::parse("金額>=100").unwrap());
+        assert_eq!(Condition {column: String::from("name"), operator: String::from("!="), value: String::from("")},
+                   Condition::parse("name!=").unwrap());
+        assert!(Condition::parse("=100").is_err());
+    }
+
+    #[test]
+    fn condition_matches() {
+        let csv = read_from("id,name\n9,いるかねこ\n".as_bytes(), &CsvDialect::default()).unwrap();
+        let row = csv.get_body().get_row(0).unwrap();
+
+        assert!(Condition::parse("id<10").unwrap().matches(&row));
+        assert!(!Condition::parse("id>10").unwrap().matches(&row));
+        assert!(Condition::parse("name~ねこ").unwrap().matches(&row));
+        assert!(Condition::parse("name!=やかまし").unwrap().matches(&row));
+    }
+
+    #[test]
+    fn stats_short_row() {
+        // 項目が足りない行は空として集計する
+        let csv = read_from("a,b\n1\n2,3\n".as_bytes(), &CsvDialect::default()).unwrap();
+
+        let stats = stats(&csv).unwrap();
+
+        assert_eq!(vec!["a", "2", "0", "2", "1", "2", "1.5"], stats.rows()[0].values());
+        assert_eq!(vec!["b", "2", "1", "1", "3", "3", "3"], stats.rows()[1].values());
+    }
+
+    #[test]
+    fn load_short_row() {
+        let paths: Vec<String> = ["1", "2"].iter()
+            .map(|name| std::env::temp_dir().join(format!("csv-io-load-{}-{}.csv", name, std::process::id())))
+            .map(|path| path.to_str().unwrap().to_string())
+            .collect();
+        std::fs::write(&paths[0], "a,b\n1\n2,3\n").unwrap();
+        std::fs::write(&paths[1], "a,b\n4\n").unwrap();
+        let args: Vec<String> = ["cat", &paths[0], &paths[1]].iter().map(|arg| arg.to_string()).collect();
+
+        let csv = load(&parse_args(&args).unwrap());
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        let csv = csv.unwrap();
+        assert_eq!(3, csv.rows().len());
+        assert_eq!(vec!["4"], csv.rows()[2].values());
+    }
+}
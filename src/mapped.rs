@@ -0,0 +1,269 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::str;
+use std::sync::OnceLock;
+
+use memmap2::Mmap;
+
+use crate::compression::Compression;
+use crate::dialect::{split_record, FieldError, QuoteScanner};
+use crate::error::RecordPosition;
+use crate::{build_csv_row, CsvBody, CsvDialect, CsvFile, CsvHeader, ParseError};
+
+// メモリマップしたファイルを読み込むCsvFile
+// ヘッダー以外は読み込まず、行を取得したときにマップ上の文字列を参照して分割する
+// 囲み文字のエスケープ("")がある項目だけをコピーする
+//
+// マップ中にほかのプロセスがファイルを変更した場合の動作は保証されない
+pub struct MappedCsvFile {
+    mmap: Mmap,
+    dialect: CsvDialect,
+    csv_header: CsvHeader,
+    // ヘッダーの次の位置と物理的な行番号
+    body_start: (usize, usize),
+    // 各行の開始位置、終了位置(改行を除く)、物理的な行番号。最初に行を取得したときに作成する
+    index: OnceLock<Vec<(usize, usize, usize)>>,
+}
+impl MappedCsvFile {
+    pub fn open(path: &str) -> Result<Self, String> {
+        Self::open_with(path, &CsvDialect::default())
+    }
+
+    pub fn open_with(path: &str, dialect: &CsvDialect) -> Result<Self, String> {
+        dialect.validate()?;
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) => return Err(format!("openに失敗しました。{}", e)),
+        };
+        // ファイルの内容は読み込みのみで使用する
+        let mmap = match unsafe { Mmap::map(&file) } {
+            Ok(mmap) => mmap,
+            Err(e) => return Err(format!("mmapに失敗しました。[{}]", e)),
+        };
+        if Compression::from_magic(&mmap) != Compression::None {
+            return Err(String::from("圧縮されたファイルはメモリマップで読み込めません。"));
+        }
+
+        // ヘッダー
+        let mut scanner = QuoteScanner::new(dialect);
+        // 先頭のBOMは読み飛ばす
+        let mut start = if mmap.starts_with("\u{FEFF}".as_bytes()) { '\u{FEFF}'.len_utf8() } else { 0 };
+        let mut line = 1;
+        let mut csv_header = CsvHeader::new();
+        while start < mmap.len() {
+            let (end, next) = record_end(&mmap, start, &mut scanner);
+            let text = to_str(&mmap[start..end], 0)?;
+            let position = RecordPosition {record: 0, line, offset: start};
+            line += 1 + count_newlines(&mmap[start..end]);
+            start = next;
+            if text.is_empty() {
+                continue;
+            }
+            for name in split_record(text, dialect).map_err(|e| field_error(e, text, position, dialect))? {
+                csv_header.append(&name);
+            }
+            break;
+        }
+
+        Ok(Self {mmap, dialect: dialect.clone(), csv_header, body_start: (start, line), index: OnceLock::new()})
+    }
+
+    pub fn get_header(&self) -> &CsvHeader {
+        &self.csv_header
+    }
+
+    pub fn len(&self) -> usize {
+        self.index().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index().is_empty()
+    }
+
+    // 項目数がヘッダーより多い場合はエラー
+    pub fn get_row(&self, index: usize) -> Result<Vec<Cow<'_, str>>, String> {
+        let (start, end, line) = match self.index().get(index) {
+            Some(range) => *range,
+            None => return Err(format!("範囲外のインデックスが指定されました。[{}]", index)),
+        };
+        let text = to_str(&self.mmap[start..end], index + 1)?;
+        let position = RecordPosition {record: index + 1, line, offset: start};
+        let fields = split_record(text, &self.dialect).map_err(|e| field_error(e, text, position, &self.dialect))?;
+        if let Err(e) = self.csv_header.get_name(fields.len().saturating_sub(1)) {
+            return Err(ParseError::new(&e, text, position, Some(self.csv_header.len()), &self.dialect).into());
+        }
+        Ok(fields)
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = Result<Vec<Cow<'_, str>>, String>> + '_ {
+        (0..self.len()).map(|index| self.get_row(index))
+    }
+
+    // すべての行をコピーしてCsvFileを作成する
+    pub fn to_csv_file(&self) -> Result<CsvFile, String> {
+        let mut csv_body = CsvBody::new();
+        for row in self.rows() {
+            csv_body.append(build_csv_row(&self.csv_header, &row?)?);
+        }
+        Ok(CsvFile::new(self.csv_header.clone(), csv_body))
+    }
+
+    fn index(&self) -> &[(usize, usize, usize)] {
+        self.index.get_or_init(|| {
+            let mut scanner = QuoteScanner::new(&self.dialect);
+            let mut index = Vec::new();
+            let (mut start, mut line) = self.body_start;
+            while start < self.mmap.len() {
+                let (end, next) = record_end(&self.mmap, start, &mut scanner);
+                // 空行は読み飛ばす
+                if end > start {
+                    index.push((start, end, line));
+                }
+                line += 1 + count_newlines(&self.mmap[start..end]);
+                start = next;
+            }
+            index
+        })
+    }
+}
+
+// startから始まるレコードの終わり(改行を除く)と次のレコードの開始位置
+fn record_end(bytes: &[u8], start: usize, scanner: &mut QuoteScanner) -> (usize, usize) {
+    scanner.reset();
+    let mut index = start;
+    while index < bytes.len() {
+        if bytes[index] == b'\n' && !scanner.in_quote() {
+            let end = if index > start && bytes[index - 1] == b'\r' { index - 1 } else { index };
+            return (end, index + 1);
+        }
+        index = scanner.advance(bytes, index);
+    }
+    (bytes.len(), bytes.len())
+}
+
+fn count_newlines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|byte| **byte == b'\n').count()
+}
+
+fn field_error(e: FieldError, text: &str, position: RecordPosition, dialect: &CsvDialect) -> String {
+    ParseError::new(e.message, text, position, Some(e.field), dialect).into()
+}
+
+fn to_str(bytes: &[u8], record: usize) -> Result<&str, String> {
+    match str::from_utf8(bytes) {
+        Ok(text) => Ok(text),
+        Err(e) => Err(format!("readに失敗しました。レコード=[{}], [{}]", record, e)),
+    }
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read;
+
+    #[test]
+    fn mapped_get_row() {
+        let path = write_temp("get_row", "\u{FEFF}id,名前,備考\r\n1,いるかねこ,\"改行\r\n\"\"あり\"\"\"\r\n\r\n2,船長うさぎ,\r\n3,,なし");
+
+        let csv = MappedCsvFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(vec!["id", "名前", "備考"], csv.get_header().name);
+        assert_eq!(3, csv.len());
+        let row = csv.get_row(0).unwrap();
+        assert_eq!(vec!["1", "いるかねこ", "改行\r\n\"あり\""], row);
+        // エスケープのない項目はマップ上の文字列を参照する
+        assert!(matches!(row[1], Cow::Borrowed(_)));
+        assert!(matches!(row[2], Cow::Owned(_)));
+        assert_eq!(vec!["3", "", "なし"], csv.get_row(2).unwrap());
+        match csv.get_row(3) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("範囲外のインデックスが指定されました。[3]", e),
+        }
+    }
+
+    #[test]
+    fn mapped_to_csv_file() {
+        let csv = MappedCsvFile::open("test/test.csv").unwrap().to_csv_file().unwrap();
+        let expected = read("test/test.csv").unwrap();
+
+        assert_eq!(expected.get_header().name, csv.get_header().name);
+        assert_eq!(expected.get_body().len(), csv.get_body().len());
+        assert_eq!(expected.get_body().get_row(2).unwrap().values(), csv.get_body().get_row(2).unwrap().values());
+    }
+
+    #[test]
+    fn mapped_multibyte_quote() {
+        let path = write_temp("quote", "a,b\n「1\n2「,3\n");
+
+        let csv = MappedCsvFile::open_with(&path, &CsvDialect::new(',', '「')).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(1, csv.len());
+        assert_eq!(vec!["1\n2", "3"], csv.get_row(0).unwrap());
+    }
+
+    #[test]
+    fn mapped_stray_quote() {
+        let path = write_temp("stray", "名前,金額\n船長\"うさぎ,200\nやかまし,300\n");
+
+        let csv = MappedCsvFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(2, csv.len());
+        assert_eq!(vec!["船長\"うさぎ", "200"], csv.get_row(0).unwrap());
+        assert_eq!(vec!["やかまし", "300"], csv.get_row(1).unwrap());
+    }
+
+    #[test]
+    fn mapped_bom_quoted() {
+        let path = write_temp("bom", "\u{FEFF}\"名\n前\",金額\n船長うさぎ,200\n");
+
+        let csv = MappedCsvFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(vec!["名\n前", "金額"], csv.get_header().name);
+        assert_eq!(vec!["船長うさぎ", "200"], csv.get_row(0).unwrap());
+    }
+
+    #[test]
+    fn mapped_empty() {
+        let path = write_temp("empty", "");
+
+        let csv = MappedCsvFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(csv.get_header().name.is_empty());
+        assert!(csv.is_empty());
+    }
+
+    #[test]
+    fn mapped_error() {
+        let path = write_temp("error", "a,b\n1,2,3\n");
+
+        let csv = MappedCsvFile::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        match csv.to_csv_file() {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("範囲外のインデックスが指定されました。[2] 行=[2], 列=[5], レコード=[1], バイト位置=[8], 項目=[2]\n1,2,3\n    ^", e),
+        }
+    }
+
+    fn write_temp(name: &str, text: &str) -> String {
+        let path = std::env::temp_dir().join(format!("csv-io-mapped-{}-{}.csv", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+}
@@ -0,0 +1,293 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::compression;
+use crate::dialect::{QuoteScanner, RecordReader};
+use crate::error::RecordPosition;
+use crate::{parse_header, parse_row, CsvBody, CsvDialect, CsvFile, CsvHeader, CsvRow, ParseError};
+
+// 1つのチャンクのおよそのバイト数
+const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+// 複数のスレッドで読み込む
+// 圧縮されたファイルは展開しながら読み込む
+// threadsが0の場合は使用できるCPUの数とする
+pub fn read_parallel(path: &str, dialect: &CsvDialect, threads: usize) -> Result<CsvFile, String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("openに失敗しました。{}", e)),
+    };
+
+    let mut reader = BufReader::new(file);
+    let compression = compression::detect(&mut reader)?;
+    read_parallel_from(compression::decoder(reader, compression)?, dialect, threads)
+}
+
+// 読み込むスレッドが囲み文字の外の改行でチャンクに区切り、他のスレッドで解析する
+// 解析を待つチャンクはスレッド数までとし、ファイル全体はメモリに読み込まない
+pub fn read_parallel_from<R: Read>(reader: R, dialect: &CsvDialect, threads: usize) -> Result<CsvFile, String> {
+    read_parallel_from_with(reader, dialect, threads, CHUNK_SIZE)
+}
+
+// チャンクのおよそのバイト数を指定する
+// 小さくするとスレッドに均等に分配しやすくなり、大きくするとチャンクごとの処理が減る
+pub fn read_parallel_from_with<R: Read>(reader: R, dialect: &CsvDialect, threads: usize, chunk_size: usize)
+    -> Result<CsvFile, String> {
+    dialect.validate()?;
+    if chunk_size == 0 {
+        return Err(String::from("チャンクのサイズには1以上を指定してください。"));
+    }
+    let threads = match threads {
+        0 => thread::available_parallelism().map(|count| count.get()).unwrap_or(1),
+        threads => threads,
+    };
+    let mut reader = BufReader::new(reader);
+
+    // ヘッダー
+    let mut records = RecordReader::new(&mut reader, dialect);
+    let csv_header = match records.next_record() {
+        Some(record) => parse_header(&record?, dialect)?,
+        None => CsvHeader::new(),
+    };
+    let body = records.position();
+
+    // データ
+    let mut starts = Vec::new();
+    let (chunk_sender, chunk_receiver) = mpsc::sync_channel::<(usize, Vec<u8>)>(threads);
+    let chunk_receiver = Arc::new(Mutex::new(chunk_receiver));
+    let (result_sender, result_receiver) = mpsc::channel();
+    let (read, finished) = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                // 全てのスレッドが終了するとReceiverが破棄され、区切る処理も終了する
+                let chunk_receiver = Arc::clone(&chunk_receiver);
+                let result_sender = result_sender.clone();
+                let csv_header = &csv_header;
+                scope.spawn(move || {
+                    while let Ok(Ok((index, chunk))) = chunk_receiver.lock().map(|receiver| receiver.recv()) {
+                        let _ = result_sender.send((index, parse_chunk(&chunk, csv_header, dialect)));
+                    }
+                })
+            })
+            .collect();
+        drop(chunk_receiver);
+        let read = split_chunks(&mut reader, dialect, chunk_size, &mut starts, chunk_sender);
+        (read, handles.into_iter().all(|handle| handle.join().is_ok()))
+    });
+    drop(result_sender);
+    if !finished {
+        return Err("スレッドが異常終了しました。".to_string());
+    }
+    let mut results: Vec<Option<Result<Vec<CsvRow>, ParseError>>> = starts.iter().map(|_| None).collect();
+    for (index, result) in result_receiver {
+        results[index] = Some(result);
+    }
+
+    // 元の順序で連結する
+    // 読み込みに失敗した場合も、それより前のチャンクのエラーを優先する
+    let mut csv_body = CsvBody::new();
+    for ((lines, offset), result) in starts.into_iter().zip(results) {
+        match result {
+            Some(Ok(rows)) => csv_body.rows.extend(rows),
+            Some(Err(mut e)) => {
+                // チャンク内の位置をファイル内の位置にする
+                e.record += csv_body.rows.len();
+                e.line += body.line - 1 + lines;
+                e.offset += body.offset + offset;
+                return Err(e.into());
+            }
+            None => return Err("スレッドが異常終了しました。".to_string()),
+        }
+    }
+    read?;
+    Ok(CsvFile::new(csv_header, csv_body))
+}
+
+// チャンク内の位置(最初のレコードを1行目のレコード番号1とする)でエラーを返す
+fn parse_chunk(chunk: &[u8], csv_header: &CsvHeader, dialect: &CsvDialect) -> Result<Vec<CsvRow>, ParseError> {
+    let mut rows = Vec::new();
+    let mut records = RecordReader::new(chunk, dialect).starting_at(RecordPosition {record: 1, line: 1, offset: 0});
+    while let Some(record) = records.next_record() {
+        rows.push(parse_row(csv_header, &record?, dialect)?);
+    }
+    Ok(rows)
+}
+
+// sizeバイト以上になった後の囲み文字の外の改行の直後で区切って送る
+// startsには各チャンクの前の改行の数とバイト数を追加する
+fn split_chunks<R: BufRead>(reader: &mut R, dialect: &CsvDialect, size: usize, starts: &mut Vec<(usize, usize)>,
+                            sender: SyncSender<(usize, Vec<u8>)>) -> Result<(), String> {
+    let mut scanner = QuoteScanner::new(dialect);
+    let (mut lines, mut offset) = (0, 0);
+    let mut chunk = Vec::new();
+    let mut chunk_lines = 0;
+    loop {
+        // 1行ずつ判定するため、複数バイトの囲み文字が途中で分かれない
+        let start = chunk.len();
+        let read = match reader.read_until(b'\n', &mut chunk) {
+            Ok(read) => read,
+            Err(e) => return Err(format!("readに失敗しました。[{}]", e)),
+        };
+        let closed = read > 0 && scanner.scan(&chunk[start..]);
+        if chunk.last() == Some(&b'\n') && read > 0 {
+            chunk_lines += 1;
+        }
+        if (read == 0 && !chunk.is_empty()) || (closed && chunk.len() >= size) {
+            starts.push((lines, offset));
+            lines += std::mem::take(&mut chunk_lines);
+            offset += chunk.len();
+            if sender.send((starts.len() - 1, std::mem::take(&mut chunk))).is_err() {
+                // 解析するスレッドが全て終了した
+                starts.pop();
+                return Ok(());
+            }
+        }
+        if read == 0 {
+            return Ok(());
+        }
+    }
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_from;
+
+    #[test]
+    fn split_chunks_quoted_newline() {
+        let (sender, receiver) = mpsc::sync_channel(8);
+        let mut starts = Vec::new();
+
+        split_chunks(&mut &b"a,\"1\n2\n3\"\nb,4\nc,5\n"[..], &CsvDialect::default(), 3, &mut starts, sender).unwrap();
+
+        let chunks: Vec<(usize, Vec<u8>)> = receiver.into_iter().collect();
+        assert_eq!(vec![(0, b"a,\"1\n2\n3\"\n".to_vec()), (1, b"b,4\n".to_vec()), (2, b"c,5\n".to_vec())], chunks);
+        assert_eq!(vec![(0, 0), (3, 10), (4, 14)], starts);
+    }
+
+    #[test]
+    fn split_chunks_stray_quote() {
+        // 囲まれていない項目の途中の囲み文字では囲みを開始しない
+        let (sender, receiver) = mpsc::sync_channel(8);
+        let mut starts = Vec::new();
+
+        split_chunks(&mut &b"a,1\"2\nb,3\n"[..], &CsvDialect::default(), 1, &mut starts, sender).unwrap();
+
+        let chunks: Vec<(usize, Vec<u8>)> = receiver.into_iter().collect();
+        assert_eq!(vec![(0, b"a,1\"2\n".to_vec()), (1, b"b,3\n".to_vec())], chunks);
+    }
+
+    #[test]
+    fn read_parallel_same_as_sequential() {
+        let mut text = String::from("\u{FEFF}id,名前,備考\r\n");
+        for index in 0..500 {
+            text.push_str(&format!("{},いるかねこ{},\"改行\r\n\"\"{}\"\"\"\r\n", index, index, index));
+            if index % 7 == 0 {
+                text.push_str("\r\n");
+            }
+        }
+
+        let sequential = read_from(text.as_bytes(), &CsvDialect::default()).unwrap();
+        for (threads, size) in [(1, 1), (3, 100), (8, 4096), (0, CHUNK_SIZE)] {
+            let parallel = read_parallel_from_with(text.as_bytes(), &CsvDialect::default(), threads, size).unwrap();
+
+            assert_eq!(sequential.get_header().name, parallel.get_header().name);
+            assert_eq!(sequential.get_body().len(), parallel.get_body().len());
+            for index in [0, 1, 250, 499] {
+                assert_eq!(sequential.get_body().get_row(index).unwrap().values(),
+                           parallel.get_body().get_row(index).unwrap().values());
+            }
+        }
+    }
+
+    #[test]
+    fn read_parallel_multibyte_quote() {
+        // 囲み文字が1バイトの文字でない場合も囲み文字の中の改行では区切らない
+        let text = "a,b\n「1\n2「,3\n4,5\n";
+        let dialect = CsvDialect::new(',', '「');
+
+        let csv = read_parallel_from_with(text.as_bytes(), &dialect, 2, 1).unwrap();
+
+        assert_eq!(vec!["1\n2", "3"], csv.get_body().get_row(0).unwrap().values());
+        assert_eq!(vec!["4", "5"], csv.get_body().get_row(1).unwrap().values());
+    }
+
+    #[test]
+    fn read_parallel_file() {
+        let csv = read_parallel("test/test.csv", &CsvDialect::default(), 2).unwrap();
+
+        assert_eq!(vec!["ヘッダー1", "ヘッダー2", "ヘッダー3"], csv.get_header().name);
+        assert_eq!(vec!["いぬてんし", "おけぶろ", "すもっく"], csv.get_body().get_row(2).unwrap().values());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn read_parallel_gzip() {
+        use crate::{read, write_with};
+
+        // 展開しながら読み込む
+        let csv = read("test/test.csv").unwrap();
+        let path = std::env::temp_dir().join(format!("csv-io-parallel-{}.csv.gz", std::process::id()));
+        let path = path.to_str().unwrap();
+        write_with(path, &csv, &CsvDialect::default()).unwrap();
+
+        let parallel = read_parallel(path, &CsvDialect::default(), 2);
+        std::fs::remove_file(path).unwrap();
+
+        let parallel = parallel.unwrap();
+        assert_eq!(csv.get_header().name, parallel.get_header().name);
+        assert_eq!(csv.get_body().get_row(2).unwrap().values(), parallel.get_body().get_row(2).unwrap().values());
+    }
+
+    #[test]
+    fn read_parallel_chunk_size_error() {
+        match read_parallel_from_with("a\n1\n".as_bytes(), &CsvDialect::default(), 2, 0) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("チャンクのサイズには1以上を指定してください。", e),
+        }
+    }
+
+    #[test]
+    fn read_parallel_error() {
+        let text = "a,b\n1,2\n3,4,5\n";
+
+        match read_parallel_from_with(text.as_bytes(), &CsvDialect::default(), 2, 1) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("範囲外のインデックスが指定されました。[2] 行=[3], 列=[5], レコード=[2], バイト位置=[12], 項目=[2]\n3,4,5\n    ^", e),
+        }
+    }
+
+    #[test]
+    fn read_parallel_error_position() {
+        let mut text = String::from("id,名前\n");
+        for index in 0..100 {
+            text.push_str(&format!("{},\"いるかねこ\n{}\"\n\n", index, index));
+        }
+        text.push_str("100,\"船長\"うさぎ\n");
+
+        let sequential = match read_from(text.as_bytes(), &CsvDialect::default()) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => e,
+        };
+        // 後ろのチャンクで発生したエラーもファイル内の位置になる
+        match read_parallel_from_with(text.as_bytes(), &CsvDialect::default(), 4, 64) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!(sequential, e),
+        }
+        assert!(sequential.contains("行=[302], 列=[5], レコード=[101]"), "{}", sequential);
+    }
+}
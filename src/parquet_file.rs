@@ -0,0 +1,115 @@
+use std::fs::File;
+
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::arrow::ArrowWriter;
+
+use crate::{CsvFile, CsvSchema};
+
+// 値から推定した型でParquetファイルに書き込む
+// 0埋めのコードなど、変換すると値が変わる列は文字列とする
+// 既存のファイルは上書きする
+pub fn write_parquet(path: &str, csv_file: &CsvFile) -> Result<(), String> {
+    write_parquet_with(path, csv_file, &CsvSchema::infer_preserving(csv_file))
+}
+
+pub fn write_parquet_with(path: &str, csv_file: &CsvFile, schema: &CsvSchema) -> Result<(), String> {
+    let batch = csv_file.to_record_batch_with(schema)?;
+    let file = match File::create(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("createに失敗しました。[{}]", e)),
+    };
+
+    let mut writer = match ArrowWriter::try_new(file, batch.schema(), None) {
+        Ok(writer) => writer,
+        Err(e) => return Err(format!("Parquetの書き込みに失敗しました。[{}]", e)),
+    };
+    if let Err(e) = writer.write(&batch) {
+        return Err(format!("Parquetの書き込みに失敗しました。[{}]", e));
+    }
+    if let Err(e) = writer.close() {
+        return Err(format!("Parquetの書き込みに失敗しました。[{}]", e));
+    }
+    Ok(())
+}
+
+pub fn read_parquet(path: &str) -> Result<CsvFile, String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("openに失敗しました。{}", e)),
+    };
+
+    let reader = match ParquetRecordBatchReaderBuilder::try_new(file).and_then(|builder| builder.build()) {
+        Ok(reader) => reader,
+        Err(e) => return Err(format!("Parquetの読み込みに失敗しました。[{}]", e)),
+    };
+    let mut batches = Vec::new();
+    for batch in reader {
+        match batch {
+            Ok(batch) => batches.push(batch),
+            Err(e) => return Err(format!("Parquetの読み込みに失敗しました。[{}]", e)),
+        }
+    }
+
+    CsvFile::from_record_batches(&batches)
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read, ColumnType};
+
+    #[test]
+    fn parquet_round_trip() {
+        let csv = read("test/test.csv").unwrap();
+        let path = std::env::temp_dir().join(format!("csv-io-test-{}.parquet", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        write_parquet(path, &csv).unwrap();
+        let read = read_parquet(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(csv.get_header().name, read.get_header().name);
+        for index in 0..3 {
+            assert_eq!(csv.get_body().get_row(index).unwrap().values(), read.get_body().get_row(index).unwrap().values());
+        }
+    }
+
+    #[test]
+    fn parquet_preserves_values() {
+        let csv = CsvFile::from_rows(
+            vec![String::from("郵便番号"), String::from("金額")],
+            vec![vec![String::from("00123"), String::from("1.50")]],
+        );
+        let path = std::env::temp_dir().join(format!("csv-io-preserve-{}.parquet", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        write_parquet(path, &csv).unwrap();
+        let read = read_parquet(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(vec!["00123", "1.50"], read.get_body().get_row(0).unwrap().values());
+    }
+
+    #[test]
+    fn write_parquet_error() {
+        let csv = read("test/test.csv").unwrap();
+        let mut schema = CsvSchema::new();
+        schema.push("ヘッダー1", ColumnType::Boolean);
+
+        match write_parquet_with("never-created.parquet", &csv, &schema) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("RecordBatchへの変換に失敗しました。[真偽値に変換できません。行=[0], 列=[ヘッダー1], 値=[いるかねこ]]", e),
+        }
+    }
+}
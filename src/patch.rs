@@ -0,0 +1,919 @@
+use std::collections::HashMap;
+
+use crate::json::JsonValue;
+use crate::{CellChange, CsvDiff, CsvFile, CsvRow};
+
+// 操作対象の行の指定方法
+#[derive(Clone, Debug, PartialEq)]
+pub enum RowTarget {
+    // キー列の値
+    Key(Vec<String>),
+    // 行番号
+    Index(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchOperation {
+    // indexがNoneの場合は末尾に追加する
+    Insert {index: Option<usize>, row: Vec<(String, String)>},
+    // rowには削除する行の内容を保持する(反転と適用時の検証に使用する)
+    // positionは削除する時点の行番号で、反転したときに同じ位置に挿入する(Noneの場合は末尾)
+    Delete {target: RowTarget, position: Option<usize>, row: Vec<(String, String)>},
+    Update {target: RowTarget, cells: Vec<CellChange>},
+}
+
+// 適用した操作を取り消すための記録
+enum Undo {
+    // 挿入した行番号
+    Insert(usize),
+    // 削除した行番号と行
+    Delete(usize, CsvRow),
+    // 変更した行番号と変更前の行
+    Update(usize, CsvRow),
+}
+
+// パッチのキー列の値→行番号の索引
+// 適用前のファイルでキーが重複している場合に備えて、行番号は複数保持する
+struct KeyIndex {
+    rows: HashMap<Vec<String>, Vec<usize>>,
+}
+impl KeyIndex {
+    fn new() -> Self {
+        Self {rows: HashMap::new()}
+    }
+
+    fn push(&mut self, key: Vec<String>, index: usize) {
+        self.rows.entry(key).or_default().push(index);
+    }
+
+    // キーが一致する最初の行
+    fn find(&self, key: &[String]) -> Option<usize> {
+        self.rows.get(key).and_then(|indexes| indexes.iter().min().copied())
+    }
+
+    fn contains_other(&self, key: &[String], ignore: Option<usize>) -> bool {
+        self.rows.get(key).is_some_and(|indexes| indexes.iter().any(|index| Some(*index) != ignore))
+    }
+
+    // 行数がlenのときにindex行目に挿入する
+    fn insert(&mut self, key: Vec<String>, index: usize, len: usize) {
+        if index < len {
+            for row_index in self.rows.values_mut().flatten() {
+                if *row_index >= index {
+                    *row_index += 1;
+                }
+            }
+        }
+        self.push(key, index);
+    }
+
+    fn remove(&mut self, key: &[String], index: usize) {
+        self.detach(key, index);
+        for row_index in self.rows.values_mut().flatten() {
+            if *row_index > index {
+                *row_index -= 1;
+            }
+        }
+    }
+
+    // index行目のキーを変更する
+    fn replace(&mut self, old_key: &[String], key: Vec<String>, index: usize) {
+        if old_key != key.as_slice() {
+            self.detach(old_key, index);
+            self.push(key, index);
+        }
+    }
+
+    fn detach(&mut self, key: &[String], index: usize) {
+        if let Some(indexes) = self.rows.get_mut(key) {
+            indexes.retain(|row_index| *row_index != index);
+            if indexes.is_empty() {
+                self.rows.remove(key);
+            }
+        }
+    }
+}
+
+// CsvFileに適用できる行単位の変更の集まり
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvPatch {
+    key_columns: Vec<String>,
+    operations: Vec<PatchOperation>,
+}
+impl CsvPatch {
+    // key_columnsが空の場合は行番号で行を指定する
+    pub fn new(key_columns: &[&str]) -> Self {
+        Self {
+            key_columns: key_columns.iter().map(|name| name.to_string()).collect(),
+            operations: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, operation: PatchOperation) {
+        self.operations.push(operation);
+    }
+
+    pub fn key_columns(&self) -> &[String] {
+        &self.key_columns
+    }
+
+    pub fn operations(&self) -> &[PatchOperation] {
+        &self.operations
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    // 先頭の操作から順に適用する
+    // 途中でエラーになった場合、適用した操作を取り消すため、csv_fileは変更されない
+    pub fn apply(&self, csv_file: &mut CsvFile) -> Result<(), String> {
+        for key_column in &self.key_columns {
+            if !csv_file.csv_header.name.contains(key_column) {
+                return Err(format!("パッチの適用に失敗しました。[存在しないヘッダー名です。[{}]]", key_column));
+            }
+        }
+
+        let mut key_index = KeyIndex::new();
+        if !self.key_columns.is_empty() {
+            for (index, row) in csv_file.csv_body.rows.iter().enumerate() {
+                key_index.push(self.row_key(row), index);
+            }
+        }
+        let mut undo = Vec::new();
+        for (seq, operation) in self.operations.iter().enumerate() {
+            if let Err(e) = self.apply_operation(csv_file, &mut key_index, operation, &mut undo) {
+                // 適用した操作を逆の順に取り消す
+                let rows = &mut csv_file.csv_body.rows;
+                for entry in undo.into_iter().rev() {
+                    match entry {
+                        Undo::Insert(index) => {
+                            rows.remove(index);
+                        }
+                        Undo::Delete(index, row) => rows.insert(index, row),
+                        Undo::Update(index, row) => rows[index] = row,
+                    }
+                }
+                csv_file.rebuild_primary_key();
+                return Err(format!("パッチの適用に失敗しました。操作=[{}], [{}]", seq, e));
+            }
+        }
+
+        Ok(())
+    }
+
+    // 検証してから変更するため、エラーの場合はcsv_fileを変更しない
+    fn apply_operation(&self, csv_file: &mut CsvFile, key_index: &mut KeyIndex, operation: &PatchOperation,
+                       undo: &mut Vec<Undo>) -> Result<(), String> {
+        match operation {
+            PatchOperation::Insert {index, row} => {
+                let mut values = Vec::with_capacity(csv_file.csv_header.len());
+                for name in &csv_file.csv_header.name {
+                    match row.iter().find(|(header_name, _)| header_name == name) {
+                        Some((_, value)) => values.push(value.clone()),
+                        None => return Err(format!("行に値がありません。[{}]", name)),
+                    }
+                }
+                let csv_row = csv_file.build_row(values)?;
+                csv_file.check_primary_key(&csv_row, None)?;
+                let key = self.check_key(key_index, &csv_row, None)?;
+
+                let rows = &mut csv_file.csv_body.rows;
+                let index = index.unwrap_or(rows.len());
+                if index > rows.len() {
+                    return Err(format!("不正なインデックスです。指定したインデックス=[{}], 許容範囲=[{}]"
+                                       , index, rows.len()));
+                }
+                if let Some(key) = key {
+                    key_index.insert(key, index, rows.len());
+                }
+                rows.insert(index, csv_row);
+                csv_file.insert_primary_key(index);
+                undo.push(Undo::Insert(index));
+            }
+            PatchOperation::Delete {target, row, ..} => {
+                let index = self.find_row(csv_file, key_index, target)?;
+                let current = &csv_file.csv_body.rows[index];
+                for (header_name, value) in row {
+                    let current_value = current.get_value(header_name)?;
+                    if current_value != *value {
+                        return Err(format!("行の内容が一致しません。列=[{}], 期待値=[{}], 現在値=[{}]"
+                                           , header_name, value, current_value));
+                    }
+                }
+                let removed = csv_file.csv_body.rows.remove(index);
+                if !self.key_columns.is_empty() {
+                    key_index.remove(&self.row_key(&removed), index);
+                }
+                csv_file.remove_primary_key(&removed, index);
+                undo.push(Undo::Delete(index, removed));
+            }
+            PatchOperation::Update {target, cells} => {
+                let index = self.find_row(csv_file, key_index, target)?;
+                let mut csv_row = csv_file.csv_body.rows[index].clone();
+                for cell in cells {
+                    let csv_data = match csv_row.data.iter_mut()
+                        .find(|csv_data| csv_data.header_name == cell.header_name) {
+                        Some(csv_data) => csv_data,
+                        None => return Err(format!("存在しないヘッダー名です。[{}]", cell.header_name)),
+                    };
+                    if csv_data.value != cell.old_value {
+                        return Err(format!("行の内容が一致しません。列=[{}], 期待値=[{}], 現在値=[{}]"
+                                           , cell.header_name, cell.old_value, csv_data.value));
+                    }
+                    csv_data.value = cell.new_value.clone();
+                }
+                csv_file.check_primary_key(&csv_row, Some(index))?;
+                let key = self.check_key(key_index, &csv_row, Some(index))?;
+
+                let old_row = std::mem::replace(&mut csv_file.csv_body.rows[index], csv_row);
+                if let Some(key) = key {
+                    key_index.replace(&self.row_key(&old_row), key, index);
+                }
+                csv_file.update_primary_key(&old_row, index);
+                undo.push(Undo::Update(index, old_row));
+            }
+        }
+
+        Ok(())
+    }
+
+    // パッチのキー列で行が一意に決まるかを検証し、行のキーを返す(キー列がない場合はNone)
+    fn check_key(&self, key_index: &KeyIndex, csv_row: &CsvRow, ignore: Option<usize>)
+        -> Result<Option<Vec<String>>, String> {
+        if self.key_columns.is_empty() {
+            return Ok(None);
+        }
+        let key = self.row_key(csv_row);
+        if key_index.contains_other(&key, ignore) {
+            return Err(format!("キーが重複しています。[{}]", key.join(", ")));
+        }
+        Ok(Some(key))
+    }
+
+    fn find_row(&self, csv_file: &CsvFile, key_index: &KeyIndex, target: &RowTarget) -> Result<usize, String> {
+        match target {
+            RowTarget::Index(index) => {
+                if *index >= csv_file.csv_body.rows.len() {
+                    return Err(format!("範囲外のインデックスが指定されました。[{}]", index));
+                }
+                Ok(*index)
+            }
+            RowTarget::Key(key) => {
+                if key.len() != self.key_columns.len() {
+                    return Err(format!("キーの数が不正です。必要数=[{}], 渡した数=[{}]"
+                                       , self.key_columns.len(), key.len()));
+                }
+                match key_index.find(key) {
+                    Some(index) => Ok(index),
+                    None => Err(format!("存在しないキーです。[{}]", key.join(", "))),
+                }
+            }
+        }
+    }
+
+    fn row_key(&self, row: &CsvRow) -> Vec<String> {
+        self.key_columns.iter()
+            .map(|name| row.get_value(name).unwrap_or_default())
+            .collect()
+    }
+
+    fn pairs_key(&self, row: &[(String, String)]) -> Vec<String> {
+        self.key_columns.iter()
+            .map(|name| row.iter()
+                .find(|(header_name, _)| header_name == name)
+                .map(|(_, value)| value.clone())
+                .unwrap_or_default())
+            .collect()
+    }
+
+    // 適用前の状態に戻すパッチを作成する
+    pub fn invert(&self) -> Result<CsvPatch, String> {
+        let mut operations = Vec::with_capacity(self.operations.len());
+        for operation in self.operations.iter().rev() {
+            let inverted = match operation {
+                PatchOperation::Insert {index, row} => {
+                    let target = if !self.key_columns.is_empty() {
+                        RowTarget::Key(self.pairs_key(row))
+                    } else {
+                        match index {
+                            Some(index) => RowTarget::Index(*index),
+                            None => return Err(String::from(
+                                "パッチの反転に失敗しました。[行番号のない追加はキー列がない場合反転できません。]")),
+                        }
+                    };
+                    PatchOperation::Delete {target, position: *index, row: row.clone()}
+                }
+                PatchOperation::Delete {target, position, row} => {
+                    let index = match target {
+                        RowTarget::Index(index) => Some(*index),
+                        RowTarget::Key(_) => *position,
+                    };
+                    PatchOperation::Insert {index, row: row.clone()}
+                }
+                PatchOperation::Update {target, cells} => {
+                    // キー列が変更された場合、変更後のキーで行を指定する
+                    let target = match target {
+                        RowTarget::Key(key) => {
+                            let mut key = key.clone();
+                            for (key_index, name) in self.key_columns.iter().enumerate() {
+                                if let Some(cell) = cells.iter().find(|cell| cell.header_name == *name) {
+                                    key[key_index] = cell.new_value.clone();
+                                }
+                            }
+                            RowTarget::Key(key)
+                        }
+                        RowTarget::Index(index) => RowTarget::Index(*index),
+                    };
+                    let cells = cells.iter()
+                        .map(|cell| CellChange::new(&cell.header_name, &cell.new_value, &cell.old_value))
+                        .collect();
+                    PatchOperation::Update {target, cells}
+                }
+            };
+            operations.push(inverted);
+        }
+
+        Ok(CsvPatch {key_columns: self.key_columns.clone(), operations})
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut operations = Vec::with_capacity(self.operations.len());
+        for operation in &self.operations {
+            let mut members = Vec::new();
+            match operation {
+                PatchOperation::Insert {index, row} => {
+                    members.push((String::from("op"), JsonValue::String(String::from("insert"))));
+                    if let Some(index) = index {
+                        members.push((String::from("index"), JsonValue::Number(index.to_string())));
+                    }
+                    members.push((String::from("row"), pairs_to_json(row)));
+                }
+                PatchOperation::Delete {target, position, row} => {
+                    members.push((String::from("op"), JsonValue::String(String::from("delete"))));
+                    members.push(target_to_json(target));
+                    if let Some(position) = position {
+                        members.push((String::from("position"), JsonValue::Number(position.to_string())));
+                    }
+                    members.push((String::from("row"), pairs_to_json(row)));
+                }
+                PatchOperation::Update {target, cells} => {
+                    members.push((String::from("op"), JsonValue::String(String::from("update"))));
+                    members.push(target_to_json(target));
+                    let cells = cells.iter()
+                        .map(|cell| JsonValue::Object(vec![
+                            (String::from("column"), JsonValue::String(cell.header_name.clone())),
+                            (String::from("old"), JsonValue::String(cell.old_value.clone())),
+                            (String::from("new"), JsonValue::String(cell.new_value.clone())),
+                        ]))
+                        .collect();
+                    members.push((String::from("cells"), JsonValue::Array(cells)));
+                }
+            }
+            operations.push(JsonValue::Object(members));
+        }
+
+        JsonValue::Object(vec![
+            (String::from("key_columns"), JsonValue::Array(self.key_columns.iter()
+                .map(|name| JsonValue::String(name.clone()))
+                .collect())),
+            (String::from("operations"), JsonValue::Array(operations)),
+        ]).to_json()
+    }
+
+    pub fn from_json(text: &str) -> Result<CsvPatch, String> {
+        let value = JsonValue::parse(text)?;
+        let key_columns = match value.get("key_columns") {
+            Some(JsonValue::Array(values)) => json_strings(values)?,
+            None => Vec::new(),
+            _ => return Err(String::from("パッチの読み込みに失敗しました。[key_columnsが不正です。]")),
+        };
+        let values = match value.get("operations") {
+            Some(JsonValue::Array(values)) => values,
+            _ => return Err(String::from("パッチの読み込みに失敗しました。[operationsが不正です。]")),
+        };
+
+        let mut operations = Vec::with_capacity(values.len());
+        for (seq, value) in values.iter().enumerate() {
+            match json_to_operation(value) {
+                Ok(operation) => operations.push(operation),
+                Err(e) => return Err(format!("パッチの読み込みに失敗しました。操作=[{}], [{}]", seq, e)),
+            }
+        }
+
+        Ok(CsvPatch {key_columns, operations})
+    }
+
+    // 1セル1行のCsvFileにする
+    // ヘッダーはseq, op, index, position, key:キー列, column, old_value, new_value
+    // セルのない操作はcolumnを空にした1行にする
+    pub fn to_csv(&self) -> CsvFile {
+        let mut header_names = vec![String::from("seq"), String::from("op"), String::from("index"), String::from("position")];
+        header_names.extend(self.key_columns.iter().map(|name| format!("key:{}", name)));
+        header_names.extend([
+            String::from("column"),
+            String::from("old_value"),
+            String::from("new_value"),
+        ]);
+
+        let mut rows = Vec::new();
+        for (seq, operation) in self.operations.iter().enumerate() {
+            let (op, index, key) = match operation {
+                PatchOperation::Insert {index, row} => ("insert", *index, self.pairs_key(row)),
+                PatchOperation::Delete {target, row, ..} => match target {
+                    RowTarget::Index(index) => ("delete", Some(*index), self.pairs_key(row)),
+                    RowTarget::Key(key) => ("delete", None, key.clone()),
+                },
+                PatchOperation::Update {target, ..} => match target {
+                    RowTarget::Index(index) => ("update", Some(*index), vec![String::new(); self.key_columns.len()]),
+                    RowTarget::Key(key) => ("update", None, key.clone()),
+                },
+            };
+            let position = match operation {
+                PatchOperation::Delete {position, ..} => *position,
+                _ => None,
+            };
+            let mut cells: Vec<(String, String, String)> = match operation {
+                PatchOperation::Insert {row, ..} => row.iter()
+                    .map(|(name, value)| (name.clone(), String::new(), value.clone()))
+                    .collect(),
+                PatchOperation::Delete {row, ..} => row.iter()
+                    .map(|(name, value)| (name.clone(), value.clone(), String::new()))
+                    .collect(),
+                PatchOperation::Update {cells, ..} => cells.iter()
+                    .map(|cell| (cell.header_name.clone(), cell.old_value.clone(), cell.new_value.clone()))
+                    .collect(),
+            };
+            if cells.is_empty() {
+                cells.push((String::new(), String::new(), String::new()));
+            }
+            for (column, old_value, new_value) in cells {
+                let mut values = vec![
+                    seq.to_string(),
+                    op.to_string(),
+                    index.map(|index| index.to_string()).unwrap_or_default(),
+                    position.map(|position| position.to_string()).unwrap_or_default(),
+                ];
+                values.extend(key.iter().cloned());
+                values.extend([column, old_value, new_value]);
+                rows.push(values);
+            }
+        }
+
+        CsvFile::from_rows(header_names, rows)
+    }
+
+    // positionの列がない場合は削除する時点の行番号を記録しない
+    pub fn from_csv(csv_file: &CsvFile) -> Result<CsvPatch, String> {
+        let names = &csv_file.csv_header.name;
+        for name in ["seq", "op", "index", "column", "old_value", "new_value"] {
+            if !names.iter().any(|header_name| header_name == name) {
+                return Err(format!("パッチの読み込みに失敗しました。[存在しないヘッダー名です。[{}]]", name));
+            }
+        }
+        let key_columns: Vec<String> = names.iter()
+            .filter_map(|name| name.strip_prefix("key:"))
+            .map(|name| name.to_string())
+            .collect();
+
+        let mut operations: Vec<PatchOperation> = Vec::new();
+        let mut current_seq: Option<String> = None;
+        for (row_index, row) in csv_file.csv_body.rows.iter().enumerate() {
+            let seq = row.get_value("seq")?;
+            let op = row.get_value("op")?;
+            let index = row.get_value("index")?;
+            let column = row.get_value("column")?;
+            let old_value = row.get_value("old_value")?;
+            let new_value = row.get_value("new_value")?;
+
+            // seqが変わったら新しい操作を開始する
+            if current_seq.as_ref() != Some(&seq) {
+                let index = parse_index(&index, row_index)?;
+                let position = match names.iter().any(|name| name == "position") {
+                    true => parse_index(&row.get_value("position")?, row_index)?,
+                    false => None,
+                };
+                let mut key = Vec::with_capacity(key_columns.len());
+                for name in &key_columns {
+                    key.push(row.get_value(&format!("key:{}", name))?);
+                }
+                let target = match index {
+                    Some(index) => RowTarget::Index(index),
+                    None => RowTarget::Key(key),
+                };
+                let operation = match op.as_str() {
+                    "insert" => PatchOperation::Insert {index, row: Vec::new()},
+                    "delete" => PatchOperation::Delete {target, position, row: Vec::new()},
+                    "update" => PatchOperation::Update {target, cells: Vec::new()},
+                    _ => return Err(format!("パッチの読み込みに失敗しました。行=[{}], [不正な操作です。[{}]]"
+                                            , row_index, op)),
+                };
+                operations.push(operation);
+                current_seq = Some(seq);
+            }
+
+            // セルのない操作
+            if column.is_empty() {
+                continue;
+            }
+            match operations.last_mut() {
+                Some(PatchOperation::Insert {row, ..}) => row.push((column, new_value)),
+                Some(PatchOperation::Delete {row, ..}) => row.push((column, old_value)),
+                Some(PatchOperation::Update {cells, ..}) => {
+                    cells.push(CellChange::new(&column, &old_value, &new_value))
+                }
+                None => {}
+            }
+        }
+
+        Ok(CsvPatch {key_columns, operations})
+    }
+}
+
+impl CsvDiff {
+    // 古いCsvFileを新しいCsvFileの行に更新するパッチを作成する
+    // 列の追加・削除はパッチに含まれない
+    pub fn to_patch(&self) -> CsvPatch {
+        let mut patch = CsvPatch {key_columns: self.key_columns.clone(), operations: Vec::new()};
+        // 前の行から順に削除するため、削除する時点の行番号は先に削除した行の数だけ小さくなる
+        for (count, (index, row)) in self.removed_indexes.iter().zip(&self.removed).enumerate() {
+            patch.push(PatchOperation::Delete {
+                target: RowTarget::Key(patch.row_key(row)),
+                position: Some(index - count),
+                row: row_to_pairs(row),
+            });
+        }
+        for row_change in &self.changed {
+            patch.push(PatchOperation::Update {
+                target: RowTarget::Key(row_change.key.clone()),
+                cells: row_change.cells.clone(),
+            });
+        }
+        for (index, row) in self.added_indexes.iter().zip(&self.added) {
+            patch.push(PatchOperation::Insert {index: Some(*index), row: row_to_pairs(row)});
+        }
+        patch
+    }
+}
+
+// 空の場合はNone
+fn parse_index(value: &str, row_index: usize) -> Result<Option<usize>, String> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    match value.parse::<usize>() {
+        Ok(index) => Ok(Some(index)),
+        Err(_) => Err(format!("パッチの読み込みに失敗しました。行=[{}], [不正なインデックスです。[{}]]", row_index, value)),
+    }
+}
+
+fn row_to_pairs(row: &CsvRow) -> Vec<(String, String)> {
+    row.data.iter()
+        .map(|csv_data| (csv_data.header_name.clone(), csv_data.value.clone()))
+        .collect()
+}
+
+fn pairs_to_json(row: &[(String, String)]) -> JsonValue {
+    JsonValue::Object(row.iter()
+        .map(|(name, value)| (name.clone(), JsonValue::String(value.clone())))
+        .collect())
+}
+
+fn target_to_json(target: &RowTarget) -> (String, JsonValue) {
+    match target {
+        RowTarget::Index(index) => (String::from("index"), JsonValue::Number(index.to_string())),
+        RowTarget::Key(key) => (String::from("key"), JsonValue::Array(key.iter()
+            .map(|value| JsonValue::String(value.clone()))
+            .collect())),
+    }
+}
+
+fn json_strings(values: &[JsonValue]) -> Result<Vec<String>, String> {
+    let mut strings = Vec::with_capacity(values.len());
+    for value in values {
+        match value.as_str() {
+            Some(value) => strings.push(value.to_string()),
+            None => return Err(String::from("文字列ではない値があります。")),
+        }
+    }
+    Ok(strings)
+}
+
+fn json_to_pairs(value: Option<&JsonValue>) -> Result<Vec<(String, String)>, String> {
+    match value {
+        Some(JsonValue::Object(members)) => {
+            let mut row = Vec::with_capacity(members.len());
+            for (name, value) in members {
+                match value.as_str() {
+                    Some(value) => row.push((name.clone(), value.to_string())),
+                    None => return Err(format!("文字列ではない値があります。[{}]", name)),
+                }
+            }
+            Ok(row)
+        }
+        _ => Err(String::from("rowが不正です。")),
+    }
+}
+
+fn json_to_target(value: &JsonValue) -> Result<RowTarget, String> {
+    if let Some(index) = value.get("index") {
+        return match index.as_usize() {
+            Some(index) => Ok(RowTarget::Index(index)),
+            None => Err(String::from("indexが不正です。")),
+        };
+    }
+    match value.get("key") {
+        Some(JsonValue::Array(values)) => Ok(RowTarget::Key(json_strings(values)?)),
+        _ => Err(String::from("keyまたはindexがありません。")),
+    }
+}
+
+fn json_to_operation(value: &JsonValue) -> Result<PatchOperation, String> {
+    match value.get("op").and_then(|op| op.as_str()) {
+        Some("insert") => {
+            let index = match value.get("index") {
+                Some(index) => match index.as_usize() {
+                    Some(index) => Some(index),
+                    None => return Err(String::from("indexが不正です。")),
+                },
+                None => None,
+            };
+            Ok(PatchOperation::Insert {index, row: json_to_pairs(value.get("row"))?})
+        }
+        Some("delete") => {
+            let position = match value.get("position") {
+                Some(position) => match position.as_usize() {
+                    Some(position) => Some(position),
+                    None => return Err(String::from("positionが不正です。")),
+                },
+                None => None,
+            };
+            Ok(PatchOperation::Delete {target: json_to_target(value)?, position, row: json_to_pairs(value.get("row"))?})
+        }
+        Some("update") => {
+            let values = match value.get("cells") {
+                Some(JsonValue::Array(values)) => values,
+                _ => return Err(String::from("cellsが不正です。")),
+            };
+            let mut cells = Vec::with_capacity(values.len());
+            for cell in values {
+                let column = cell.get("column").and_then(|value| value.as_str());
+                let old_value = cell.get("old").and_then(|value| value.as_str());
+                let new_value = cell.get("new").and_then(|value| value.as_str());
+                match (column, old_value, new_value) {
+                    (Some(column), Some(old_value), Some(new_value)) => {
+                        cells.push(CellChange::new(column, old_value, new_value))
+                    }
+                    _ => return Err(String::from("cellsが不正です。")),
+                }
+            }
+            Ok(PatchOperation::Update {target: json_to_target(value)?, cells})
+        }
+        _ => Err(String::from("opが不正です。")),
+    }
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff;
+
+    #[test]
+    fn patch_apply_diff() {
+        let mut old = make_old();
+        let new = make_new();
+        let patch = diff(&old, &new, &["id"]).unwrap().to_patch();
+
+        patch.apply(&mut old).unwrap();
+
+        assert_rows(&[
+            ["1", "いるかねこ"],
+            ["4", "おけぶろ"],
+            ["3", "すもっく"],
+        ], &old);
+    }
+
+    #[test]
+    fn patch_invert() {
+        let mut file = make_old();
+        let patch = diff(&file, &make_new(), &["id"]).unwrap().to_patch();
+
+        patch.apply(&mut file).unwrap();
+        patch.invert().unwrap().apply(&mut file).unwrap();
+
+        // キー指定で削除した行も元の位置に戻る
+        assert_rows(&[
+            ["1", "いるかねこ"],
+            ["2", "船長うさぎ"],
+            ["3", "やかまし"],
+        ], &file);
+    }
+
+    #[test]
+    fn patch_invert_removed_rows() {
+        let mut file = make(&[["1", "いるかねこ"], ["2", "船長うさぎ"], ["3", "やかまし"], ["4", "おけぶろ"], ["5", "すもっく"]]);
+        let new = make(&[["6", "いぬねこ"], ["1", "いるかねこ"], ["3", "やかまし"], ["5", "すもっく"]]);
+        let patch = diff(&file, &new, &["id"]).unwrap().to_patch();
+
+        patch.apply(&mut file).unwrap();
+        assert_rows(&[["6", "いぬねこ"], ["1", "いるかねこ"], ["3", "やかまし"], ["5", "すもっく"]], &file);
+
+        patch.invert().unwrap().apply(&mut file).unwrap();
+        assert_rows(&[["1", "いるかねこ"], ["2", "船長うさぎ"], ["3", "やかまし"], ["4", "おけぶろ"], ["5", "すもっく"]], &file);
+    }
+
+    #[test]
+    fn patch_index() {
+        let mut file = make_old();
+        let mut patch = CsvPatch::new(&[]);
+        patch.push(PatchOperation::Update {
+            target: RowTarget::Index(1),
+            cells: vec![CellChange::new("name", "船長うさぎ", "いぬねこ")],
+        });
+        patch.push(PatchOperation::Delete {
+            target: RowTarget::Index(0),
+            position: None,
+            row: vec![(String::from("id"), String::from("1")), (String::from("name"), String::from("いるかねこ"))],
+        });
+        patch.push(PatchOperation::Insert {
+            index: Some(1),
+            row: vec![(String::from("name"), String::from("おけぶろ")), (String::from("id"), String::from("5"))],
+        });
+
+        patch.apply(&mut file).unwrap();
+        assert_rows(&[["2", "いぬねこ"], ["5", "おけぶろ"], ["3", "やかまし"]], &file);
+
+        patch.invert().unwrap().apply(&mut file).unwrap();
+        assert_rows(&[["1", "いるかねこ"], ["2", "船長うさぎ"], ["3", "やかまし"]], &file);
+    }
+
+    #[test]
+    fn patch_apply_error_conflict() {
+        let mut file = make_old();
+        let mut patch = CsvPatch::new(&["id"]);
+        patch.push(PatchOperation::Delete {
+            target: RowTarget::Key(vec![String::from("1")]),
+            position: None,
+            row: vec![(String::from("id"), String::from("1"))],
+        });
+        patch.push(PatchOperation::Update {
+            target: RowTarget::Key(vec![String::from("2")]),
+            cells: vec![CellChange::new("name", "いぬねこ", "おけぶろ")],
+        });
+
+        match patch.apply(&mut file) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("パッチの適用に失敗しました。操作=[1], \
+                                  [行の内容が一致しません。列=[name], 期待値=[いぬねこ], 現在値=[船長うさぎ]]", e),
+        }
+        // エラーの場合は変更されない
+        assert_rows(&[["1", "いるかねこ"], ["2", "船長うさぎ"], ["3", "やかまし"]], &file);
+    }
+
+    #[test]
+    fn patch_apply_rollback() {
+        let mut file = make_old();
+        file.set_primary_key(&["id"]).unwrap();
+        let mut patch = CsvPatch::new(&["id"]);
+        patch.push(PatchOperation::Update {
+            target: RowTarget::Key(vec![String::from("3")]),
+            cells: vec![CellChange::new("id", "3", "5")],
+        });
+        patch.push(PatchOperation::Delete {
+            target: RowTarget::Key(vec![String::from("1")]),
+            position: None,
+            row: vec![(String::from("id"), String::from("1"))],
+        });
+        patch.push(PatchOperation::Insert {
+            index: Some(0),
+            row: vec![(String::from("id"), String::from("3")), (String::from("name"), String::from("おけぶろ"))],
+        });
+        // 変更後のキーで指定できる
+        patch.push(PatchOperation::Update {
+            target: RowTarget::Key(vec![String::from("5")]),
+            cells: vec![CellChange::new("name", "やかまし", "すもっく")],
+        });
+        patch.push(PatchOperation::Delete {
+            target: RowTarget::Key(vec![String::from("1")]),
+            position: None,
+            row: vec![(String::from("id"), String::from("1"))],
+        });
+
+        match patch.apply(&mut file) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("パッチの適用に失敗しました。操作=[4], [存在しないキーです。[1]]", e),
+        }
+        // 適用した操作は取り消され、主キーの索引も元に戻る
+        assert_rows(&[["1", "いるかねこ"], ["2", "船長うさぎ"], ["3", "やかまし"]], &file);
+        assert_eq!("やかまし", file.get_by_key(&["3"]).unwrap().get_value("name").unwrap());
+
+        patch.operations.pop();
+        patch.apply(&mut file).unwrap();
+        assert_rows(&[["3", "おけぶろ"], ["2", "船長うさぎ"], ["5", "すもっく"]], &file);
+        assert_eq!("すもっく", file.get_by_key(&["5"]).unwrap().get_value("name").unwrap());
+        assert_eq!("おけぶろ", file.get_by_key(&["3"]).unwrap().get_value("name").unwrap());
+        assert!(file.get_by_key(&["1"]).is_err());
+    }
+
+    #[test]
+    fn patch_apply_error_duplicate_key() {
+        let mut file = make_old();
+        let mut patch = CsvPatch::new(&["id"]);
+        patch.push(PatchOperation::Insert {
+            index: None,
+            row: vec![(String::from("id"), String::from("2")), (String::from("name"), String::from("おけぶろ"))],
+        });
+
+        match patch.apply(&mut file) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("パッチの適用に失敗しました。操作=[0], [キーが重複しています。[2]]", e),
+        }
+    }
+
+    #[test]
+    fn patch_json() {
+        let patch = diff(&make_old(), &make_new(), &["id"]).unwrap().to_patch();
+
+        let json = patch.to_json();
+        assert_eq!(r#"{"key_columns":["id"],"operations":["#.to_string()
+                       + r#"{"op":"delete","key":["2"],"position":1,"row":{"id":"2","name":"船長うさぎ"}},"#
+                       + r#"{"op":"update","key":["3"],"cells":[{"column":"name","old":"やかまし","new":"すもっく"}]},"#
+                       + r#"{"op":"insert","index":1,"row":{"id":"4","name":"おけぶろ"}}]}"#, json);
+        assert_eq!(patch, CsvPatch::from_json(&json).unwrap());
+    }
+
+    #[test]
+    fn patch_csv() {
+        let patch = diff(&make_old(), &make_new(), &["id"]).unwrap().to_patch();
+
+        let csv = patch.to_csv();
+        let header = csv.get_header();
+        let expect = ["seq", "op", "index", "position", "key:id", "column", "old_value", "new_value"];
+        for (index, name) in expect.iter().enumerate() {
+            assert_eq!(*name, header.get_name(index).unwrap());
+        }
+        assert_eq!(5, csv.get_body().len());
+        assert_eq!(patch, CsvPatch::from_csv(&csv).unwrap());
+    }
+
+    #[test]
+    fn patch_csv_without_cells() {
+        // セルのない操作も失われない
+        let mut patch = CsvPatch::new(&["id"]);
+        patch.push(PatchOperation::Delete {target: RowTarget::Key(vec![String::from("2")]), position: Some(1), row: Vec::new()});
+        patch.push(PatchOperation::Update {target: RowTarget::Key(vec![String::from("3")]), cells: Vec::new()});
+        patch.push(PatchOperation::Insert {index: None, row: vec![(String::from("id"), String::from("4"))]});
+
+        let csv = patch.to_csv();
+
+        assert_eq!(3, csv.get_body().len());
+        assert_eq!("", csv.get_value("column", 0).unwrap());
+        assert_eq!(patch, CsvPatch::from_csv(&csv).unwrap());
+    }
+
+    #[test]
+    fn patch_from_json_error() {
+        match CsvPatch::from_json(r#"{"operations":[{"op":"move"}]}"#) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("パッチの読み込みに失敗しました。操作=[0], [opが不正です。]", e),
+        }
+    }
+
+    fn assert_rows(expect: &[[&str; 2]], file: &CsvFile) {
+        assert_eq!(expect.len(), file.get_body().len());
+        for (index, values) in expect.iter().enumerate() {
+            assert_eq!(values[0], file.get_value("id", index).unwrap());
+            assert_eq!(values[1], file.get_value("name", index).unwrap());
+        }
+    }
+
+    fn make_old() -> CsvFile {
+        make(&[["1", "いるかねこ"], ["2", "船長うさぎ"], ["3", "やかまし"]])
+    }
+
+    fn make_new() -> CsvFile {
+        make(&[["1", "いるかねこ"], ["4", "おけぶろ"], ["3", "すもっく"]])
+    }
+
+    fn make(rows: &[[&str; 2]]) -> CsvFile {
+        CsvFile::from_rows(
+            vec![String::from("id"), String::from("name")],
+            rows.iter().map(|row| row.iter().map(|value| value.to_string()).collect()).collect(),
+        )
+    }
+}
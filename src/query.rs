@@ -0,0 +1,318 @@
+use rusqlite::Connection;
+
+use crate::{read_with, ColumnType, CsvDialect, CsvFile, CsvSchema};
+
+// CsvFileをテーブルとしてSQLを実行する
+// 実行のたびにメモリ上のSQLiteにテーブルを作成するため、元のCsvFileは変更されない
+pub struct CsvQuery {
+    // テーブル名、内容、列の型(Noneの場合は値を変えない型を推定する)
+    tables: Vec<(String, CsvFile, Option<CsvSchema>)>,
+    // SQLで指定したファイルを読み込むか
+    read_files: bool,
+}
+impl CsvQuery {
+    pub fn new() -> Self {
+        Self {tables: Vec::new(), read_files: true}
+    }
+
+    // 信頼できないSQLを実行する場合はfalseにし、登録したテーブルだけを使用する
+    pub fn set_read_files(&mut self, read_files: bool) {
+        self.read_files = read_files;
+    }
+
+    // 同じ名前のテーブルは置き換える
+    pub fn register(&mut self, name: &str, csv_file: CsvFile) {
+        self.register_table(name, csv_file, None);
+    }
+
+    // 列の型を指定して登録する
+    pub fn register_with_schema(&mut self, name: &str, csv_file: CsvFile, schema: CsvSchema) {
+        self.register_table(name, csv_file, Some(schema));
+    }
+
+    fn register_table(&mut self, name: &str, csv_file: CsvFile, schema: Option<CsvSchema>) {
+        match self.tables.iter_mut().find(|(table, _, _)| table == name) {
+            Some(table) => *table = (name.to_string(), csv_file, schema),
+            None => self.tables.push((name.to_string(), csv_file, schema)),
+        }
+    }
+
+    // FROMとJOINの直後に'sales.csv'のように引用符で囲んで指定した名前が
+    // 登録されたテーブルでもWITHで定義した名前でもなく、拡張子が.csv, .tsv(圧縮の拡張子も可)の場合は
+    // ファイルとして読み込む(拡張子が.tsvの場合はタブ区切り)
+    // 列の型を指定しないテーブルは、値を変えずに読み戻せる列だけを数値の列にする
+    pub fn execute(&self, sql: &str) -> Result<CsvFile, String> {
+        let mut files = Vec::new();
+        let references = if self.read_files { table_references(sql) } else { Vec::new() };
+        for name in references {
+            if self.tables.iter().any(|(table, _, _)| *table == name) || files.iter().any(|(file, _, _)| *file == name) {
+                continue;
+            }
+            let dialect = if file_extension(&name) == Some(".tsv") { CsvDialect::tsv() } else { CsvDialect::default() };
+            match read_with(&name, &dialect) {
+                Ok(csv_file) => files.push((name, csv_file, None)),
+                Err(e) => return Err(format!("SQLの実行に失敗しました。[{}]", e)),
+            }
+        }
+
+        let mut conn = match Connection::open_in_memory() {
+            Ok(conn) => conn,
+            Err(e) => return Err(format!("SQLの実行に失敗しました。[{}]", e)),
+        };
+        for (name, csv_file, schema) in self.tables.iter().chain(files.iter()) {
+            let schema = match schema {
+                Some(schema) => schema.clone(),
+                None => preserving_schema(csv_file),
+            };
+            if let Err(e) = csv_file.to_sqlite(&mut conn, name, &schema) {
+                return Err(format!("SQLの実行に失敗しました。テーブル=[{}], [{}]", name, e));
+            }
+        }
+        match CsvFile::from_sqlite_query(&conn, sql) {
+            Ok(csv_file) => Ok(csv_file),
+            Err(e) => Err(format!("SQLの実行に失敗しました。[{}]", e)),
+        }
+    }
+}
+impl Default for CsvQuery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// 値が変わらない型を推定し、SQLiteでは0と1になる真偽値の列も文字列にする
+fn preserving_schema(csv_file: &CsvFile) -> CsvSchema {
+    let mut schema = CsvSchema::new();
+    for (name, column_type) in CsvSchema::infer_preserving(csv_file).columns() {
+        schema.push(name, if *column_type == ColumnType::Boolean { ColumnType::String } else { *column_type });
+    }
+    schema
+}
+
+// ファイルのテーブルだけを使用してSQLを実行する
+pub fn query(sql: &str) -> Result<CsvFile, String> {
+    CsvQuery::new().execute(sql)
+}
+
+// SQLの字句
+#[derive(Debug, PartialEq)]
+enum Token {
+    // 大文字にした単語
+    Word(String),
+    // 引用符で囲まれた名前
+    Quoted(String),
+    Symbol(char),
+}
+
+// FROM、JOINの直後の引用符で囲まれた名前のうち、ファイルのパスとみなす名前を取り出す
+// WITHで定義した名前は除く
+fn table_references(sql: &str) -> Vec<String> {
+    let tokens = tokenize(sql);
+    let ctes = cte_names(&tokens);
+    let mut references = Vec::new();
+    for pair in tokens.windows(2) {
+        if let [Token::Word(word), Token::Quoted(name)] = pair
+            && (word == "FROM" || word == "JOIN")
+            && file_extension(name).is_some()
+            && !ctes.contains(name) {
+            references.push(name.clone());
+        }
+    }
+    references
+}
+
+// "名前" [(列, ...)] AS ( の形の名前(ファイルとみなすのは引用符で囲まれた名前だけのため、単語は除く)
+fn cte_names(tokens: &[Token]) -> Vec<String> {
+    let mut names = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        let name = match token {
+            Token::Quoted(name) => name,
+            _ => continue,
+        };
+        let mut next = index + 1;
+        // 列名の指定を読み飛ばす
+        if tokens.get(next) == Some(&Token::Symbol('(')) {
+            match tokens[next..].iter().position(|token| *token == Token::Symbol(')')) {
+                Some(position) => next += position + 1,
+                None => continue,
+            }
+        }
+        if tokens.get(next) == Some(&Token::Word(String::from("AS"))) && tokens.get(next + 1) == Some(&Token::Symbol('(')) {
+            names.push(name.clone());
+        }
+    }
+    names
+}
+
+fn tokenize(sql: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => {
+                let mut name = String::new();
+                while let Some(next) = chars.next() {
+                    if next == c {
+                        // 引用符を2つ続けた場合は引用符そのもの
+                        if chars.peek() == Some(&c) {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                    name.push(next);
+                }
+                tokens.push(Token::Quoted(name));
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = String::from(c);
+                while let Some(next) = chars.peek() {
+                    if !(next.is_alphanumeric() || *next == '_') {
+                        break;
+                    }
+                    word.push(*next);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word.to_uppercase()));
+            }
+            c if c.is_whitespace() => {}
+            c => tokens.push(Token::Symbol(c)),
+        }
+    }
+    tokens
+}
+
+// 圧縮の拡張子を除いた拡張子(.csvか.tsvの場合のみ)
+fn file_extension(name: &str) -> Option<&'static str> {
+    let mut name = name.to_lowercase();
+    for suffix in [".gz", ".gzip", ".zst", ".zstd", ".bz2"] {
+        if let Some(stripped) = name.strip_suffix(suffix) {
+            name = stripped.to_string();
+            break;
+        }
+    }
+    [".csv", ".tsv"].into_iter().find(|extension| name.ends_with(extension))
+}
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_group_by() {
+        let mut query = CsvQuery::new();
+        query.register("sales", make_sales());
+
+        let result = query.execute(
+            "SELECT region, sum(amount) AS total, count(*) AS count FROM sales WHERE amount > 0 GROUP BY region ORDER BY total DESC"
+        ).unwrap();
+
+        assert_eq!(vec!["region", "total", "count"], result.get_header().name);
+        assert_eq!(vec!["東京", "300", "2"], result.get_body().get_row(0).unwrap().values());
+        assert_eq!(vec!["大阪", "50", "1"], result.get_body().get_row(1).unwrap().values());
+        assert_eq!(2, result.get_body().len());
+    }
+
+    #[test]
+    fn query_preserves_values() {
+        let csv = CsvFile::from_rows(
+            vec![String::from("郵便番号"), String::from("有効"), String::from("単価"), String::from("数量")],
+            vec![
+                vec![String::from("00123"), String::from("true"), String::from("1.50"), String::from("3")],
+                vec![String::from("45678"), String::from("false"), String::from("2"), String::from("")],
+            ],
+        );
+        let mut query = CsvQuery::new();
+        query.register("住所", csv.clone());
+
+        let result = query.execute("SELECT * FROM 住所").unwrap();
+
+        assert_eq!(csv.get_header().name, result.get_header().name);
+        assert_eq!(csv.get_body().get_row(0).unwrap().values(), result.get_body().get_row(0).unwrap().values());
+        assert_eq!(csv.get_body().get_row(1).unwrap().values(), result.get_body().get_row(1).unwrap().values());
+        // 値が変わらない列は数値として比較する
+        let result = query.execute("SELECT 郵便番号 FROM 住所 WHERE 数量 > 2").unwrap();
+        assert_eq!(vec!["00123"], result.get_body().get_row(0).unwrap().values());
+
+        // 列の型を指定した場合はその型で登録する
+        let mut schema = CsvSchema::new();
+        schema.push("郵便番号", ColumnType::Integer);
+        query.register_with_schema("住所", csv, schema);
+        let result = query.execute("SELECT 郵便番号 FROM 住所").unwrap();
+        assert_eq!(vec!["123"], result.get_body().get_row(0).unwrap().values());
+    }
+
+    #[test]
+    fn query_file_join() {
+        let mut query = CsvQuery::new();
+        query.register("sales", make_sales());
+
+        let result = query.execute(
+            r#"SELECT s."ヘッダー1", sales.region FROM 'test/test.csv' AS s JOIN sales ON sales.amount = 100 LIMIT 1"#
+        ).unwrap();
+
+        assert_eq!(vec!["ヘッダー1", "region"], result.get_header().name);
+        assert_eq!(vec!["いるかねこ", "東京"], result.get_body().get_row(0).unwrap().values());
+    }
+
+    #[test]
+    fn query_error() {
+        match query("SELECT * FROM 'test/not_found.csv'") {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert!(e.starts_with("SQLの実行に失敗しました。[openに失敗しました。"), "{}", e),
+        }
+        match CsvQuery::new().execute("SELECT * FROM sales") {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("SQLの実行に失敗しました。[SQLiteからの読み込みに失敗しました。[no such table: sales]]", e),
+        }
+    }
+
+    #[test]
+    fn table_references_quoted() {
+        assert_eq!(vec!["a.csv", "b\"c.tsv"],
+                   table_references(r#"select 'x' from 'a.csv' join "b""c.tsv" on 1 where v = 'd.csv'"#));
+        // ファイルのパスでない名前とWITHで定義した名前は除く
+        assert_eq!(vec!["data/売上.CSV.gz"],
+                   table_references(r#"WITH "t.csv"(a) AS (SELECT 1), u AS (SELECT 2) SELECT * FROM "t.csv" JOIN 'u' JOIN "/etc/passwd" JOIN 'data/売上.CSV.gz'"#));
+    }
+
+    #[test]
+    fn query_cte() {
+        let result = query(r#"WITH "t" AS (SELECT 1 AS a) SELECT * FROM "t""#).unwrap();
+
+        assert_eq!(vec!["1"], result.get_body().get_row(0).unwrap().values());
+    }
+
+    #[test]
+    fn query_without_files() {
+        let mut query = CsvQuery::new();
+        query.set_read_files(false);
+
+        match query.execute("SELECT * FROM 'test/test.csv'") {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("SQLの実行に失敗しました。[SQLiteからの読み込みに失敗しました。[no such table: test/test.csv]]", e),
+        }
+    }
+
+    fn make_sales() -> CsvFile {
+        CsvFile::from_rows(
+            vec![String::from("region"), String::from("amount")],
+            vec![
+                vec![String::from("東京"), String::from("100")],
+                vec![String::from("大阪"), String::from("50")],
+                vec![String::from("東京"), String::from("200")],
+                vec![String::from("大阪"), String::from("-10")],
+            ],
+        )
+    }
+}
@@ -0,0 +1,433 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+
+use crate::compression;
+use crate::dialect::{format_record, split_record, RawRecord, RecordReader, UNCLOSED_QUOTE};
+use crate::error::RecordPosition;
+use crate::{build_csv_row, parse_header, write_with as write_to_path, CsvBody, CsvDialect, CsvFile, CsvHeader, CsvRow, ParseError};
+
+// 項目数がヘッダーと異なる行の扱い
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum RaggedPolicy {
+    // 項目数が異なる行はエラー
+    #[default]
+    Strict,
+    // 足りない項目は空の値で補う(多い場合はエラー)
+    Pad,
+    // 多い項目は切り捨て、足りない項目は空の値で補う
+    Truncate,
+    // 多い項目は区切り文字でつないで指定した名前の列に入れ、足りない項目は空の値で補う
+    Overflow(String),
+}
+
+// 補正の内容
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Repair {
+    Padded,
+    Truncated,
+    Overflowed,
+}
+
+// 補正した行
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowRepair {
+    // 0から始まるデータの行番号
+    pub row: usize,
+    // 1から始まる物理的な行番号
+    pub line: usize,
+    // 元の項目数
+    pub fields: usize,
+    pub repair: Repair,
+}
+
+// 解析できないレコードの扱い
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ErrorPolicy {
+    // 最初のエラーで読み込みを中止する
+    #[default]
+    Fail,
+    // エラーのレコードを除外して読み込みを続ける
+    Reject,
+}
+
+// 除外したレコード
+#[derive(Clone, Debug, PartialEq)]
+pub struct RejectedRecord {
+    // レコードの内容(改行を除く)
+    pub text: String,
+    pub error: ParseError,
+}
+
+// 読み込みの設定
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReadOptions {
+    pub dialect: CsvDialect,
+    pub ragged: RaggedPolicy,
+    // ヘッダーと読み込み自体のエラーはRejectでも中止する
+    pub on_error: ErrorPolicy,
+    // 除外したレコードを<ファイル名>.rejects.csvに書き込む(read_with_optionsのみ)
+    pub write_rejects: bool,
+}
+
+// 読み込み時に補正、除外した内容
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReadReport {
+    repairs: Vec<RowRepair>,
+    rejects: Vec<RejectedRecord>,
+}
+impl ReadReport {
+    pub fn repairs(&self) -> &[RowRepair] {
+        &self.repairs
+    }
+
+    pub fn rejects(&self) -> &[RejectedRecord] {
+        &self.rejects
+    }
+
+    pub fn len(&self) -> usize {
+        self.repairs.len() + self.rejects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.repairs.is_empty() && self.rejects.is_empty()
+    }
+
+    // 除外したレコードの一覧
+    // 物理的な行番号、レコード番号、理由、レコードの内容の列
+    pub fn rejects_to_csv(&self) -> CsvFile {
+        CsvFile::from_rows(
+            vec![String::from("行"), String::from("レコード"), String::from("理由"), String::from("内容")],
+            self.rejects.iter()
+                .map(|reject| vec![reject.error.line.to_string(), reject.error.record.to_string(),
+                                   reject.error.message.clone(), reject.text.clone()])
+                .collect(),
+        )
+    }
+}
+impl fmt::Display for ReadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for repair in &self.repairs {
+            let name = match repair.repair {
+                Repair::Padded => "補完",
+                Repair::Truncated => "切り捨て",
+                Repair::Overflowed => "超過列",
+            };
+            writeln!(f, "行=[{}], 物理行=[{}], 項目数=[{}], [{}]", repair.row, repair.line, repair.fields, name)?;
+        }
+        for reject in &self.rejects {
+            writeln!(f, "物理行=[{}], レコード=[{}], [除外], [{}]", reject.error.line, reject.error.record, reject.error.message)?;
+        }
+        Ok(())
+    }
+}
+
+// 除外したレコードを書き込むファイルのパス
+// sales.csvの場合はsales.rejects.csv
+fn rejects_path(path: &str) -> String {
+    let stem = match path.len().checked_sub(4) {
+        Some(index) if path.is_char_boundary(index) && path[index..].eq_ignore_ascii_case(".csv") => &path[..index],
+        _ => path,
+    };
+    format!("{}.rejects.csv", stem)
+}
+
+// 圧縮されたファイルは先頭のバイト列から形式を判定して展開する
+pub fn read_with_options(path: &str, options: &ReadOptions) -> Result<(CsvFile, ReadReport), String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("openに失敗しました。{}", e)),
+    };
+
+    let mut reader = BufReader::new(file);
+    let compression = compression::detect(&mut reader)?;
+    let (csv_file, report) = read_from_with_options(compression::decoder(reader, compression)?, options)?;
+    // 除外したレコードがない場合も書き込み、前回の内容を残さない
+    if options.write_rejects && options.on_error == ErrorPolicy::Reject {
+        write_to_path(&rejects_path(path), &report.rejects_to_csv(), &options.dialect)?;
+    }
+    Ok((csv_file, report))
+}
+
+pub fn read_from_with_options<R: Read>(reader: R, options: &ReadOptions) -> Result<(CsvFile, ReadReport), String> {
+    options.dialect.validate()?;
+
+    let mut reading = Reading {csv_header: CsvHeader::new(), width: 0, csv_body: CsvBody::new(), report: ReadReport::default()};
+    let mut rest = reading.read_records(RecordReader::new(BufReader::new(reader), &options.dialect), options)?;
+    while let Some((text, position)) = rest {
+        rest = reading.read_records(RecordReader::new(text.as_bytes(), &options.dialect).starting_at(position), options)?;
+    }
+
+    Ok((CsvFile::new(reading.csv_header, reading.csv_body), reading.report))
+}
+
+// 読み込み中の内容
+struct Reading {
+    csv_header: CsvHeader,
+    // ファイルのヘッダーの項目数(超過列を除く)
+    width: usize,
+    csv_body: CsvBody,
+    report: ReadReport,
+}
+impl Reading {
+    // レコードを最後まで読み込む
+    // 閉じていない囲み文字のレコードを除外した場合は、読み直す残りの文字列と位置を返す
+    fn read_records<B: BufRead>(&mut self, mut records: RecordReader<B>, options: &ReadOptions)
+        -> Result<Option<(String, RecordPosition)>, String> {
+        let dialect = &options.dialect;
+        while let Some(record) = records.next_record() {
+            let record = record?;
+
+            // 最初のレコードはヘッダにする
+            if record.position.record == 0 {
+                self.csv_header = parse_header(&record, dialect)?;
+                self.width = self.csv_header.len();
+                if let RaggedPolicy::Overflow(name) = &options.ragged {
+                    if self.csv_header.name.contains(name) {
+                        return Err(format!("超過列の名前がヘッダーと重複しています。[{}]", name));
+                    }
+                    self.csv_header.append(name);
+                }
+                continue;
+            }
+
+            let row = self.csv_body.rows.len();
+            match parse_ragged_row(&self.csv_header, self.width, &record, options) {
+                Ok((csv_row, repair)) => {
+                    if let Some((fields, repair)) = repair {
+                        self.report.repairs.push(RowRepair {row, line: record.position.line, fields, repair});
+                    }
+                    self.csv_body.append(csv_row);
+                }
+                Err(error) if options.on_error == ErrorPolicy::Reject => {
+                    let rest = match error.message == UNCLOSED_QUOTE {
+                        true => split_unclosed(record.text, record.position, error.line),
+                        false => (record.text, None),
+                    };
+                    self.report.rejects.push(RejectedRecord {text: rest.0, error});
+                    if rest.1.is_some() {
+                        return Ok(rest.1);
+                    }
+                }
+                Err(error) => return Err(error.into()),
+            }
+        }
+        Ok(None)
+    }
+}
+
+// 閉じていない囲み文字はファイルの終わりまで続くため、囲み文字が始まった行までを除外するレコードにする
+// 残りの行は読み直す
+fn split_unclosed(mut text: String, position: RecordPosition, line: usize) -> (String, Option<(String, RecordPosition)>) {
+    let index = match text.match_indices('\n').nth(line - position.line) {
+        Some((index, _)) => index,
+        None => return (text, None),
+    };
+    let rest = text[index + 1..].to_string();
+    let rest_position = RecordPosition {record: position.record + 1, line: line + 1, offset: position.offset + index + 1};
+    text.truncate(index);
+    if text.ends_with('\r') {
+        text.pop();
+    }
+    (text, Some((rest, rest_position)))
+}
+
+// 項目数をヘッダーに合わせて行を作成する
+// 補正した場合は元の項目数と補正の内容も返す
+fn parse_ragged_row(csv_header: &CsvHeader, width: usize, record: &RawRecord, options: &ReadOptions)
+    -> Result<(CsvRow, Option<(usize, Repair)>), ParseError> {
+    let dialect = &options.dialect;
+    let mut fields = split_record(&record.text, dialect).map_err(|e| e.at(record, dialect))?;
+    let count = fields.len();
+    let mismatch = |field: usize| -> ParseError {
+        record.error(&format!("項目数がヘッダーと一致しません。ヘッダー=[{}], 項目数=[{}]", width, count), Some(field), dialect)
+    };
+    let repair = if count < width {
+        if options.ragged == RaggedPolicy::Strict {
+            return Err(mismatch(count));
+        }
+        fields.resize(width, Cow::Borrowed(""));
+        Some(Repair::Padded)
+    } else if count > width {
+        match &options.ragged {
+            RaggedPolicy::Strict | RaggedPolicy::Pad => return Err(mismatch(width)),
+            RaggedPolicy::Truncate => {
+                fields.truncate(width);
+                Some(Repair::Truncated)
+            }
+            RaggedPolicy::Overflow(_) => {
+                let extras = fields.split_off(width);
+                fields.push(Cow::Owned(format_record(extras.iter().map(|field| field.as_ref()), dialect)));
+                Some(Repair::Overflowed)
+            }
+        }
+    } else {
+        None
+    };
+    // 超過列に入れる項目がない行は空の値にする
+    if matches!(options.ragged, RaggedPolicy::Overflow(_)) && repair != Some(Repair::Overflowed) {
+        fields.push(Cow::Borrowed(""));
+    }
+
+    let csv_row = build_csv_row(csv_header, &fields).map_err(|e| record.error(&e, Some(width), dialect))?;
+    Ok((csv_row, repair.map(|repair| (count, repair))))
+}
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "名前,金額,備考\nいるかねこ,100\n船長うさぎ,200,なし\nやかまし,300,\"a,b\",\"c\",d\n";
+
+    #[test]
+    fn ragged_strict() {
+        match read_from_with_options(TEXT.as_bytes(), &ReadOptions::default()) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert!(e.starts_with("項目数がヘッダーと一致しません。ヘッダー=[3], 項目数=[2] 行=[2], 列=[10], レコード=[1]"), "{}", e),
+        }
+    }
+
+    #[test]
+    fn ragged_pad() {
+        let options = ReadOptions {ragged: RaggedPolicy::Pad, ..Default::default()};
+
+        match read_from_with_options(TEXT.as_bytes(), &options) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert!(e.starts_with("項目数がヘッダーと一致しません。ヘッダー=[3], 項目数=[5] 行=[4], 列=[16], レコード=[3], バイト位置=[91], 項目=[3]"), "{}", e),
+        }
+        let (csv, report) = read_from_with_options(&TEXT.as_bytes()[..TEXT.find("やかまし").unwrap()], &options).unwrap();
+        assert_eq!(vec!["いるかねこ", "100", ""], csv.get_body().get_row(0).unwrap().values());
+        assert_eq!(vec![RowRepair {row: 0, line: 2, fields: 2, repair: Repair::Padded}], report.repairs());
+    }
+
+    #[test]
+    fn ragged_truncate() {
+        let options = ReadOptions {ragged: RaggedPolicy::Truncate, ..Default::default()};
+
+        let (csv, report) = read_from_with_options(TEXT.as_bytes(), &options).unwrap();
+
+        assert_eq!(vec!["名前", "金額", "備考"], csv.get_header().name);
+        assert_eq!(vec!["いるかねこ", "100", ""], csv.get_body().get_row(0).unwrap().values());
+        assert_eq!(vec!["やかまし", "300", "a,b"], csv.get_body().get_row(2).unwrap().values());
+        assert_eq!("行=[0], 物理行=[2], 項目数=[2], [補完]\n行=[2], 物理行=[4], 項目数=[5], [切り捨て]\n", report.to_string());
+    }
+
+    #[test]
+    fn ragged_overflow() {
+        let options = ReadOptions {ragged: RaggedPolicy::Overflow(String::from("超過")), ..Default::default()};
+
+        let (csv, report) = read_from_with_options(TEXT.as_bytes(), &options).unwrap();
+
+        assert_eq!(vec!["名前", "金額", "備考", "超過"], csv.get_header().name);
+        assert_eq!(vec!["いるかねこ", "100", "", ""], csv.get_body().get_row(0).unwrap().values());
+        assert_eq!(vec!["船長うさぎ", "200", "なし", ""], csv.get_body().get_row(1).unwrap().values());
+        assert_eq!(vec!["やかまし", "300", "a,b", "c,d"], csv.get_body().get_row(2).unwrap().values());
+        assert_eq!(2, report.len());
+
+        let options = ReadOptions {ragged: RaggedPolicy::Overflow(String::from("金額")), ..Default::default()};
+        match read_from_with_options(TEXT.as_bytes(), &options) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("超過列の名前がヘッダーと重複しています。[金額]", e),
+        }
+    }
+
+    #[test]
+    fn reject_records() {
+        let text = "名前,金額\nいるかねこ,100\n船長うさぎ,200,なし\n\"閉じて\"いない,300\nやかまし,400\n";
+        let options = ReadOptions {on_error: ErrorPolicy::Reject, ..Default::default()};
+
+        let (csv, report) = read_from_with_options(text.as_bytes(), &options).unwrap();
+
+        assert_eq!(2, csv.get_body().len());
+        assert_eq!(vec!["やかまし", "400"], csv.get_body().get_row(1).unwrap().values());
+        assert_eq!(2, report.rejects().len());
+        let reject = &report.rejects()[0];
+        assert_eq!("船長うさぎ,200,なし", reject.text);
+        assert_eq!((3, 2), (reject.error.line, reject.error.record));
+        assert_eq!("項目数がヘッダーと一致しません。ヘッダー=[2], 項目数=[3]", reject.error.message);
+        assert_eq!((4, 3), (report.rejects()[1].error.line, report.rejects()[1].error.record));
+        assert!(report.repairs().is_empty());
+
+        // 既定では最初のエラーで中止する
+        assert!(read_from_with_options(text.as_bytes(), &ReadOptions::default()).is_err());
+    }
+
+    #[test]
+    fn reject_stray_quote() {
+        // 項目の途中の囲み文字はエラーにしない
+        let text = "名前,金額\nいるかねこ,100\n船長\"うさぎ,200\nやかまし,300\nおけぶろ,400\n";
+        let options = ReadOptions {on_error: ErrorPolicy::Reject, ..Default::default()};
+
+        let (csv, report) = read_from_with_options(text.as_bytes(), &options).unwrap();
+
+        assert_eq!(4, csv.get_body().len());
+        assert_eq!(vec!["船長\"うさぎ", "200"], csv.get_body().get_row(1).unwrap().values());
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn reject_unclosed_quote() {
+        // 閉じていない囲み文字の行だけを除外し、後の行は読み込む
+        let text = "名前,金額\nいるかねこ,100\n\"船長\n\"\"うさぎ\",200\n\"やかまし,300\r\nおけぶろ,400\nこいぬ,500\n";
+        let options = ReadOptions {on_error: ErrorPolicy::Reject, ..Default::default()};
+
+        let (csv, report) = read_from_with_options(text.as_bytes(), &options).unwrap();
+
+        assert_eq!(4, csv.get_body().len());
+        assert_eq!(vec!["船長\n\"うさぎ", "200"], csv.get_body().get_row(1).unwrap().values());
+        assert_eq!(vec!["おけぶろ", "400"], csv.get_body().get_row(2).unwrap().values());
+        assert_eq!(vec!["こいぬ", "500"], csv.get_body().get_row(3).unwrap().values());
+        let rejects: Vec<(&str, usize, usize)> = report.rejects().iter()
+            .map(|reject| (reject.text.as_str(), reject.error.line, reject.error.record))
+            .collect();
+        assert_eq!(vec![("\"やかまし,300", 5, 3)], rejects);
+    }
+
+    #[test]
+    fn reject_with_ragged() {
+        let options = ReadOptions {ragged: RaggedPolicy::Pad, on_error: ErrorPolicy::Reject, ..Default::default()};
+
+        let (csv, report) = read_from_with_options(TEXT.as_bytes(), &options).unwrap();
+
+        assert_eq!(2, csv.get_body().len());
+        assert_eq!("行=[0], 物理行=[2], 項目数=[2], [補完]\n物理行=[4], レコード=[3], [除外], [項目数がヘッダーと一致しません。ヘッダー=[3], 項目数=[5]]\n",
+                   report.to_string());
+    }
+
+    #[test]
+    fn write_rejects() {
+        let path = std::env::temp_dir().join(format!("csv-io-rejects-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        std::fs::write(&path, "名前,金額\nいるかねこ,100\n\"船長\nうさぎ\",200,300\n").unwrap();
+
+        let options = ReadOptions {on_error: ErrorPolicy::Reject, write_rejects: true, ..Default::default()};
+        let (csv, _) = read_with_options(&path, &options).unwrap();
+        let rejects = rejects_path(&path);
+        let text = std::fs::read_to_string(&rejects).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&rejects).unwrap();
+
+        assert_eq!(1, csv.get_body().len());
+        assert!(rejects.ends_with(&format!("csv-io-rejects-{}.rejects.csv", std::process::id())));
+        // 行は多い項目が始まる物理行
+        assert_eq!("行,レコード,理由,内容\n4,2,\"項目数がヘッダーと一致しません。ヘッダー=[2], 項目数=[3]\",\"\"\"船長\nうさぎ\"\",200,300\"\n", text);
+    }
+
+    #[test]
+    fn rejects_path_name() {
+        assert_eq!("sales.rejects.csv", rejects_path("sales.csv"));
+        assert_eq!("data/売上.rejects.csv", rejects_path("data/売上.CSV"));
+        assert_eq!("sales.tsv.rejects.csv", rejects_path("sales.tsv"));
+    }
+}
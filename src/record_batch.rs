@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{
+    Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type,
+    UInt16Type, UInt32Type, UInt64Type, UInt8Type,
+};
+use arrow_array::{Array, ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema};
+
+use crate::schema::parse_bool;
+use crate::{ColumnType, CsvFile, CsvSchema};
+
+impl CsvFile {
+    // 値から推定した型でRecordBatchに変換する
+    // 0埋めのコードなど、変換すると値が変わる列は文字列とする
+    pub fn to_record_batch(&self) -> Result<RecordBatch, String> {
+        self.to_record_batch_with(&CsvSchema::infer_preserving(self))
+    }
+
+    // スキーマの型でRecordBatchに変換する
+    // 文字列以外の列の空の値はnullとする
+    pub fn to_record_batch_with(&self, schema: &CsvSchema) -> Result<RecordBatch, String> {
+        let mut fields = Vec::with_capacity(self.csv_header.name.len());
+        let mut columns: Vec<ArrayRef> = Vec::with_capacity(self.csv_header.name.len());
+        for (index, name) in self.csv_header.name.iter().enumerate() {
+            let values: Vec<&str> = self.csv_body.rows.iter()
+                .map(|row| row.data.get(index).map(|csv_data| csv_data.value.as_str()).unwrap_or(""))
+                .collect();
+            let column_type = schema.get(name);
+            let (data_type, column): (DataType, ArrayRef) = match column_type {
+                ColumnType::String => (DataType::Utf8, Arc::new(StringArray::from(values))),
+                ColumnType::Integer => {
+                    let values = parse_column(name, &values, column_type, |value| value.parse::<i64>().ok())?;
+                    (DataType::Int64, Arc::new(Int64Array::from(values)))
+                }
+                ColumnType::Float => {
+                    let values = parse_column(name, &values, column_type, |value| value.parse::<f64>().ok())?;
+                    (DataType::Float64, Arc::new(Float64Array::from(values)))
+                }
+                ColumnType::Boolean => {
+                    let values = parse_column(name, &values, column_type, parse_bool)?;
+                    (DataType::Boolean, Arc::new(BooleanArray::from(values)))
+                }
+            };
+            fields.push(Field::new(name, data_type, true));
+            columns.push(column);
+        }
+
+        match RecordBatch::try_new(Arc::new(Schema::new(fields)), columns) {
+            Ok(batch) => Ok(batch),
+            Err(e) => Err(format!("RecordBatchへの変換に失敗しました。[{}]", e)),
+        }
+    }
+
+    // 最初のRecordBatchのスキーマをヘッダーとし、すべての行を連結する
+    // nullは空の値とする
+    pub fn from_record_batches(batches: &[RecordBatch]) -> Result<CsvFile, String> {
+        let header_names: Vec<String> = match batches.first() {
+            Some(batch) => batch.schema().fields().iter().map(|field| field.name().clone()).collect(),
+            None => return Ok(CsvFile::from_rows(Vec::new(), Vec::new())),
+        };
+
+        let mut rows = Vec::new();
+        for batch in batches {
+            let names: Vec<&String> = batch.schema_ref().fields().iter().map(|field| field.name()).collect();
+            if names.len() != header_names.len() || names.iter().zip(&header_names).any(|(a, b)| *a != b) {
+                return Err(String::from("RecordBatchからの変換に失敗しました。[スキーマが一致しません。]"));
+            }
+
+            let mut columns = Vec::with_capacity(batch.num_columns());
+            for (name, column) in header_names.iter().zip(batch.columns()) {
+                columns.push(column_values(name, column.as_ref())?);
+            }
+            for row_index in 0..batch.num_rows() {
+                rows.push(columns.iter_mut().map(|values| std::mem::take(&mut values[row_index])).collect());
+            }
+        }
+
+        Ok(CsvFile::from_rows(header_names, rows))
+    }
+}
+
+fn parse_column<T, F>(name: &str, values: &[&str], column_type: ColumnType, parse: F) -> Result<Vec<Option<T>>, String>
+where
+    F: Fn(&str) -> Option<T>,
+{
+    let mut parsed = Vec::with_capacity(values.len());
+    for (row_index, value) in values.iter().enumerate() {
+        if value.is_empty() {
+            parsed.push(None);
+            continue;
+        }
+        match parse(value) {
+            Some(value) => parsed.push(Some(value)),
+            None => return Err(format!("RecordBatchへの変換に失敗しました。[{}に変換できません。行=[{}], 列=[{}], 値=[{}]]",
+                                       column_type.name(), row_index, name, value)),
+        }
+    }
+    Ok(parsed)
+}
+
+// 列の値を文字列にする
+fn column_values(name: &str, column: &dyn Array) -> Result<Vec<String>, String> {
+    macro_rules! primitive {
+        ($type:ty) => {
+            column.as_primitive::<$type>().iter()
+                .map(|value| value.map(|value| value.to_string()).unwrap_or_default())
+                .collect()
+        };
+    }
+
+    let values = match column.data_type() {
+        DataType::Utf8 => column.as_string::<i32>().iter()
+            .map(|value| value.unwrap_or("").to_string())
+            .collect(),
+        DataType::LargeUtf8 => column.as_string::<i64>().iter()
+            .map(|value| value.unwrap_or("").to_string())
+            .collect(),
+        DataType::Boolean => column.as_boolean().iter()
+            .map(|value| value.map(|value| value.to_string()).unwrap_or_default())
+            .collect(),
+        DataType::Int8 => primitive!(Int8Type),
+        DataType::Int16 => primitive!(Int16Type),
+        DataType::Int32 => primitive!(Int32Type),
+        DataType::Int64 => primitive!(Int64Type),
+        DataType::UInt8 => primitive!(UInt8Type),
+        DataType::UInt16 => primitive!(UInt16Type),
+        DataType::UInt32 => primitive!(UInt32Type),
+        DataType::UInt64 => primitive!(UInt64Type),
+        DataType::Float32 => primitive!(Float32Type),
+        DataType::Float64 => primitive!(Float64Type),
+        data_type => return Err(format!("RecordBatchからの変換に失敗しました。[未対応の型です。列=[{}], 型=[{}]]", name, data_type)),
+    };
+    Ok(values)
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_record_batch_infer() {
+        let csv = make_file();
+
+        let batch = csv.to_record_batch().unwrap();
+
+        let schema = batch.schema();
+        let types: Vec<&DataType> = schema.fields().iter().map(|field| field.data_type()).collect();
+        assert_eq!(vec![&DataType::Int64, &DataType::Utf8, &DataType::Float64, &DataType::Boolean], types);
+        assert_eq!(3, batch.num_rows());
+        assert_eq!(-2.5, batch.column(2).as_primitive::<Float64Type>().value(1));
+        assert!(batch.column(2).is_null(2));
+        assert_eq!("船長うさぎ", batch.column(1).as_string::<i32>().value(1));
+    }
+
+    #[test]
+    fn to_record_batch_with_schema_error() {
+        let csv = make_file();
+        let mut schema = CsvSchema::new();
+        schema.push("名前", ColumnType::Integer);
+
+        match csv.to_record_batch_with(&schema) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("RecordBatchへの変換に失敗しました。[整数に変換できません。行=[0], 列=[名前], 値=[いるかねこ]]", e),
+        }
+    }
+
+    #[test]
+    fn from_record_batches_round_trip() {
+        let csv = make_file();
+        let batch = csv.to_record_batch().unwrap();
+
+        let read = CsvFile::from_record_batches(&[batch.slice(0, 2), batch.slice(2, 1)]).unwrap();
+
+        assert_eq!(csv.get_header().name, read.get_header().name);
+        assert_eq!(vec!["1", "いるかねこ", "100", "true"], read.get_body().get_row(0).unwrap().values());
+        assert_eq!(vec!["2", "船長うさぎ", "-2.5", "false"], read.get_body().get_row(1).unwrap().values());
+        assert_eq!(vec!["3", "", "", ""], read.get_body().get_row(2).unwrap().values());
+    }
+
+    #[test]
+    fn to_record_batch_preserves_values() {
+        let csv = CsvFile::from_rows(
+            vec![String::from("郵便番号"), String::from("金額")],
+            vec![vec![String::from("00123"), String::from("1.50")], vec![String::from("4567"), String::from("2")]],
+        );
+
+        let batch = csv.to_record_batch().unwrap();
+        let read = CsvFile::from_record_batches(&[batch]).unwrap();
+
+        assert_eq!(vec!["00123", "1.50"], read.get_body().get_row(0).unwrap().values());
+        assert_eq!(vec!["4567", "2"], read.get_body().get_row(1).unwrap().values());
+    }
+
+    fn make_file() -> CsvFile {
+        CsvFile::from_rows(
+            vec![String::from("id"), String::from("名前"), String::from("金額"), String::from("有効")],
+            vec![
+                vec![String::from("1"), String::from("いるかねこ"), String::from("100"), String::from("true")],
+                vec![String::from("2"), String::from("船長うさぎ"), String::from("-2.5"), String::from("false")],
+                vec![String::from("3"), String::new(), String::new(), String::new()],
+            ],
+        )
+    }
+}
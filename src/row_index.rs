@@ -0,0 +1,508 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::time::UNIX_EPOCH;
+
+use crate::compression::Compression;
+use crate::dialect::{QuoteScanner, RecordReader};
+use crate::error::RecordPosition;
+use crate::{parse_header, parse_row, CsvDialect, CsvHeader, CsvRow};
+
+const MAGIC: &[u8; 8] = b"CSVIDX3\n";
+
+// 一定の行数ごとに行の開始位置を記録した索引
+// 元のファイルの大きさ・更新日時・チェックサムと作成時の区切り文字・囲み文字を保持する
+#[derive(Clone, Debug, PartialEq)]
+pub struct RowIndex {
+    interval: usize,
+    rows: usize,
+    size: u64,
+    // UNIX時間(ナノ秒)、取得できない場合は0
+    modified: u64,
+    checksum: u64,
+    delimiter: char,
+    quote: char,
+    // 0, interval, interval * 2, ...行目の開始位置と物理的な行番号
+    offsets: Vec<(u64, u64)>,
+}
+impl RowIndex {
+    // ファイルを先頭から読み込み、interval行ごとの開始位置を記録する
+    pub fn build(path: &str, dialect: &CsvDialect, interval: usize) -> Result<Self, String> {
+        dialect.validate()?;
+        if interval == 0 {
+            return Err(String::from("索引の間隔には1以上を指定してください。"));
+        }
+        let (_, modified) = file_stamp(path)?;
+        let mut reader = open_source(path)?;
+
+        let mut scanner = QuoteScanner::new(dialect);
+        let mut checksum = Checksum::new();
+        let mut position = 0;
+        let mut line_number = 1;
+        let mut record = 0;
+        let mut offsets = Vec::new();
+        let mut line = Vec::new();
+        loop {
+            // 囲み文字の外で行が終わるまでを1レコードとする
+            let start = (position, line_number);
+            let mut content = 0;
+            scanner.reset();
+            loop {
+                line.clear();
+                match reader.read_until(b'\n', &mut line) {
+                    Ok(0) => break,
+                    Ok(_) => {}
+                    Err(e) => return Err(format!("readに失敗しました。[{}]", e)),
+                }
+                checksum.update(&line);
+                // 先頭のBOMは項目に含めない
+                let bom = if position == 0 && line.starts_with(b"\xEF\xBB\xBF") { 3 } else { 0 };
+                position += line.len() as u64;
+                line_number += line.ends_with(b"\n") as u64;
+                content += line.len() - line_end_len(&line) - bom;
+                if scanner.scan(&line[bom..]) {
+                    break;
+                }
+            }
+            if position == start.0 {
+                break;
+            }
+            // 空行は除く
+            if content == 0 {
+                continue;
+            }
+
+            // 最初のレコードはヘッダー
+            if record > 0 && (record - 1) % interval == 0 {
+                offsets.push(start);
+            }
+            record += 1;
+        }
+
+        Ok(Self {
+            interval,
+            rows: record.saturating_sub(1),
+            size: position,
+            modified,
+            checksum: checksum.finish(),
+            delimiter: dialect.delimiter,
+            quote: dialect.quote,
+            offsets,
+        })
+    }
+
+    // 索引ファイルを読み込む
+    pub fn load(index_path: &str) -> Result<Self, String> {
+        let bytes = match fs::read(index_path) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(format!("openに失敗しました。{}", e)),
+        };
+        let invalid = || String::from("索引ファイルの形式が正しくありません。");
+        if !bytes.starts_with(MAGIC) || !(bytes.len() - MAGIC.len()).is_multiple_of(8) {
+            return Err(invalid());
+        }
+        let values: Vec<u64> = bytes[MAGIC.len()..].chunks(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        if values.len() < 7 || values[0] == 0 || values.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+        let (interval, rows) = (values[0] as usize, values[1] as usize);
+        let to_char = |value: u64| u32::try_from(value).ok().and_then(char::from_u32).ok_or_else(invalid);
+        let (delimiter, quote) = (to_char(values[5])?, to_char(values[6])?);
+        let offsets: Vec<(u64, u64)> = values[7..].chunks(2).map(|pair| (pair[0], pair[1])).collect();
+        if offsets.len() != rows.div_ceil(interval) {
+            return Err(invalid());
+        }
+        Ok(Self {interval, rows, size: values[2], modified: values[3], checksum: values[4], delimiter, quote, offsets})
+    }
+
+    // 索引ファイルに書き込む
+    pub fn save(&self, index_path: &str) -> Result<(), String> {
+        let mut bytes = MAGIC.to_vec();
+        let values = [self.interval as u64, self.rows as u64, self.size, self.modified, self.checksum,
+                      self.delimiter as u64, self.quote as u64];
+        let offsets = self.offsets.iter().flat_map(|(offset, line)| [*offset, *line]);
+        for value in values.into_iter().chain(offsets) {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        match fs::write(index_path, bytes) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("writeに失敗しました。[{}]", e)),
+        }
+    }
+
+    // 索引ファイルのパス(元のファイルのパス + .idx)
+    pub fn sidecar_path(path: &str) -> String {
+        format!("{}.idx", path)
+    }
+
+    // 索引を作成したときから元のファイルが変更されていないか
+    // ファイル全体のチェックサムを計算して照合する
+    pub fn matches(&self, path: &str) -> Result<bool, String> {
+        let (size, _) = file_stamp(path)?;
+        if size != self.size {
+            return Ok(false);
+        }
+        let mut reader = open_source(path)?;
+        let mut checksum = Checksum::new();
+        loop {
+            let length = match reader.fill_buf() {
+                Ok([]) => break,
+                Ok(bytes) => {
+                    checksum.update(bytes);
+                    bytes.len()
+                }
+                Err(e) => return Err(format!("readに失敗しました。[{}]", e)),
+            };
+            reader.consume(length);
+        }
+        Ok(checksum.finish() == self.checksum)
+    }
+
+    // 索引を作成したときと同じ区切り文字と囲み文字か
+    pub fn matches_dialect(&self, dialect: &CsvDialect) -> bool {
+        self.delimiter == dialect.delimiter && self.quote == dialect.quote
+    }
+
+    pub fn interval(&self) -> usize {
+        self.interval
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+}
+
+// 索引を使用して、指定した行だけを読み込む
+pub struct IndexedReader {
+    reader: BufReader<File>,
+    dialect: CsvDialect,
+    csv_header: CsvHeader,
+    index: RowIndex,
+}
+impl IndexedReader {
+    pub fn open(path: &str) -> Result<Self, String> {
+        Self::open_with(path, &CsvDialect::default(), 1000)
+    }
+
+    // 索引ファイルがない場合や元のファイルが変更されている場合は、索引を作成して保存する
+    // 間隔・区切り文字・囲み文字が異なる場合も作り直す
+    pub fn open_with(path: &str, dialect: &CsvDialect, interval: usize) -> Result<Self, String> {
+        let index_path = RowIndex::sidecar_path(path);
+        let index = match RowIndex::load(&index_path) {
+            Ok(mut index) if index.interval == interval && index.matches_dialect(dialect) => {
+                let (size, modified) = file_stamp(path)?;
+                if size == index.size && modified == index.modified && modified != 0 {
+                    // 大きさと更新日時が同じ場合はチェックサムを計算しない
+                    Some(index)
+                } else if index.matches(path)? {
+                    // 更新日時だけが変わった場合は記録し直す
+                    index.modified = modified;
+                    index.save(&index_path)?;
+                    Some(index)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        let index = match index {
+            Some(index) => index,
+            None => {
+                let index = RowIndex::build(path, dialect, interval)?;
+                index.save(&index_path)?;
+                index
+            }
+        };
+        Self::with_index(path, dialect, index)
+    }
+
+    // 作成済みの索引を使用する(元のファイルとの照合はしない)
+    pub fn with_index(path: &str, dialect: &CsvDialect, index: RowIndex) -> Result<Self, String> {
+        dialect.validate()?;
+        if !index.matches_dialect(dialect) {
+            return Err(String::from("索引を作成したときと区切り文字または囲み文字が異なります。"));
+        }
+        let mut reader = open_source(path)?;
+
+        let csv_header = match RecordReader::new(&mut reader, dialect).next_record() {
+            Some(record) => parse_header(&record?, dialect)?,
+            None => CsvHeader::new(),
+        };
+
+        Ok(Self {reader, dialect: dialect.clone(), csv_header, index})
+    }
+
+    pub fn get_header(&self) -> &CsvHeader {
+        &self.csv_header
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    // 直前の記録位置に移動し、そこから指定した行まで読み進める
+    pub fn get_row(&mut self, index: usize) -> Result<CsvRow, String> {
+        if index >= self.index.rows {
+            return Err(format!("範囲外のインデックスが指定されました。[{}]", index));
+        }
+        let block = index / self.index.interval;
+        let (offset, line) = self.index.offsets[block];
+        if let Err(e) = self.reader.seek(SeekFrom::Start(offset)) {
+            return Err(format!("seekに失敗しました。[{}]", e));
+        }
+
+        let position = RecordPosition {record: block * self.index.interval + 1, line: line as usize, offset: offset as usize};
+        let mut records = RecordReader::new(&mut self.reader, &self.dialect).starting_at(position);
+        for _ in 0..index % self.index.interval {
+            match records.next_record() {
+                Some(record) => { record?; }
+                None => return Err(format!("範囲外のインデックスが指定されました。[{}]", index)),
+            }
+        }
+        match records.next_record() {
+            Some(record) => Ok(parse_row(&self.csv_header, &record?, &self.dialect)?),
+            None => Err(format!("範囲外のインデックスが指定されました。[{}]", index)),
+        }
+    }
+}
+
+fn open_source(path: &str) -> Result<BufReader<File>, String> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("openに失敗しました。{}", e)),
+    };
+    let mut reader = BufReader::new(file);
+    let compressed = match reader.fill_buf() {
+        Ok(bytes) => Compression::from_magic(bytes) != Compression::None,
+        Err(e) => return Err(format!("readに失敗しました。[{}]", e)),
+    };
+    if compressed {
+        return Err(String::from("圧縮されたファイルには索引を作成できません。"));
+    }
+    Ok(reader)
+}
+
+// ファイルの大きさと更新日時(UNIX時間のナノ秒、取得できない場合は0)
+fn file_stamp(path: &str) -> Result<(u64, u64), String> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => return Err(format!("openに失敗しました。{}", e)),
+    };
+    let modified = metadata.modified().ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos() as u64);
+    Ok((metadata.len(), modified))
+}
+
+fn line_end_len(line: &[u8]) -> usize {
+    if line.ends_with(b"\r\n") {
+        2
+    } else if line.ends_with(b"\n") {
+        1
+    } else {
+        0
+    }
+}
+
+// FNV-1a(64bit)
+struct Checksum(u64);
+impl Checksum {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read;
+
+    #[test]
+    fn indexed_reader_get_row() {
+        let path = temp_path("get_row");
+        let mut text = String::from("\u{FEFF}id,名前,備考\r\n");
+        for index in 0..25 {
+            text.push_str(&format!("{},いるかねこ{},\"改行\r\n\"\"{}\"\"\"\r\n", index, index, index));
+            if index % 4 == 0 {
+                text.push_str("\r\n");
+            }
+        }
+        fs::write(&path, &text).unwrap();
+
+        let mut reader = IndexedReader::open_with(&path, &CsvDialect::default(), 10).unwrap();
+        let expected = read(&path).unwrap();
+
+        assert_eq!(vec!["id", "名前", "備考"], reader.get_header().name);
+        assert_eq!(25, reader.len());
+        // 順序に関係なく取得できる
+        for index in [24, 0, 10, 9, 11, 20, 3] {
+            assert_eq!(expected.get_body().get_row(index).unwrap().values(), reader.get_row(index).unwrap().values());
+        }
+        match reader.get_row(25) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("範囲外のインデックスが指定されました。[25]", e),
+        }
+
+        // 索引ファイルが保存される
+        let index = RowIndex::load(&RowIndex::sidecar_path(&path)).unwrap();
+        assert_eq!(3, index.offsets.len());
+        assert!(index.matches(&path).unwrap());
+        remove(&path);
+    }
+
+    #[test]
+    fn indexed_reader_rebuild() {
+        let path = temp_path("rebuild");
+        fs::write(&path, "名前\nいるかねこ\n").unwrap();
+        IndexedReader::open(&path).unwrap();
+
+        // 大きさが同じでも内容と更新日時が変わった場合は索引を作り直す
+        let index = RowIndex::load(&RowIndex::sidecar_path(&path)).unwrap();
+        fs::write(&path, "名前\n船長うさぎ\n").unwrap();
+        set_modified(&path, index.modified + 1_000_000_000);
+        assert!(!index.matches(&path).unwrap());
+
+        let mut reader = IndexedReader::open(&path).unwrap();
+        assert_eq!(1, reader.len());
+        assert_eq!(vec!["船長うさぎ"], reader.get_row(0).unwrap().values());
+        remove(&path);
+    }
+
+    #[test]
+    fn indexed_reader_modified() {
+        let path = temp_path("modified");
+        fs::write(&path, "名前\nいるかねこ\n").unwrap();
+        let index_path = RowIndex::sidecar_path(&path);
+        IndexedReader::open(&path).unwrap();
+        let index = RowIndex::load(&index_path).unwrap();
+
+        // 大きさと更新日時が同じ場合はチェックサムを計算せずに索引を使用する
+        fs::write(&path, "名前\nかねこ1\n").unwrap();
+        set_modified(&path, index.modified);
+        assert_eq!(vec!["かねこ1"], IndexedReader::open(&path).unwrap().get_row(0).unwrap().values());
+
+        // 更新日時だけが変わった場合は索引を使用し、更新日時を記録し直す
+        fs::write(&path, "名前\nいるかねこ\n").unwrap();
+        set_modified(&path, index.modified + 1_000_000_000);
+        IndexedReader::open(&path).unwrap();
+        let reused = RowIndex::load(&index_path).unwrap();
+        assert_eq!(index.modified + 1_000_000_000, reused.modified);
+        assert_eq!(index.checksum, reused.checksum);
+        remove(&path);
+    }
+
+    #[test]
+    fn indexed_reader_dialect() {
+        let path = temp_path("dialect");
+        fs::write(&path, "名前\t金額\n\"船長\tうさぎ\n\"\t200\n'いるか\tねこ\n'\t300\n").unwrap();
+        IndexedReader::open_with(&path, &CsvDialect::tsv(), 1).unwrap();
+
+        // 囲み文字が異なる場合は索引を作り直す
+        let dialect = CsvDialect::new('\t', '\'');
+        let mut reader = IndexedReader::open_with(&path, &dialect, 1).unwrap();
+        assert_eq!(3, reader.len());
+        assert_eq!(vec!["いるか\tねこ\n", "300"], reader.get_row(2).unwrap().values());
+        assert!(RowIndex::load(&RowIndex::sidecar_path(&path)).unwrap().matches_dialect(&dialect));
+
+        // 作成済みの索引と異なる場合はエラー
+        let index = RowIndex::build(&path, &CsvDialect::tsv(), 1).unwrap();
+        match IndexedReader::with_index(&path, &dialect, index) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("索引を作成したときと区切り文字または囲み文字が異なります。", e),
+        }
+        remove(&path);
+    }
+
+    #[test]
+    fn row_index_stray_quote() {
+        let path = temp_path("stray");
+        fs::write(&path, "名前,金額\n船長\"うさぎ,200\nやかまし,300\n").unwrap();
+
+        // 項目の途中の囲み文字では囲みを開始しない
+        let mut reader = IndexedReader::open_with(&path, &CsvDialect::default(), 1).unwrap();
+        assert_eq!(2, reader.len());
+        assert_eq!(vec!["やかまし", "300"], reader.get_row(1).unwrap().values());
+        remove(&path);
+    }
+
+    #[test]
+    fn indexed_reader_error_position() {
+        let path = temp_path("position");
+        let mut text = String::from("名前,金額\n");
+        for index in 0..12 {
+            text.push_str(&format!("\"いるかねこ\n{}\",{}\n", index, index));
+        }
+        text.push_str("船長うさぎ,100,200\n");
+        fs::write(&path, &text).unwrap();
+
+        let mut reader = IndexedReader::open_with(&path, &CsvDialect::default(), 5).unwrap();
+        let result = reader.get_row(12);
+        let expected = read(&path);
+        remove(&path);
+
+        // ファイル全体を読み込んだ場合と同じ位置になる
+        assert_eq!(expected.err().unwrap(), result.err().unwrap());
+    }
+
+    #[test]
+    fn row_index_error() {
+        let path = temp_path("error");
+        fs::write(&path, "a\n1\n").unwrap();
+
+        match RowIndex::build(&path, &CsvDialect::default(), 0) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("索引の間隔には1以上を指定してください。", e),
+        }
+        match RowIndex::load(&path) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("索引ファイルの形式が正しくありません。", e),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("csv-io-index-{}-{}.csv", name, std::process::id()));
+        path.to_str().unwrap().to_string()
+    }
+
+    fn set_modified(path: &str, nanos: u64) {
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(UNIX_EPOCH + std::time::Duration::from_nanos(nanos)).unwrap();
+    }
+
+    fn remove(path: &str) {
+        fs::remove_file(path).unwrap();
+        fs::remove_file(RowIndex::sidecar_path(path)).unwrap();
+    }
+}
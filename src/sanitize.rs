@@ -0,0 +1,243 @@
+use std::fmt;
+use std::io::Write;
+
+use crate::{write_to, write_with, CellChange, CsvDialect, CsvFile};
+
+// 数式として解釈される値の扱い
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FormulaPolicy {
+    // 先頭に'を付ける
+    Prefix,
+    // エラーにする
+    Reject,
+    // 先頭の=,+,-,@を全角文字に、タブと復帰を空白に置き換える
+    Escape,
+}
+
+pub struct SanitizeOptions {
+    pub policy: FormulaPolicy,
+    // 対象の列(Noneの場合はすべての列)
+    pub columns: Option<Vec<String>>,
+}
+impl SanitizeOptions {
+    pub fn new(policy: FormulaPolicy) -> Self {
+        Self {policy, columns: None}
+    }
+}
+
+// 変更したセルの一覧
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SanitizeReport {
+    changes: Vec<(usize, CellChange)>,
+}
+impl SanitizeReport {
+    // 行番号と変更内容
+    pub fn changes(&self) -> &[(usize, CellChange)] {
+        &self.changes
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+impl fmt::Display for SanitizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (row_index, change) in &self.changes {
+            writeln!(f, "行=[{}], 列=[{}], [{}] -> [{}]",
+                     row_index, change.header_name(), change.old_value(), change.new_value())?;
+        }
+        Ok(())
+    }
+}
+
+impl CsvFile {
+    // 表計算ソフトで数式として解釈される値を変換する
+    // Rejectの場合は該当する値をすべてエラーにし、CsvFileは変更しない
+    pub fn sanitize(&mut self, options: &SanitizeOptions) -> Result<SanitizeReport, String> {
+        let targets: Vec<bool> = match &options.columns {
+            Some(columns) => {
+                for column in columns {
+                    if !self.csv_header.name.contains(column) {
+                        return Err(format!("数式の無害化に失敗しました。[存在しないヘッダー名です。[{}]]", column));
+                    }
+                }
+                self.csv_header.name.iter().map(|name| columns.contains(name)).collect()
+            }
+            None => vec![true; self.csv_header.name.len()],
+        };
+
+        let mut report = SanitizeReport::default();
+        let mut rejected = Vec::new();
+        for (row_index, row) in self.csv_body.rows.iter_mut().enumerate() {
+            for (index, csv_data) in row.data.iter_mut().enumerate() {
+                if !targets.get(index).copied().unwrap_or(false) || !is_formula(&csv_data.value) {
+                    continue;
+                }
+                let new_value = match options.policy {
+                    FormulaPolicy::Prefix => format!("'{}", csv_data.value),
+                    FormulaPolicy::Escape => escape(&csv_data.value),
+                    FormulaPolicy::Reject => {
+                        rejected.push(format!("行=[{}], 列=[{}], 値=[{}]", row_index, csv_data.header_name, csv_data.value));
+                        continue;
+                    }
+                };
+                report.changes.push((row_index, CellChange::new(&csv_data.header_name, &csv_data.value, &new_value)));
+                csv_data.value = new_value;
+            }
+        }
+        if !rejected.is_empty() {
+            return Err(format!("数式の無害化に失敗しました。[数式として解釈される値があります。{}]", rejected.join(", ")));
+        }
+
+        if !report.is_empty() {
+            self.rebuild_primary_key();
+        }
+        Ok(report)
+    }
+}
+
+// 無害化してから書き込む
+// 既存のファイルは上書きする。拡張子が.gz/.zst/.bz2の場合は圧縮する
+pub fn write_sanitized(path: &str, csv_file: &CsvFile, dialect: &CsvDialect, options: &SanitizeOptions)
+    -> Result<SanitizeReport, String> {
+    let mut sanitized = csv_file.clone();
+    let report = sanitized.sanitize(options)?;
+    write_with(path, &sanitized, dialect)?;
+    Ok(report)
+}
+
+pub fn write_sanitized_to<W: Write>(writer: W, csv_file: &CsvFile, dialect: &CsvDialect, options: &SanitizeOptions)
+    -> Result<SanitizeReport, String> {
+    let mut sanitized = csv_file.clone();
+    let report = sanitized.sanitize(options)?;
+    write_to(writer, &sanitized, dialect)?;
+    Ok(report)
+}
+
+// 表計算ソフトが数式として解釈する値か
+// 負の数などの数値は対象外とする
+pub(crate) fn is_formula(value: &str) -> bool {
+    match value.chars().next() {
+        Some('=') | Some('@') | Some('\t') | Some('\r') => true,
+        Some('+') | Some('-') => value.parse::<f64>().is_err(),
+        _ => false,
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut chars = value.chars();
+    let first = match chars.next() {
+        Some('=') => '＝',
+        Some('+') => '＋',
+        Some('-') => '－',
+        Some('@') => '＠',
+        Some(_) => ' ',
+        None => return String::new(),
+    };
+    let mut escaped = String::from(first);
+    escaped.push_str(chars.as_str());
+    escaped
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_prefix() {
+        let mut csv = make_file();
+
+        let report = csv.sanitize(&SanitizeOptions::new(FormulaPolicy::Prefix)).unwrap();
+
+        assert_eq!(vec!["'=1+2", "-2.5", "'@いるかねこ"], csv.get_body().get_row(0).unwrap().values());
+        assert_eq!(vec!["'\tタブ", "'-A1", "船長うさぎ"], csv.get_body().get_row(1).unwrap().values());
+        assert_eq!(4, report.len());
+        assert_eq!(&(1, CellChange::new("金額", "-A1", "'-A1")), &report.changes()[3]);
+        assert!(report.to_string().starts_with("行=[0], 列=[式], [=1+2] -> ['=1+2]\n"));
+    }
+
+    #[test]
+    fn sanitize_escape_columns() {
+        let mut csv = make_file();
+        let options = SanitizeOptions {policy: FormulaPolicy::Escape, columns: Some(vec![String::from("式"), String::from("名前")])};
+
+        let report = csv.sanitize(&options).unwrap();
+
+        assert_eq!(vec!["＝1+2", "-2.5", "＠いるかねこ"], csv.get_body().get_row(0).unwrap().values());
+        assert_eq!(vec![" タブ", "-A1", "船長うさぎ"], csv.get_body().get_row(1).unwrap().values());
+        assert_eq!(3, report.len());
+    }
+
+    #[test]
+    fn sanitize_reject() {
+        let mut csv = make_file();
+        let options = SanitizeOptions {policy: FormulaPolicy::Reject, columns: Some(vec![String::from("金額")])};
+
+        match csv.sanitize(&options) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("数式の無害化に失敗しました。[数式として解釈される値があります。行=[1], 列=[金額], 値=[-A1]]", e),
+        }
+        assert_eq!(vec!["\tタブ", "-A1", "船長うさぎ"], csv.get_body().get_row(1).unwrap().values());
+    }
+
+    #[test]
+    fn write_sanitized_tsv() {
+        let csv = make_file();
+        let mut output = Vec::new();
+
+        let report = write_sanitized_to(&mut output, &csv, &CsvDialect::tsv(), &SanitizeOptions::new(FormulaPolicy::Prefix)).unwrap();
+
+        assert_eq!(4, report.len());
+        assert_eq!("式\t金額\t名前\n'=1+2\t-2.5\t'@いるかねこ\n\"'\tタブ\"\t'-A1\t船長うさぎ\n",
+                   String::from_utf8(output).unwrap());
+        // 元のCsvFileは変更しない
+        assert_eq!("=1+2", csv.get_value("式", 0).unwrap());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn write_sanitized_gzip() {
+        use crate::{read_with, Compression};
+
+        // 拡張子に合わせて圧縮する
+        let csv = make_file();
+        let path = std::env::temp_dir().join(format!("csv-io-sanitize-{}.tsv.gz", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let report = write_sanitized(path, &csv, &CsvDialect::tsv(), &SanitizeOptions::new(FormulaPolicy::Prefix)).unwrap();
+        let bytes = std::fs::read(path).unwrap();
+        let written = read_with(path, &CsvDialect::tsv());
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(4, report.len());
+        assert_eq!(Compression::Gzip, Compression::from_magic(&bytes));
+        let written = written.unwrap();
+        assert_eq!(vec!["'=1+2", "-2.5", "'@いるかねこ"], written.get_body().get_row(0).unwrap().values());
+        assert_eq!(vec!["'\tタブ", "'-A1", "船長うさぎ"], written.get_body().get_row(1).unwrap().values());
+    }
+
+    fn make_file() -> CsvFile {
+        CsvFile::from_rows(
+            vec![String::from("式"), String::from("金額"), String::from("名前")],
+            vec![
+                vec![String::from("=1+2"), String::from("-2.5"), String::from("@いるかねこ")],
+                vec![String::from("\tタブ"), String::from("-A1"), String::from("船長うさぎ")],
+            ],
+        )
+    }
+}
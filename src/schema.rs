@@ -0,0 +1,181 @@
+use crate::CsvFile;
+
+// 列の型
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColumnType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+}
+impl ColumnType {
+    // 値がこの型として解釈できるか
+    // 空の値はどの型でも欠損値として扱う
+    pub fn accepts(&self, value: &str) -> bool {
+        if value.is_empty() {
+            return true;
+        }
+        match self {
+            ColumnType::String => true,
+            ColumnType::Integer => value.parse::<i64>().is_ok(),
+            ColumnType::Float => value.parse::<f64>().is_ok_and(|number| number.is_finite()),
+            ColumnType::Boolean => parse_bool(value).is_some(),
+        }
+    }
+
+    // エラーメッセージで使用する型の名前
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ColumnType::String => "文字列",
+            ColumnType::Integer => "整数",
+            ColumnType::Float => "小数",
+            ColumnType::Boolean => "真偽値",
+        }
+    }
+}
+
+// 列名と型の対応
+// 指定のない列は文字列として扱う
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CsvSchema {
+    columns: Vec<(String, ColumnType)>,
+}
+impl CsvSchema {
+    pub fn new() -> Self {
+        Self {columns: Vec::new()}
+    }
+
+    // 同じ列名を指定した場合は型を置き換える
+    pub fn push(&mut self, header_name: &str, column_type: ColumnType) {
+        match self.columns.iter_mut().find(|(name, _)| name == header_name) {
+            Some((_, current)) => *current = column_type,
+            None => self.columns.push((header_name.to_string(), column_type)),
+        }
+    }
+
+    pub fn get(&self, header_name: &str) -> ColumnType {
+        self.columns.iter()
+            .find(|(name, _)| name == header_name)
+            .map(|(_, column_type)| *column_type)
+            .unwrap_or(ColumnType::String)
+    }
+
+    pub fn columns(&self) -> &[(String, ColumnType)] {
+        &self.columns
+    }
+
+    // すべての値を解釈できる型を列ごとに推定する
+    // 整数、小数、真偽値、文字列の順に判定する
+    pub fn infer(csv_file: &CsvFile) -> Self {
+        let mut schema = Self::new();
+        for (index, name) in csv_file.csv_header.name.iter().enumerate() {
+            let values = || csv_file.csv_body.rows.iter()
+                .filter_map(move |row| row.data.get(index))
+                .map(|csv_data| csv_data.value.as_str());
+            let column_type = if values().all(|value| value.is_empty()) {
+                ColumnType::String
+            } else {
+                [ColumnType::Integer, ColumnType::Float, ColumnType::Boolean].into_iter()
+                    .find(|column_type| values().all(|value| column_type.accepts(value)))
+                    .unwrap_or(ColumnType::String)
+            };
+            schema.push(name, column_type);
+        }
+        schema
+    }
+
+    // inferと同じく推定し、変換すると値の表記が変わる列は文字列にする
+    // 0埋めのコード(00123)や末尾の0(1.50)、TRUEや1の真偽値は文字列になる
+    pub fn infer_preserving(csv_file: &CsvFile) -> Self {
+        let inferred = Self::infer(csv_file);
+        let mut schema = Self::new();
+        for (index, name) in csv_file.csv_header.name.iter().enumerate() {
+            let mut values = csv_file.csv_body.rows.iter()
+                .filter_map(|row| row.data.get(index))
+                .map(|csv_data| csv_data.value.as_str())
+                .filter(|value| !value.is_empty());
+            let preserved = match inferred.get(name) {
+                ColumnType::Integer => values.all(|value| value.parse::<i64>().is_ok_and(|number| number.to_string() == value)),
+                ColumnType::Float => values.all(|value| value.parse::<f64>().is_ok_and(|number| number.to_string() == value)),
+                ColumnType::Boolean => values.all(|value| value == "true" || value == "false"),
+                ColumnType::String => true,
+            };
+            schema.push(name, if preserved { inferred.get(name) } else { ColumnType::String });
+        }
+        schema
+    }
+}
+
+pub(crate) fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_infer() {
+        let csv = CsvFile::from_rows(
+            vec![String::from("id"), String::from("金額"), String::from("有効"), String::from("名前"), String::from("備考")],
+            vec![
+                vec![String::from("1"), String::from("100"), String::from("true"), String::from("いるかねこ"), String::new()],
+                vec![String::from("2"), String::from("2.5"), String::from("FALSE"), String::from("3"), String::new()],
+                vec![String::new(), String::from("-1"), String::from("false"), String::from("船長うさぎ"), String::new()],
+            ],
+        );
+
+        let schema = CsvSchema::infer(&csv);
+
+        assert_eq!(ColumnType::Integer, schema.get("id"));
+        assert_eq!(ColumnType::Float, schema.get("金額"));
+        assert_eq!(ColumnType::Boolean, schema.get("有効"));
+        assert_eq!(ColumnType::String, schema.get("名前"));
+        assert_eq!(ColumnType::String, schema.get("備考"));
+        assert_eq!(ColumnType::String, schema.get("存在しない列"));
+    }
+
+    #[test]
+    fn schema_infer_preserving() {
+        let csv = CsvFile::from_rows(
+            vec![String::from("郵便番号"), String::from("金額"), String::from("単価"), String::from("有効"), String::from("確認"), String::from("id")],
+            vec![
+                vec![String::from("00123"), String::from("1.50"), String::from("2.5"), String::from("TRUE"), String::from("true"), String::from("1")],
+                vec![String::from("4567"), String::from("2"), String::new(), String::from("false"), String::from("false"), String::from("-20")],
+            ],
+        );
+
+        let schema = CsvSchema::infer_preserving(&csv);
+
+        assert_eq!(ColumnType::String, schema.get("郵便番号"));
+        assert_eq!(ColumnType::String, schema.get("金額"));
+        assert_eq!(ColumnType::Float, schema.get("単価"));
+        assert_eq!(ColumnType::String, schema.get("有効"));
+        assert_eq!(ColumnType::Boolean, schema.get("確認"));
+        assert_eq!(ColumnType::Integer, schema.get("id"));
+    }
+
+    #[test]
+    fn schema_push() {
+        let mut schema = CsvSchema::new();
+        schema.push("id", ColumnType::String);
+        schema.push("id", ColumnType::Integer);
+
+        assert_eq!(vec![(String::from("id"), ColumnType::Integer)], schema.columns());
+    }
+}
@@ -0,0 +1,521 @@
+use std::cmp::Ordering;
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::dialect::{format_record, split_record, RecordReader};
+use crate::error::RecordPosition;
+use crate::{CsvBody, CsvDialect, CsvFile, CsvRow};
+
+// ソート順
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+// 値の比較方法
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortCompare {
+    // 文字列の辞書順
+    Lexicographic,
+    // 数値として比較
+    Numeric,
+    // 文字列中の数字を数値として比較(file2 < file10)
+    Natural,
+    // 大文字・小文字、全角・半角英数字を区別せずに比較
+    CaseInsensitive,
+}
+
+enum SortKey {
+    Text(String),
+    Number(f64),
+}
+
+// ソートキーとレコードの項目
+type KeyedRecord = (Vec<SortKey>, Vec<String>);
+
+impl CsvBody {
+    pub fn sort_by_columns(&mut self, keys: &[(&str, SortOrder)]) -> Result<(), String> {
+        self.sort_by_columns_with(keys, SortCompare::Lexicographic)
+    }
+
+    pub fn sort_by_columns_with(&mut self, keys: &[(&str, SortOrder)], compare: SortCompare)
+        -> Result<(), String> {
+        // 先にソートキーを作成し、変換エラーはここで返す(エラーの場合は行を変更しない)
+        let mut keyed = Vec::with_capacity(self.rows.len());
+        for (index, row) in self.rows.iter().enumerate() {
+            let mut sort_keys = Vec::with_capacity(keys.len());
+            for (header_name, _) in keys {
+                let value = match row.get_value(header_name) {
+                    Ok(value) => value,
+                    Err(e) => return Err(format!("ソートに失敗しました。[{}]", e)),
+                };
+                match make_sort_key(&value, compare) {
+                    Ok(key) => sort_keys.push(key),
+                    Err(e) => return Err(format!("ソートに失敗しました。行=[{}], [{}]", index, e)),
+                }
+            }
+            keyed.push((sort_keys, index));
+        }
+
+        // sort_byは安定ソートのため、同じキーの行は元の順序が保たれる
+        keyed.sort_by(|a, b| compare_sort_keys(&a.0, &b.0, keys, compare));
+        let mut rows: Vec<Option<CsvRow>> = std::mem::take(&mut self.rows).into_iter().map(Some).collect();
+        self.rows = keyed.into_iter().filter_map(|(_, index)| rows[index].take()).collect();
+
+        Ok(())
+    }
+}
+
+impl CsvFile {
+    pub fn sort_by_columns(&mut self, keys: &[(&str, SortOrder)]) -> Result<(), String> {
+        self.sort_by_columns_with(keys, SortCompare::Lexicographic)
+    }
+
+    pub fn sort_by_columns_with(&mut self, keys: &[(&str, SortOrder)], compare: SortCompare)
+        -> Result<(), String> {
+        self.csv_body.sort_by_columns_with(keys, compare)?;
+        self.rebuild_primary_key();
+        Ok(())
+    }
+}
+
+pub fn sort_file(input_path: &str, output_path: &str, keys: &[(&str, SortOrder)],
+                 compare: SortCompare, chunk_rows: usize) -> Result<(), String> {
+    sort_file_with(input_path, output_path, keys, compare, chunk_rows, &CsvDialect::default())
+}
+
+// メモリに載りきらないファイルを外部マージソートする
+// chunk_rows行ずつソートして一時ファイルに書き出し、最後にマージする
+// 囲み文字内の区切り文字や改行を含む項目も1つの項目として扱う
+pub fn sort_file_with(input_path: &str, output_path: &str, keys: &[(&str, SortOrder)],
+                      compare: SortCompare, chunk_rows: usize, dialect: &CsvDialect) -> Result<(), String> {
+    dialect.validate()?;
+    if chunk_rows == 0 {
+        return Err(String::from("chunk_rowsには1以上を指定してください。"));
+    }
+
+    let file = match File::open(input_path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("openに失敗しました。[{}]", e)),
+    };
+    let mut records = RecordReader::new(BufReader::new(file), dialect);
+
+    // ヘッダーからソートキーの列位置を求める
+    let header = match records.next_record() {
+        Some(record) => {
+            let record = record?;
+            let fields = split_record(&record.text, dialect).map_err(|e| e.at(&record, dialect))?;
+            fields.into_iter().map(|field| field.into_owned()).collect::<Vec<String>>()
+        }
+        None => return Err(String::from("ヘッダーが存在しません。")),
+    };
+    let mut key_indexes = Vec::with_capacity(keys.len());
+    for (header_name, _) in keys {
+        match header.iter().position(|name| name == header_name) {
+            Some(index) => key_indexes.push(index),
+            None => return Err(format!("存在しないヘッダー名です。[{}]", header_name)),
+        }
+    }
+
+    // チャンクごとにソートして一時ファイルへ書き出す
+    let mut chunks = TempChunks::new();
+    loop {
+        let mut keyed = Vec::with_capacity(chunk_rows);
+        while keyed.len() < chunk_rows {
+            match next_keyed_record(&mut records, &key_indexes, compare, dialect)? {
+                Some(keyed_record) => keyed.push(keyed_record),
+                None => break,
+            }
+        }
+        if keyed.is_empty() {
+            break;
+        }
+        keyed.sort_by(|a, b| compare_sort_keys(&a.0, &b.0, keys, compare));
+
+        let path = chunks.next_path();
+        let file = match File::create(&path) {
+            Ok(file) => file,
+            Err(e) => return Err(format!("createに失敗しました。[{}]", e)),
+        };
+        let mut writer = BufWriter::new(file);
+        for (_, values) in &keyed {
+            write_record(&mut writer, values, dialect)?;
+        }
+        if let Err(e) = writer.flush() {
+            return Err(format!("flushに失敗しました。[{}]", e));
+        }
+    }
+
+    // 各チャンクの先頭行を比較しながらマージする
+    let mut heads: Vec<Option<KeyedRecord>> = Vec::new();
+    let mut readers: Vec<RecordReader<BufReader<File>>> = Vec::new();
+    for path in &chunks.paths {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) => return Err(format!("openに失敗しました。[{}]", e)),
+        };
+        let mut reader = RecordReader::new(BufReader::new(file), dialect)
+            .starting_at(RecordPosition {record: 1, ..Default::default()});
+        heads.push(next_keyed_record(&mut reader, &key_indexes, compare, dialect)?);
+        readers.push(reader);
+    }
+
+    let file = match File::create(output_path) {
+        Ok(file) => file,
+        Err(e) => return Err(format!("createに失敗しました。[{}]", e)),
+    };
+    let mut writer = BufWriter::new(file);
+    write_record(&mut writer, &header, dialect)?;
+    loop {
+        // 同じキーの場合は先のチャンクを優先し、安定性を保つ
+        let mut min: Option<usize> = None;
+        for (index, head) in heads.iter().enumerate() {
+            if let Some((sort_keys, _)) = head {
+                let is_less = match min {
+                    Some(min_index) => {
+                        let (min_keys, _) = heads[min_index].as_ref().unwrap();
+                        compare_sort_keys(sort_keys, min_keys, keys, compare) == Ordering::Less
+                    }
+                    None => true,
+                };
+                if is_less {
+                    min = Some(index);
+                }
+            }
+        }
+        let index = match min {
+            Some(index) => index,
+            None => break,
+        };
+
+        let (_, values) = heads[index].take().unwrap();
+        write_record(&mut writer, &values, dialect)?;
+        heads[index] = next_keyed_record(&mut readers[index], &key_indexes, compare, dialect)?;
+    }
+    if let Err(e) = writer.flush() {
+        return Err(format!("flushに失敗しました。[{}]", e));
+    }
+
+    Ok(())
+}
+
+// 一時ファイルを管理し、終了時(エラー時も含む)に削除する
+struct TempChunks {
+    prefix: String,
+    paths: Vec<String>,
+}
+impl TempChunks {
+    fn new() -> Self {
+        let nanos = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(value) => value.as_nanos(),
+            Err(_) => 0,
+        };
+        let prefix = env::temp_dir()
+            .join(format!("csv-io-sort-{}-{}", process::id(), nanos))
+            .to_string_lossy()
+            .to_string();
+        Self {prefix, paths: Vec::new()}
+    }
+
+    fn next_path(&mut self) -> String {
+        let path = format!("{}-{}.tmp", self.prefix, self.paths.len());
+        self.paths.push(path.clone());
+        path
+    }
+}
+impl Drop for TempChunks {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// 次のレコードを項目に分割し、ソートキーを作成する
+fn next_keyed_record<R: BufRead>(records: &mut RecordReader<R>, key_indexes: &[usize], compare: SortCompare,
+                                 dialect: &CsvDialect) -> Result<Option<KeyedRecord>, String> {
+    let record = match records.next_record() {
+        Some(record) => record?,
+        None => return Ok(None),
+    };
+    let values = split_record(&record.text, dialect).map_err(|e| e.at(&record, dialect))?;
+    let mut sort_keys = Vec::with_capacity(key_indexes.len());
+    for index in key_indexes {
+        let value = match values.get(*index) {
+            Some(value) => value,
+            None => return Err(record.error("列が不足しています。", Some(values.len()), dialect).into()),
+        };
+        sort_keys.push(make_sort_key(value, compare)?);
+    }
+    Ok(Some((sort_keys, values.into_iter().map(|value| value.into_owned()).collect())))
+}
+
+fn write_record<W: Write>(writer: &mut W, values: &[String], dialect: &CsvDialect) -> Result<(), String> {
+    let text = format_record(values.iter().map(|value| value.as_str()), dialect);
+    match writeln!(writer, "{}", text) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("writeに失敗しました。[{}]", e)),
+    }
+}
+
+fn make_sort_key(value: &str, compare: SortCompare) -> Result<SortKey, String> {
+    match compare {
+        SortCompare::Lexicographic | SortCompare::Natural => Ok(SortKey::Text(value.to_string())),
+        SortCompare::CaseInsensitive => Ok(SortKey::Text(fold_case(value))),
+        SortCompare::Numeric => match value.trim().parse::<f64>() {
+            Ok(number) => Ok(SortKey::Number(number)),
+            Err(_) => Err(format!("数値に変換できません。[{}]", value)),
+        },
+    }
+}
+
+fn compare_sort_keys(a: &[SortKey], b: &[SortKey], keys: &[(&str, SortOrder)], compare: SortCompare)
+    -> Ordering {
+    for ((key_a, key_b), (_, order)) in a.iter().zip(b).zip(keys) {
+        let ordering = match (key_a, key_b) {
+            (SortKey::Number(a), SortKey::Number(b)) => a.total_cmp(b),
+            (SortKey::Text(a), SortKey::Text(b)) => {
+                if compare == SortCompare::Natural {
+                    compare_natural(a, b)
+                } else {
+                    a.cmp(b)
+                }
+            }
+            // 同じ比較方法でキーを作るため、ここには来ない
+            _ => Ordering::Equal,
+        };
+        let ordering = match order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+// 全角英数字を半角にし、小文字に揃える
+fn fold_case(value: &str) -> String {
+    value.chars()
+        .map(|c| match c {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            _ => c,
+        })
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+// 連続する数字は数値として、それ以外は文字として比較する
+fn compare_natural(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+    loop {
+        match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_char), Some(b_char)) => {
+                if a_char.is_ascii_digit() && b_char.is_ascii_digit() {
+                    let a_number = take_digits(&mut a_chars);
+                    let b_number = take_digits(&mut b_chars);
+                    // 先頭の0を除いた桁数、数字列の順に比較する
+                    let a_trimmed = a_number.trim_start_matches('0');
+                    let b_trimmed = b_number.trim_start_matches('0');
+                    let ordering = a_trimmed.len().cmp(&b_trimmed.len())
+                        .then_with(|| a_trimmed.cmp(b_trimmed))
+                        .then_with(|| a_number.len().cmp(&b_number.len()));
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                } else {
+                    let ordering = a_char.cmp(b_char);
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                    a_chars.next();
+                    b_chars.next();
+                }
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek() {
+        if !c.is_ascii_digit() {
+            break;
+        }
+        digits.push(*c);
+        chars.next();
+    }
+    digits
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{read, CsvData, CsvHeader};
+
+    #[test]
+    fn sort_by_columns_multi() {
+        let mut file = make_file();
+
+        file.sort_by_columns(&[("date", SortOrder::Desc), ("id", SortOrder::Asc)]).unwrap();
+
+        assert_eq!(vec!["3", "1", "5", "2", "4"], column(&file, "id"));
+    }
+
+    #[test]
+    fn sort_by_columns_stable() {
+        let mut file = make_file();
+
+        file.sort_by_columns(&[("date", SortOrder::Asc)]).unwrap();
+
+        assert_eq!(vec!["2", "4", "1", "5", "3"], column(&file, "id"));
+    }
+
+    #[test]
+    fn sort_by_columns_numeric() {
+        let mut file = make_file();
+
+        file.sort_by_columns_with(&[("amount", SortOrder::Asc)], SortCompare::Numeric).unwrap();
+
+        assert_eq!(vec!["9", "10", "100", "100", "1000"], column(&file, "amount"));
+        assert_eq!(vec!["3", "1", "2", "5", "4"], column(&file, "id"));
+    }
+
+    #[test]
+    fn sort_by_columns_numeric_error() {
+        let mut file = make_file();
+
+        match file.sort_by_columns_with(&[("name", SortOrder::Asc)], SortCompare::Numeric) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("ソートに失敗しました。行=[0], [数値に変換できません。[file10]]", e),
+        }
+        // エラーの場合は行を変更しない
+        assert_eq!(vec!["1", "2", "3", "4", "5"], column(&file, "id"));
+    }
+
+    #[test]
+    fn sort_by_columns_natural() {
+        let mut file = make_file();
+
+        file.sort_by_columns_with(&[("name", SortOrder::Asc)], SortCompare::Natural).unwrap();
+
+        assert_eq!(vec!["File1", "file2", "file003", "file10", "Ｆｉｌｅ３"],
+                   column(&file, "name"));
+    }
+
+    #[test]
+    fn sort_by_columns_case_insensitive() {
+        let mut file = make_file();
+
+        file.sort_by_columns_with(&[("name", SortOrder::Asc)], SortCompare::CaseInsensitive)
+            .unwrap();
+
+        assert_eq!(vec!["file003", "File1", "file10", "file2", "Ｆｉｌｅ３"],
+                   column(&file, "name"));
+    }
+
+    #[test]
+    fn sort_by_columns_error_header_name() {
+        let mut file = make_file();
+
+        match file.sort_by_columns(&[("ヘッダー4", SortOrder::Asc)]) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("ソートに失敗しました。[存在しないヘッダー名です。[ヘッダー4]]", e),
+        }
+        assert_eq!(5, file.get_body().len());
+        assert_eq!(vec!["1", "2", "3", "4", "5"], column(&file, "id"));
+    }
+
+    #[test]
+    fn sort_file_external() {
+        let input = env::temp_dir().join(format!("csv-io-sort-test-in-{}.csv", process::id()));
+        let output = env::temp_dir().join(format!("csv-io-sort-test-out-{}.csv", process::id()));
+        let input = input.to_str().unwrap();
+        let output = output.to_str().unwrap();
+        fs::write(input, "id,date,amount,name\n\
+                          1,2024-01-02,10,file10\n\
+                          2,2024-01-01,100,file2\n\
+                          3,2024-01-03,9,File1\n\
+                          4,2024-01-01,1000,file003\n\
+                          5,2024-01-02,100,Ｆｉｌｅ３\n").unwrap();
+
+        let keys = [("date", SortOrder::Desc), ("id", SortOrder::Asc)];
+        sort_file(input, output, &keys, SortCompare::Lexicographic, 2).unwrap();
+
+        let csv = read(output).unwrap();
+        fs::remove_file(input).unwrap();
+        fs::remove_file(output).unwrap();
+
+        assert_eq!(vec!["3", "1", "5", "2", "4"], column(&csv, "id"));
+    }
+
+    #[test]
+    fn sort_file_quoted() {
+        let input = env::temp_dir().join(format!("csv-io-sort-test-quoted-in-{}.tsv", process::id()));
+        let output = env::temp_dir().join(format!("csv-io-sort-test-quoted-out-{}.tsv", process::id()));
+        let input = input.to_str().unwrap();
+        let output = output.to_str().unwrap();
+        // 囲み文字内の区切り文字と改行を含む
+        fs::write(input, "名前\t金額\n'船長\tうさぎ'\t300\n'いるか\nねこ'\t100\nやかまし\t'2\t00'\n").unwrap();
+
+        let dialect = CsvDialect::new('\t', '\'');
+        sort_file_with(input, output, &[("金額", SortOrder::Asc)], SortCompare::Lexicographic, 1, &dialect).unwrap();
+
+        let text = fs::read_to_string(output).unwrap();
+        fs::remove_file(input).unwrap();
+        fs::remove_file(output).unwrap();
+
+        assert_eq!("名前\t金額\n'いるか\nねこ'\t100\nやかまし\t'2\t00'\n'船長\tうさぎ'\t300\n", text);
+    }
+
+    fn column(file: &CsvFile, header_name: &str) -> Vec<String> {
+        (0..file.get_body().len())
+            .map(|index| file.get_value(header_name, index).unwrap())
+            .collect()
+    }
+
+    fn make_file() -> CsvFile {
+        let mut header = CsvHeader::new();
+        for name in ["id", "date", "amount", "name"] {
+            header.append(name);
+        }
+
+        let mut body = CsvBody::new();
+        let rows = [
+            ["1", "2024-01-02", "10", "file10"],
+            ["2", "2024-01-01", "100", "file2"],
+            ["3", "2024-01-03", "9", "File1"],
+            ["4", "2024-01-01", "1000", "file003"],
+            ["5", "2024-01-02", "100", "Ｆｉｌｅ３"],
+        ];
+        for values in rows {
+            let mut row = CsvRow::new();
+            for (name, value) in ["id", "date", "amount", "name"].iter().zip(values) {
+                row.append(CsvData::new(name, value));
+            }
+            body.append(row);
+        }
+
+        CsvFile::new(header, body)
+    }
+}
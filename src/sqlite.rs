@@ -0,0 +1,182 @@
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params_from_iter, Connection};
+
+use crate::schema::parse_bool;
+use crate::{ColumnType, CsvFile, CsvSchema};
+
+impl CsvFile {
+    // テーブルを作成し、1つのトランザクションで全行を追加する
+    // 文字列以外の列の空の値はNULLとし、真偽値は0/1で保存する
+    // 途中でエラーになった場合はロールバックする
+    pub fn to_sqlite(&self, conn: &mut Connection, table: &str, schema: &CsvSchema) -> Result<(), String> {
+        let columns: Vec<String> = self.csv_header.name.iter()
+            .map(|name| format!("{} {}", quote_identifier(name), sql_type(schema.get(name))))
+            .collect();
+        let create = format!("CREATE TABLE {} ({})", quote_identifier(table), columns.join(", "));
+        let placeholders: Vec<String> = (1..=self.csv_header.name.len()).map(|index| format!("?{}", index)).collect();
+        let insert = format!("INSERT INTO {} VALUES ({})", quote_identifier(table), placeholders.join(", "));
+        let column_types: Vec<ColumnType> = self.csv_header.name.iter().map(|name| schema.get(name)).collect();
+
+        let transaction = match conn.transaction() {
+            Ok(transaction) => transaction,
+            Err(e) => return Err(format!("SQLiteへの書き込みに失敗しました。[{}]", e)),
+        };
+        if let Err(e) = transaction.execute(&create, []) {
+            return Err(format!("SQLiteへの書き込みに失敗しました。[{}]", e));
+        }
+        {
+            let mut statement = match transaction.prepare(&insert) {
+                Ok(statement) => statement,
+                Err(e) => return Err(format!("SQLiteへの書き込みに失敗しました。[{}]", e)),
+            };
+            for (row_index, row) in self.csv_body.rows.iter().enumerate() {
+                let mut values = Vec::with_capacity(column_types.len());
+                for (index, column_type) in column_types.iter().enumerate() {
+                    let value = row.data.get(index).map(|csv_data| csv_data.value.as_str()).unwrap_or("");
+                    match sql_value(value, *column_type) {
+                        Some(value) => values.push(value),
+                        None => return Err(format!("SQLiteへの書き込みに失敗しました。[{}に変換できません。行=[{}], 列=[{}], 値=[{}]]",
+                                                   column_type.name(), row_index, self.csv_header.name[index], value)),
+                    }
+                }
+                if let Err(e) = statement.execute(params_from_iter(values)) {
+                    return Err(format!("SQLiteへの書き込みに失敗しました。行=[{}], [{}]", row_index, e));
+                }
+            }
+        }
+        if let Err(e) = transaction.commit() {
+            return Err(format!("SQLiteへの書き込みに失敗しました。[{}]", e));
+        }
+        Ok(())
+    }
+
+    // 問い合わせ結果の列名をヘッダーとする
+    // NULLは空の値とする
+    pub fn from_sqlite_query(conn: &Connection, sql: &str) -> Result<CsvFile, String> {
+        let mut statement = match conn.prepare(sql) {
+            Ok(statement) => statement,
+            Err(e) => return Err(format!("SQLiteからの読み込みに失敗しました。[{}]", e)),
+        };
+        let header_names: Vec<String> = statement.column_names().iter().map(|name| name.to_string()).collect();
+
+        let mut result = match statement.query([]) {
+            Ok(result) => result,
+            Err(e) => return Err(format!("SQLiteからの読み込みに失敗しました。[{}]", e)),
+        };
+        let mut rows = Vec::new();
+        loop {
+            let row = match result.next() {
+                Ok(Some(row)) => row,
+                Ok(None) => break,
+                Err(e) => return Err(format!("SQLiteからの読み込みに失敗しました。[{}]", e)),
+            };
+            let mut values = Vec::with_capacity(header_names.len());
+            for (index, name) in header_names.iter().enumerate() {
+                let value = match row.get_ref(index) {
+                    Ok(ValueRef::Null) => String::new(),
+                    Ok(ValueRef::Integer(value)) => value.to_string(),
+                    Ok(ValueRef::Real(value)) => value.to_string(),
+                    Ok(ValueRef::Text(value)) => String::from_utf8_lossy(value).into_owned(),
+                    Ok(ValueRef::Blob(_)) => return Err(format!("SQLiteからの読み込みに失敗しました。[BLOBは変換できません。行=[{}], 列=[{}]]", rows.len(), name)),
+                    Err(e) => return Err(format!("SQLiteからの読み込みに失敗しました。[{}]", e)),
+                };
+                values.push(value);
+            }
+            rows.push(values);
+        }
+
+        Ok(CsvFile::from_rows(header_names, rows))
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn sql_type(column_type: ColumnType) -> &'static str {
+    match column_type {
+        ColumnType::String => "TEXT",
+        ColumnType::Integer | ColumnType::Boolean => "INTEGER",
+        ColumnType::Float => "REAL",
+    }
+}
+
+fn sql_value(value: &str, column_type: ColumnType) -> Option<Value> {
+    if value.is_empty() && column_type != ColumnType::String {
+        return Some(Value::Null);
+    }
+    match column_type {
+        ColumnType::String => Some(Value::Text(value.to_string())),
+        ColumnType::Integer => value.parse().ok().map(Value::Integer),
+        ColumnType::Float => value.parse().ok().map(Value::Real),
+        ColumnType::Boolean => parse_bool(value).map(|value| Value::Integer(value as i64)),
+    }
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sqlite_round_trip() {
+        let csv = make_file();
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        csv.to_sqlite(&mut conn, "売上", &CsvSchema::infer(&csv)).unwrap();
+        let read = CsvFile::from_sqlite_query(&conn,
+            r#"SELECT "名前", "金額" * 2 AS "倍", "有効", typeof("id") AS "型" FROM "売上" ORDER BY "id" DESC"#).unwrap();
+
+        assert_eq!(vec!["名前", "倍", "有効", "型"], read.get_header().name);
+        assert_eq!(vec!["", "", "", "integer"], read.get_body().get_row(0).unwrap().values());
+        assert_eq!(vec!["船長うさぎ", "-5", "0", "integer"], read.get_body().get_row(1).unwrap().values());
+        assert_eq!(vec!["いるかねこ", "200", "1", "integer"], read.get_body().get_row(2).unwrap().values());
+    }
+
+    #[test]
+    fn to_sqlite_rollback() {
+        let csv = make_file();
+        let mut conn = Connection::open_in_memory().unwrap();
+        let mut schema = CsvSchema::new();
+        schema.push("有効", ColumnType::Integer);
+
+        match csv.to_sqlite(&mut conn, "売上", &schema) {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("SQLiteへの書き込みに失敗しました。[整数に変換できません。行=[0], 列=[有効], 値=[true]]", e),
+        }
+        // テーブルの作成も取り消される
+        let tables = CsvFile::from_sqlite_query(&conn, "SELECT name FROM sqlite_master").unwrap();
+        assert!(tables.get_body().is_empty());
+    }
+
+    #[test]
+    fn from_sqlite_query_error() {
+        let conn = Connection::open_in_memory().unwrap();
+
+        match CsvFile::from_sqlite_query(&conn, "SELECT x'00' AS b") {
+            Ok(_) => panic!("エラーが発生しませんでした。"),
+            Err(e) => assert_eq!("SQLiteからの読み込みに失敗しました。[BLOBは変換できません。行=[0], 列=[b]]", e),
+        }
+    }
+
+    fn make_file() -> CsvFile {
+        CsvFile::from_rows(
+            vec![String::from("id"), String::from("名前"), String::from("金額"), String::from("有効")],
+            vec![
+                vec![String::from("1"), String::from("いるかねこ"), String::from("100"), String::from("true")],
+                vec![String::from("2"), String::from("船長うさぎ"), String::from("-2.5"), String::from("false")],
+                vec![String::from("3"), String::new(), String::new(), String::new()],
+            ],
+        )
+    }
+}
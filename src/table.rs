@@ -0,0 +1,255 @@
+use std::fmt;
+
+use crate::dialect::format_record;
+use crate::width::{char_width, str_width};
+use crate::{CsvDialect, CsvFile};
+
+// 罫線の種類
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TableStyle {
+    // ┌─┬─┐のような罫線
+    Box,
+    // +-+-+のような罫線
+    Ascii,
+}
+
+pub struct TableOptions {
+    pub style: TableStyle,
+    // 列の最大表示幅(超える値は…で省略する)
+    pub max_width: Option<usize>,
+    // すべての値が数値の列を右寄せにする
+    pub align_numbers: bool,
+}
+impl Default for TableOptions {
+    fn default() -> Self {
+        Self {style: TableStyle::Box, max_width: None, align_numbers: true}
+    }
+}
+
+struct Lines {
+    top: [&'static str; 3],
+    middle: [&'static str; 3],
+    bottom: [&'static str; 3],
+    horizontal: &'static str,
+    vertical: &'static str,
+}
+
+const BOX_LINES: Lines = Lines {
+    top: ["┌", "┬", "┐"],
+    middle: ["├", "┼", "┤"],
+    bottom: ["└", "┴", "┘"],
+    horizontal: "─",
+    vertical: "│",
+};
+
+const ASCII_LINES: Lines = Lines {
+    top: ["+", "+", "+"],
+    middle: ["+", "+", "+"],
+    bottom: ["+", "+", "+"],
+    horizontal: "-",
+    vertical: "|",
+};
+
+impl CsvFile {
+    // 端末表示用の表を作成する
+    // 幅は全角文字を2として計算する
+    pub fn to_table(&self, options: &TableOptions) -> String {
+        let lines = match options.style {
+            TableStyle::Box => &BOX_LINES,
+            TableStyle::Ascii => &ASCII_LINES,
+        };
+
+        let header: Vec<String> = self.csv_header.name.iter()
+            .map(|name| fit(name, options.max_width))
+            .collect();
+        let rows: Vec<Vec<String>> = self.csv_body.rows.iter()
+            .map(|row| row.data.iter().map(|csv_data| fit(&csv_data.value, options.max_width)).collect())
+            .collect();
+
+        let mut widths: Vec<usize> = header.iter().map(|name| str_width(name)).collect();
+        let mut numeric = vec![options.align_numbers; header.len()];
+        let mut filled = vec![false; header.len()];
+        for row in &rows {
+            for (index, value) in row.iter().enumerate() {
+                if index >= widths.len() {
+                    widths.push(0);
+                    numeric.push(options.align_numbers);
+                    filled.push(false);
+                }
+                widths[index] = widths[index].max(str_width(value));
+                if !value.is_empty() {
+                    filled[index] = true;
+                    if value.trim().parse::<f64>().is_err() {
+                        numeric[index] = false;
+                    }
+                }
+            }
+        }
+        // 値がない列は右寄せにしない
+        for (numeric, filled) in numeric.iter_mut().zip(&filled) {
+            *numeric = *numeric && *filled;
+        }
+
+        let mut table = String::new();
+        push_border(&mut table, &lines.top, lines.horizontal, &widths);
+        push_row(&mut table, lines.vertical, &header, &widths, &vec![false; widths.len()]);
+        push_border(&mut table, &lines.middle, lines.horizontal, &widths);
+        for row in &rows {
+            push_row(&mut table, lines.vertical, row, &widths, &numeric);
+        }
+        push_border(&mut table, &lines.bottom, lines.horizontal, &widths);
+        table
+    }
+}
+
+// {}はCSV形式、{:#}は罫線付きの表で表示する
+impl fmt::Display for CsvFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            return write!(f, "{}", self.to_table(&TableOptions::default()));
+        }
+        let dialect = CsvDialect::default();
+        writeln!(f, "{}", format_record(self.csv_header.name.iter().map(|name| name.as_str()), &dialect))?;
+        for row in &self.csv_body.rows {
+            writeln!(f, "{}", format_record(row.data.iter().map(|csv_data| csv_data.value.as_str()), &dialect))?;
+        }
+        Ok(())
+    }
+}
+
+// 改行などを空白にし、最大幅を超える場合は…で省略する
+fn fit(value: &str, max_width: Option<usize>) -> String {
+    let value: String = value.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    let max_width = match max_width {
+        Some(max_width) if str_width(&value) > max_width => max_width,
+        _ => return value,
+    };
+
+    let mut fitted = String::new();
+    let mut width = 0;
+    for c in value.chars() {
+        let c_width = char_width(c);
+        if width + c_width + 1 > max_width {
+            break;
+        }
+        fitted.push(c);
+        width += c_width;
+    }
+    if max_width > 0 {
+        fitted.push('…');
+    }
+    fitted
+}
+
+fn push_border(table: &mut String, corners: &[&str; 3], horizontal: &str, widths: &[usize]) {
+    table.push_str(corners[0]);
+    for (index, width) in widths.iter().enumerate() {
+        if index > 0 {
+            table.push_str(corners[1]);
+        }
+        table.push_str(&horizontal.repeat(width + 2));
+    }
+    table.push_str(corners[2]);
+    table.push('\n');
+}
+
+fn push_row(table: &mut String, vertical: &str, values: &[String], widths: &[usize], right: &[bool]) {
+    table.push_str(vertical);
+    for (index, width) in widths.iter().enumerate() {
+        let value = values.get(index).map(|value| value.as_str()).unwrap_or("");
+        let padding = " ".repeat(width - str_width(value));
+        table.push(' ');
+        if right[index] {
+            table.push_str(&padding);
+            table.push_str(value);
+        } else {
+            table.push_str(value);
+            table.push_str(&padding);
+        }
+        table.push(' ');
+        table.push_str(vertical);
+    }
+    table.push('\n');
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read;
+
+    #[test]
+    fn to_table_box() {
+        let csv = read("test/test.csv").unwrap();
+
+        assert_eq!("\
+┌────────────┬────────────┬────────────┐
+│ ヘッダー1  │ ヘッダー2  │ ヘッダー3  │
+├────────────┼────────────┼────────────┤
+│ いるかねこ │ 船長うさぎ │ やかまし   │
+│ しおしゃち │ いぬねこ   │ 船長メイド │
+│ いぬてんし │ おけぶろ   │ すもっく   │
+└────────────┴────────────┴────────────┘
+", csv.to_table(&TableOptions::default()));
+    }
+
+    #[test]
+    fn to_table_ascii_numbers() {
+        let csv = make_file();
+        let options = TableOptions {style: TableStyle::Ascii, ..TableOptions::default()};
+
+        assert_eq!("\
++----+------------+------+
+| id | 名前       | 金額 |
++----+------------+------+
+|  9 | いるかねこ |  100 |
+| 10 | 船長うさぎ | -2.5 |
++----+------------+------+
+", csv.to_table(&options));
+    }
+
+    #[test]
+    fn to_table_max_width() {
+        let csv = make_file();
+        let options = TableOptions {style: TableStyle::Ascii, max_width: Some(5), align_numbers: false};
+
+        assert_eq!("\
++----+-------+------+
+| id | 名前  | 金額 |
++----+-------+------+
+| 9  | いる… | 100  |
+| 10 | 船長… | -2.5 |
++----+-------+------+
+", csv.to_table(&options));
+    }
+
+    #[test]
+    fn display() {
+        let csv = make_file();
+
+        assert_eq!("id,名前,金額\n9,いるかねこ,100\n10,船長うさぎ,-2.5\n", format!("{}", csv));
+        assert_eq!(csv.to_table(&TableOptions::default()), format!("{:#}", csv));
+    }
+
+    fn make_file() -> CsvFile {
+        CsvFile::from_rows(
+            vec![String::from("id"), String::from("名前"), String::from("金額")],
+            vec![
+                vec![String::from("9"), String::from("いるかねこ"), String::from("100")],
+                vec![String::from("10"), String::from("船長うさぎ"), String::from("-2.5")],
+            ],
+        )
+    }
+}
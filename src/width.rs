@@ -0,0 +1,80 @@
+// 端末での表示幅(全角文字は2、結合文字は0)を求める
+
+pub(crate) fn char_width(c: char) -> usize {
+    let code = c as u32;
+    if is_zero_width(code) {
+        return 0;
+    }
+    if is_wide(code) {
+        return 2;
+    }
+    1
+}
+
+pub(crate) fn str_width(value: &str) -> usize {
+    value.chars().map(char_width).sum()
+}
+
+fn is_zero_width(code: u32) -> bool {
+    matches!(code,
+        0x0000..=0x001F | 0x007F..=0x009F
+        | 0x0300..=0x036F
+        | 0x200B..=0x200F
+        | 0x3099..=0x309A
+        | 0xFE00..=0xFE0F
+        | 0xFEFF
+        | 0xE0100..=0xE01EF)
+}
+
+// East Asian WidthのW(全角)とF(Fullwidth)の主な範囲
+fn is_wide(code: u32) -> bool {
+    matches!(code,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD)
+}
+
+
+
+
+
+////////////////////////////////////////////////////////////////////////////////
+//
+//
+//   Test
+//
+//
+////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn str_width_ascii() {
+        assert_eq!(5, str_width("abc12"));
+    }
+
+    #[test]
+    fn str_width_full_width() {
+        assert_eq!(10, str_width("いるかねこ"));
+        assert_eq!(8, str_width("船長うさぎ"[..12].as_ref()));
+        assert_eq!(7, str_width("ＡＢ1ｶﾅ"));
+    }
+
+    #[test]
+    fn str_width_combining() {
+        // か + 結合用濁点
+        assert_eq!(2, str_width("か\u{3099}"));
+    }
+}